@@ -0,0 +1,5 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "bundled-zstd")]
+    ndb_core::compress::compress_bundled("as.bin").expect("Failed to compress bundled BIN file");
+}
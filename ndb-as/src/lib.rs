@@ -1,10 +1,22 @@
-use anyhow::Result;
+use ndb_core::bin::DatasetKind;
+use ndb_core::{NdbDataset, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::io::Read;
+use std::path::Path;
+
+pub use ndb_core::Error;
 
 pub const CSV_NAME: &str = "as.csv";
 pub const BIN_NAME: &str = "as.bin";
+/// Version of the entry schema written to BIN files
+pub const SCHEMA_VERSION: u16 = 1;
+
+#[cfg(all(feature = "bundled", not(feature = "bundled-zstd")))]
+static BUNDLED_BIN: &[u8] = include_bytes!("../data/as.bin");
+
+#[cfg(feature = "bundled-zstd")]
+static BUNDLED_BIN: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/as.bin.zst"));
 
 /// Represents a single Autonomous System (AS) entry
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -20,14 +32,8 @@ pub struct AsDb {
 
 impl AsDb {
     /// Load database from a CSV reader
-    pub fn from_csv<R: Read>(reader: R) -> Result<Self, csv::Error> {
-        let mut rdr = csv::Reader::from_reader(reader);
-        let mut map = HashMap::new();
-        for result in rdr.deserialize::<AsEntry>() {
-            let entry = result?;
-            map.insert(entry.asn, entry.name);
-        }
-        Ok(Self { inner: map })
+    pub fn from_csv<R: Read>(reader: R) -> Result<Self> {
+        <Self as NdbDataset>::from_csv(reader)
     }
 
     /// Create a new AS database from a vector of entries
@@ -40,17 +46,37 @@ impl AsDb {
     }
 
     /// Create a new AS database from a binary slice
-    fn from_slice(slice: &[u8]) -> Result<Self> {
-        let (entries, _): (Vec<AsEntry>, _) =
-            bincode::serde::decode_from_slice(slice, bincode::config::standard())?;
-        Ok(Self::from_entries(entries))
+    pub fn from_slice(slice: &[u8]) -> Result<Self> {
+        <Self as NdbDataset>::from_slice(slice)
+    }
+
+    /// Load database from a reader of a BIN file
+    pub fn from_bin_reader<R: Read>(reader: R) -> Result<Self> {
+        <Self as NdbDataset>::from_bin_reader(reader)
+    }
+
+    /// Load database from a BIN file (e.g. one written by `ndb-cli update`)
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        <Self as NdbDataset>::from_path(path)
     }
 
     /// Load embedded (bundled) database
     #[cfg(feature = "bundled")]
     pub fn bundled() -> Self {
-        static BIN_DATA: &[u8] = include_bytes!("../data/as.bin");
-        Self::from_slice(BIN_DATA).expect("Failed to load bundled AS database")
+        Self::try_bundled().expect("Failed to load bundled AS database")
+    }
+
+    /// Load embedded (bundled) database, returning an error instead of panicking
+    #[cfg(feature = "bundled")]
+    pub fn try_bundled() -> Result<Self> {
+        Self::from_slice(BUNDLED_BIN)
+    }
+
+    /// Get the process-wide shared bundled database, loaded once on first use
+    #[cfg(feature = "bundled")]
+    pub fn bundled_static() -> &'static Self {
+        static BUNDLED: std::sync::OnceLock<AsDb> = std::sync::OnceLock::new();
+        BUNDLED.get_or_init(Self::bundled)
     }
 
     pub fn get_name(&self, asn: u32) -> Option<&str> {
@@ -61,20 +87,47 @@ impl AsDb {
         self.inner.iter()
     }
 
+    /// Get all entries as a vector, sorted by ASN
     pub fn entries(&self) -> Vec<AsEntry> {
-        self.inner
+        let mut entries: Vec<AsEntry> = self
+            .inner
             .iter()
             .map(|(&asn, name)| AsEntry {
                 asn,
                 name: name.clone(),
             })
-            .collect()
+            .collect();
+        entries.sort_by_key(|entry| entry.asn);
+        entries
+    }
+}
+
+impl NdbDataset for AsDb {
+    type Entry = AsEntry;
+
+    const KIND: DatasetKind = DatasetKind::As;
+    const CSV_NAME: &'static str = CSV_NAME;
+    const BIN_NAME: &'static str = BIN_NAME;
+    const SCHEMA_VERSION: u16 = SCHEMA_VERSION;
+
+    fn from_entries(entries: Vec<AsEntry>) -> Result<Self> {
+        Ok(AsDb::from_entries(entries))
+    }
+
+    fn entries(&self) -> Vec<AsEntry> {
+        AsDb::entries(self)
+    }
+
+    #[cfg(feature = "bundled")]
+    fn bundled_bin() -> Option<&'static [u8]> {
+        Some(BUNDLED_BIN)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ndb_core::bin;
     use std::io::Cursor;
 
     const CSV_DATA: &str = "asn,name\n13335,Cloudflare\n15169,Google LLC";
@@ -89,6 +142,20 @@ mod tests {
         assert_eq!(db.get_name(99999), None);
     }
 
+    #[test]
+    fn test_from_csv_reports_position() {
+        let csv = "asn,name\n13335,Cloudflare\nAS15169,Google LLC";
+        let err = AsDb::from_csv(csv.as_bytes()).err().unwrap();
+        assert!(matches!(
+            err,
+            Error::Csv {
+                line: Some(3),
+                field: Some(0),
+                ..
+            }
+        ));
+    }
+
     #[test]
     fn test_from_entries_and_lookup() {
         let entries = vec![
@@ -119,4 +186,37 @@ mod tests {
 
         assert_eq!(decoded.get_name(64500), Some("TestNet"));
     }
+
+    #[test]
+    fn test_from_slice_checks_header() {
+        let entries = vec![AsEntry {
+            asn: 64500,
+            name: "TestNet".into(),
+        }];
+
+        let header = bin::BinHeader::new(DatasetKind::As, SCHEMA_VERSION, "test", entries.len());
+        let buf = bin::encode_entries(&header, &entries).unwrap();
+        let decoded = AsDb::from_slice(&buf).unwrap();
+        assert_eq!(decoded.get_name(64500), Some("TestNet"));
+
+        let header = bin::BinHeader::new(DatasetKind::Ipv4Asn, 1, "test", entries.len());
+        let buf = bin::encode_entries(&header, &entries).unwrap();
+        assert!(matches!(
+            AsDb::from_slice(&buf),
+            Err(ndb_core::Error::DatasetMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_bundled_static_is_shared() {
+        let handles: Vec<_> = (0..4)
+            .map(|_| std::thread::spawn(|| AsDb::bundled_static() as *const AsDb as usize))
+            .collect();
+        let addrs: Vec<usize> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert!(addrs.iter().all(|&addr| addr == addrs[0]));
+        assert_eq!(
+            AsDb::bundled_static().get_name(13335),
+            AsDb::try_bundled().unwrap().get_name(13335)
+        );
+    }
 }
@@ -0,0 +1,58 @@
+//! Global allocator that keeps track of the live heap size,
+//! used by `stats` to measure the memory footprint of each database.
+//!
+//! Counting costs every allocation of every command, so it is only installed
+//! with the `heap-stats` feature.
+
+#[cfg(feature = "heap-stats")]
+use std::alloc::{GlobalAlloc, Layout, System};
+#[cfg(feature = "heap-stats")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "heap-stats")]
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+/// `System` allocator counting the bytes currently allocated
+#[cfg(feature = "heap-stats")]
+pub struct CountingAlloc;
+
+#[cfg(feature = "heap-stats")]
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            ALLOCATED.fetch_add(new_size, Ordering::Relaxed);
+            ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        }
+        new_ptr
+    }
+}
+
+/// Number of heap bytes currently allocated, if the `heap-stats` feature is enabled
+pub fn allocated() -> Option<usize> {
+    #[cfg(feature = "heap-stats")]
+    return Some(ALLOCATED.load(Ordering::Relaxed));
+    #[cfg(not(feature = "heap-stats"))]
+    None
+}
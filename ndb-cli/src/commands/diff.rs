@@ -0,0 +1,674 @@
+use crate::db::with_dataset;
+use anyhow::Result;
+use clap::ArgMatches;
+use ndb_as::{AsDb, AsEntry};
+use ndb_core::bin::{self, DatasetKind};
+use ndb_core::compress::{self, ZSTD_SUFFIX};
+use ndb_core::validate::RangeRecord;
+use ndb_core::NdbDataset;
+use ndb_country::{CountryDb, CountryEntry};
+use ndb_ipv4_asn::Ipv4AsnDb;
+use ndb_ipv4_country::Ipv4CountryDb;
+use ndb_ipv6_asn::Ipv6AsnDb;
+use ndb_ipv6_country::Ipv6CountryDb;
+use ndb_oui::{OuiDb, OuiEntry};
+use ndb_tcp_service::{TcpServiceDb, TcpServiceEntry};
+use ndb_udp_service::{UdpServiceDb, UdpServiceEntry};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Output format of the diff
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffFormat {
+    Text,
+    Json,
+}
+
+impl DiffFormat {
+    pub const NAMES: [&'static str; 2] = ["text", "json"];
+
+    pub fn from_str(s: &str) -> DiffFormat {
+        match s {
+            "json" => DiffFormat::Json,
+            _ => DiffFormat::Text,
+        }
+    }
+}
+
+pub struct DiffConfig {
+    pub old: PathBuf,
+    pub new: PathBuf,
+    /// Dataset of both files, detected from the BIN header or file name if not set
+    pub kind: Option<DatasetKind>,
+    pub format: DiffFormat,
+    pub output: Option<PathBuf>,
+    /// Maximum number of entries listed per section in text output
+    pub limit: usize,
+}
+
+impl DiffConfig {
+    pub fn from_cli_arg(matches: &ArgMatches) -> Self {
+        let old = matches
+            .get_one::<PathBuf>("old")
+            .cloned()
+            .unwrap_or_default();
+        let new = matches
+            .get_one::<PathBuf>("new")
+            .cloned()
+            .unwrap_or_default();
+        let kind = matches
+            .get_one::<String>("dataset")
+            .and_then(|name| DatasetKind::from_name(name));
+        let format = matches
+            .get_one::<String>("format")
+            .map(|s| DiffFormat::from_str(s))
+            .unwrap_or(DiffFormat::Text);
+        let output = matches.get_one::<PathBuf>("output").cloned();
+        let limit = matches.get_one::<usize>("limit").copied().unwrap_or(20);
+        DiffConfig {
+            old,
+            new,
+            kind,
+            format,
+            output,
+            limit,
+        }
+    }
+}
+
+/// Entry present in both builds with a different value
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Change<E> {
+    pub old: E,
+    pub new: E,
+}
+
+/// Added, removed and changed entries between two builds of a dataset.
+/// IP range datasets are compared address by address, so entries are split
+/// into the sub-ranges whose mapping changed.
+#[derive(Debug, Serialize)]
+pub struct EntryDiff<E> {
+    pub added: Vec<E>,
+    pub removed: Vec<E>,
+    pub changed: Vec<Change<E>>,
+    /// Number of entries (or sub-ranges) that are the same in both builds
+    pub unchanged: usize,
+    /// Number of addresses per section, for IP range datasets
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub addresses: Option<AddressCounts>,
+}
+
+impl<E> Default for EntryDiff<E> {
+    fn default() -> Self {
+        EntryDiff {
+            added: Vec::new(),
+            removed: Vec::new(),
+            changed: Vec::new(),
+            unchanged: 0,
+            addresses: None,
+        }
+    }
+}
+
+impl<E> EntryDiff<E> {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct AddressCounts {
+    pub added: u128,
+    pub removed: u128,
+    pub changed: u128,
+}
+
+#[derive(Debug, Serialize)]
+struct DiffSummary {
+    added: usize,
+    removed: usize,
+    changed: usize,
+    unchanged: usize,
+}
+
+/// Machine readable diff report
+#[derive(Debug, Serialize)]
+struct DiffReport<'a, E> {
+    dataset: &'static str,
+    old: String,
+    new: String,
+    summary: DiffSummary,
+    #[serde(flatten)]
+    diff: &'a EntryDiff<E>,
+}
+
+/// How the entries of a dataset are compared and described
+trait Diff: NdbDataset
+where
+    Self::Entry: Serialize,
+{
+    fn diff_entries(old: Vec<Self::Entry>, new: Vec<Self::Entry>) -> EntryDiff<Self::Entry>;
+
+    /// One line description of an entry for text output
+    fn describe(entry: &Self::Entry) -> String;
+
+    fn describe_change(change: &Change<Self::Entry>) -> String {
+        format!(
+            "{} -> {}",
+            Self::describe(&change.old),
+            Self::describe(&change.new)
+        )
+    }
+}
+
+macro_rules! impl_range_diff {
+    ($($db:ty),*) => {
+        $(
+            impl Diff for $db {
+                fn diff_entries(
+                    old: Vec<Self::Entry>,
+                    new: Vec<Self::Entry>,
+                ) -> EntryDiff<Self::Entry> {
+                    range_diff(old, new)
+                }
+
+                fn describe(entry: &Self::Entry) -> String {
+                    format!("{} {}", format_range(entry), entry.format_value())
+                }
+
+                fn describe_change(change: &Change<Self::Entry>) -> String {
+                    format!(
+                        "{} {} -> {}",
+                        format_range(&change.old),
+                        change.old.format_value(),
+                        change.new.format_value()
+                    )
+                }
+            }
+        )*
+    };
+}
+
+impl_range_diff!(Ipv4AsnDb, Ipv6AsnDb, Ipv4CountryDb, Ipv6CountryDb);
+
+impl Diff for AsDb {
+    fn diff_entries(old: Vec<AsEntry>, new: Vec<AsEntry>) -> EntryDiff<AsEntry> {
+        keyed_diff(old, new, |e| e.asn)
+    }
+
+    fn describe(entry: &AsEntry) -> String {
+        format!("AS{} {}", entry.asn, entry.name)
+    }
+}
+
+impl Diff for CountryDb {
+    fn diff_entries(old: Vec<CountryEntry>, new: Vec<CountryEntry>) -> EntryDiff<CountryEntry> {
+        keyed_diff(old, new, |e| e.code.clone())
+    }
+
+    fn describe(entry: &CountryEntry) -> String {
+        format!("{} {}", entry.code, entry.name)
+    }
+}
+
+impl Diff for OuiDb {
+    fn diff_entries(old: Vec<OuiEntry>, new: Vec<OuiEntry>) -> EntryDiff<OuiEntry> {
+        keyed_diff(old, new, |e| e.mac_prefix.clone())
+    }
+
+    fn describe(entry: &OuiEntry) -> String {
+        match &entry.vendor_detail {
+            Some(detail) => format!("{} {} ({})", entry.mac_prefix, entry.vendor, detail),
+            None => format!("{} {}", entry.mac_prefix, entry.vendor),
+        }
+    }
+}
+
+impl Diff for TcpServiceDb {
+    fn diff_entries(
+        old: Vec<TcpServiceEntry>,
+        new: Vec<TcpServiceEntry>,
+    ) -> EntryDiff<TcpServiceEntry> {
+        keyed_diff(old, new, |e| e.port)
+    }
+
+    fn describe(entry: &TcpServiceEntry) -> String {
+        format!("{}/tcp {}", entry.port, entry.name)
+    }
+}
+
+impl Diff for UdpServiceDb {
+    fn diff_entries(
+        old: Vec<UdpServiceEntry>,
+        new: Vec<UdpServiceEntry>,
+    ) -> EntryDiff<UdpServiceEntry> {
+        keyed_diff(old, new, |e| e.port)
+    }
+
+    fn describe(entry: &UdpServiceEntry) -> String {
+        format!("{}/udp {}", entry.port, entry.name)
+    }
+}
+
+pub fn diff(config: DiffConfig) -> Result<()> {
+    let kind = match config.kind {
+        Some(kind) => kind,
+        None => detect_kind(&config.old)?,
+    };
+    let mut out: Box<dyn Write> = match &config.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path).map_err(|e| {
+            anyhow::anyhow!("Failed to create output file {}: {}", path.display(), e)
+        })?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    with_dataset!(kind, diff_dataset, &config, &mut out)?;
+    out.flush()?;
+    Ok(())
+}
+
+fn diff_dataset<D>(config: &DiffConfig, out: &mut dyn Write) -> Result<()>
+where
+    D: Diff,
+    D::Entry: Serialize,
+{
+    let old = load::<D>(&config.old)?;
+    let new = load::<D>(&config.new)?;
+    let diff = D::diff_entries(old.entries(), new.entries());
+    tracing::debug!(
+        "{}: {} added, {} removed, {} changed",
+        D::KIND,
+        diff.added.len(),
+        diff.removed.len(),
+        diff.changed.len()
+    );
+    match config.format {
+        DiffFormat::Json => {
+            let report = DiffReport {
+                dataset: D::KIND.name(),
+                old: config.old.display().to_string(),
+                new: config.new.display().to_string(),
+                summary: DiffSummary {
+                    added: diff.added.len(),
+                    removed: diff.removed.len(),
+                    changed: diff.changed.len(),
+                    unchanged: diff.unchanged,
+                },
+                diff: &diff,
+            };
+            serde_json::to_writer_pretty(&mut *out, &report)?;
+            writeln!(out)?;
+        }
+        DiffFormat::Text => write_text::<D, _>(out, config, &diff)?,
+    }
+    Ok(())
+}
+
+/// Load `D` from a CSV file (by extension) or a BIN file
+fn load<D: NdbDataset>(path: &Path) -> Result<D> {
+    let is_csv = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+    let db = if is_csv {
+        File::open(path)
+            .map_err(ndb_core::Error::from)
+            .and_then(|file| D::from_csv(BufReader::new(file)))
+    } else {
+        D::from_path(path)
+    };
+    db.map_err(|e| anyhow::anyhow!("Failed to load {} from {}: {}", D::KIND, path.display(), e))
+}
+
+/// Detect the dataset of `path` from its BIN header, or from its file name
+/// (e.g. `ipv4-country.bin` or `ipv4-country.csv`)
+fn detect_kind(path: &Path) -> Result<DatasetKind> {
+    if let Ok(bytes) = std::fs::read(path) {
+        let header = if compress::is_zstd(&bytes) {
+            compress::decompress(&bytes)
+                .ok()
+                .and_then(|data| bin::decode_header(&data).ok().and_then(|(h, _)| h))
+        } else {
+            bin::decode_header(&bytes).ok().and_then(|(h, _)| h)
+        };
+        if let Some(header) = header {
+            return Ok(header.kind);
+        }
+    }
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let file_name = file_name.strip_suffix(ZSTD_SUFFIX).unwrap_or(file_name);
+    DatasetKind::ALL
+        .into_iter()
+        .find(|&kind| with_dataset!(kind, file_names).contains(&file_name))
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Cannot detect the dataset of {}, use --dataset",
+                path.display()
+            )
+        })
+}
+
+/// BIN and CSV file names of dataset `D`
+fn file_names<D: NdbDataset>() -> [&'static str; 2] {
+    [D::BIN_NAME, D::CSV_NAME]
+}
+
+/// Diff entries identified by `key`
+fn keyed_diff<E, K, F>(old: Vec<E>, new: Vec<E>, key: F) -> EntryDiff<E>
+where
+    E: PartialEq,
+    K: Ord,
+    F: Fn(&E) -> K,
+{
+    let mut old: BTreeMap<K, E> = old.into_iter().map(|e| (key(&e), e)).collect();
+    let mut diff = EntryDiff::default();
+    for entry in new {
+        match old.remove(&key(&entry)) {
+            Some(prev) if prev == entry => diff.unchanged += 1,
+            Some(prev) => diff.changed.push(Change {
+                old: prev,
+                new: entry,
+            }),
+            None => diff.added.push(entry),
+        }
+    }
+    diff.removed = old.into_values().collect();
+    diff.added.sort_by_key(|e| key(e));
+    diff.changed.sort_by_key(|c| key(&c.new));
+    diff
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Added,
+    Removed,
+    Changed,
+    Unchanged,
+}
+
+/// Diff IP range entries by comparing the value each address maps to.
+/// Both lists must be free of overlaps.
+fn range_diff<E: RangeRecord>(mut old: Vec<E>, mut new: Vec<E>) -> EntryDiff<E> {
+    old.sort_by_key(|e| e.bounds());
+    new.sort_by_key(|e| e.bounds());
+    // Every address between two consecutive boundaries maps to the same old and new value
+    let mut boundaries: Vec<u128> = old
+        .iter()
+        .chain(new.iter())
+        .flat_map(|e| {
+            let (start, end) = e.bounds();
+            [Some(start), end.checked_add(1)]
+        })
+        .flatten()
+        .collect();
+    boundaries.sort_unstable();
+    boundaries.dedup();
+
+    let mut diff = EntryDiff::default();
+    let mut counts = AddressCounts::default();
+    // Last emitted segment, extended while the following segments continue it
+    let mut last: Option<(Section, u128, (usize, usize))> = None;
+    let (mut i, mut j) = (0, 0);
+    for (k, &start) in boundaries.iter().enumerate() {
+        let end = boundaries.get(k + 1).map_or(u128::MAX, |next| next - 1);
+        let old_entry = covering(&old, &mut i, start);
+        let new_entry = covering(&new, &mut j, start);
+        let section = match (old_entry, new_entry) {
+            (None, None) => {
+                last = None;
+                continue;
+            }
+            (Some(_), None) => Section::Removed,
+            (None, Some(_)) => Section::Added,
+            (Some(a), Some(b)) if a.same_value(b) => Section::Unchanged,
+            (Some(_), Some(_)) => Section::Changed,
+        };
+        // Saturate, as the full IPv6 space (`::/0`) has 2^128 addresses
+        let len = (end - start).saturating_add(1);
+        match section {
+            Section::Added => counts.added = counts.added.saturating_add(len),
+            Section::Removed => counts.removed = counts.removed.saturating_add(len),
+            Section::Changed => counts.changed = counts.changed.saturating_add(len),
+            Section::Unchanged => {}
+        }
+        let extends = last
+            .is_some_and(|(s, last_end, _)| s == section && last_end.checked_add(1) == Some(start));
+        let extended = extends
+            && match section {
+                Section::Added => extend(diff.added.last_mut(), new_entry, end),
+                Section::Removed => extend(diff.removed.last_mut(), old_entry, end),
+                Section::Changed => match diff.changed.last_mut() {
+                    Some(c)
+                        if old_entry.is_some_and(|e| c.old.same_value(e))
+                            && new_entry.is_some_and(|e| c.new.same_value(e)) =>
+                    {
+                        let (from, _) = c.old.bounds();
+                        c.old.set_bounds(from, end);
+                        c.new.set_bounds(from, end);
+                        true
+                    }
+                    _ => false,
+                },
+                // Only merge segments of the same pair of entries
+                Section::Unchanged => last.is_some_and(|(_, _, idx)| idx == (i, j)),
+            };
+        if !extended {
+            match section {
+                Section::Added => diff.added.extend(segment(new_entry, start, end)),
+                Section::Removed => diff.removed.extend(segment(old_entry, start, end)),
+                Section::Changed => {
+                    if let (Some(old), Some(new)) = (
+                        segment(old_entry, start, end),
+                        segment(new_entry, start, end),
+                    ) {
+                        diff.changed.push(Change { old, new });
+                    }
+                }
+                Section::Unchanged => diff.unchanged += 1,
+            }
+        }
+        last = Some((section, end, (i, j)));
+    }
+    diff.addresses = Some(counts);
+    diff
+}
+
+/// Entry of the sorted `entries` containing `addr`, advancing `idx` past earlier entries
+fn covering<'a, E: RangeRecord>(entries: &'a [E], idx: &mut usize, addr: u128) -> Option<&'a E> {
+    while entries.get(*idx).is_some_and(|e| e.bounds().1 < addr) {
+        *idx += 1;
+    }
+    entries.get(*idx).filter(|e| e.bounds().0 <= addr)
+}
+
+/// Copy of `entry` narrowed to `start..=end`
+fn segment<E: RangeRecord>(entry: Option<&E>, start: u128, end: u128) -> Option<E> {
+    entry.map(|e| {
+        let mut e = e.clone();
+        e.set_bounds(start, end);
+        e
+    })
+}
+
+/// Extend `last` up to `end` if it maps to the same value as `entry`
+fn extend<E: RangeRecord>(last: Option<&mut E>, entry: Option<&E>, end: u128) -> bool {
+    match (last, entry) {
+        (Some(last), Some(entry)) if last.same_value(entry) => {
+            let (start, _) = last.bounds();
+            last.set_bounds(start, end);
+            true
+        }
+        _ => false,
+    }
+}
+
+fn format_range<E: RangeRecord>(entry: &E) -> String {
+    let (start, end) = entry.bounds();
+    format!("{}-{}", E::format_bound(start), E::format_bound(end))
+}
+
+fn write_text<D, W>(out: &mut W, config: &DiffConfig, diff: &EntryDiff<D::Entry>) -> Result<()>
+where
+    D: Diff,
+    D::Entry: Serialize,
+    W: Write + ?Sized,
+{
+    writeln!(
+        out,
+        "{}: {} -> {}",
+        D::KIND,
+        config.old.display(),
+        config.new.display()
+    )?;
+    let addresses = |count: u128| match diff.addresses {
+        Some(_) => format!(" ({} addresses)", count),
+        None => String::new(),
+    };
+    let counts = diff.addresses.unwrap_or_default();
+    writeln!(
+        out,
+        "  added:     {}{}",
+        diff.added.len(),
+        addresses(counts.added)
+    )?;
+    writeln!(
+        out,
+        "  removed:   {}{}",
+        diff.removed.len(),
+        addresses(counts.removed)
+    )?;
+    writeln!(
+        out,
+        "  changed:   {}{}",
+        diff.changed.len(),
+        addresses(counts.changed)
+    )?;
+    writeln!(out, "  unchanged: {}", diff.unchanged)?;
+    if diff.is_empty() {
+        writeln!(out, "No differences")?;
+        return Ok(());
+    }
+
+    let lines = [
+        ('+', diff.added.iter().map(D::describe).collect::<Vec<_>>()),
+        ('-', diff.removed.iter().map(D::describe).collect()),
+        ('~', diff.changed.iter().map(D::describe_change).collect()),
+    ];
+    for (marker, lines) in lines {
+        if lines.is_empty() {
+            continue;
+        }
+        writeln!(out)?;
+        for line in lines.iter().take(config.limit) {
+            writeln!(out, "{} {}", marker, line)?;
+        }
+        if lines.len() > config.limit {
+            writeln!(out, "  ... and {} more", lines.len() - config.limit)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndb_ipv4_country::Ipv4CountryEntry;
+
+    fn entry(ip_from: u32, ip_to: u32, country_code: &str) -> Ipv4CountryEntry {
+        Ipv4CountryEntry {
+            ip_from,
+            ip_to,
+            country_code: country_code.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_range_diff() {
+        let old = vec![
+            entry(0, 99, "JP"),
+            entry(100, 199, "US"),
+            entry(300, 399, "DE"),
+        ];
+        let new = vec![
+            entry(0, 49, "JP"),
+            entry(50, 199, "US"),
+            entry(200, 249, "FR"),
+        ];
+        let diff = range_diff(old, new);
+        assert_eq!(diff.added, vec![entry(200, 249, "FR")]);
+        assert_eq!(diff.removed, vec![entry(300, 399, "DE")]);
+        assert_eq!(
+            diff.changed,
+            vec![Change {
+                old: entry(50, 99, "JP"),
+                new: entry(50, 99, "US"),
+            }]
+        );
+        assert_eq!(diff.unchanged, 2);
+        assert_eq!(
+            diff.addresses,
+            Some(AddressCounts {
+                added: 50,
+                removed: 100,
+                changed: 50,
+            })
+        );
+    }
+
+    #[test]
+    fn test_range_diff_full_ipv6_space() {
+        use ndb_ipv6_asn::Ipv6AsnEntry;
+        let v6 = |ip_from: u128, ip_to: u128, asn: u32| Ipv6AsnEntry {
+            ip_from,
+            ip_to,
+            asn,
+        };
+        let diff = range_diff(vec![v6(0, u128::MAX, 1)], vec![v6(0, u128::MAX, 2)]);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.addresses.unwrap().changed, u128::MAX);
+
+        // The running total saturates across several large segments
+        let half = 1u128 << 127;
+        let new = vec![v6(0, half - 1, 2), v6(half, u128::MAX, 3)];
+        let diff = range_diff(vec![v6(0, u128::MAX, 1)], new);
+        assert_eq!(diff.changed.len(), 2);
+        assert_eq!(diff.addresses.unwrap().changed, u128::MAX);
+    }
+
+    #[test]
+    fn test_range_diff_identical() {
+        let entries = vec![entry(0, 99, "JP"), entry(100, 199, "US")];
+        let diff = range_diff(entries.clone(), entries);
+        assert!(diff.is_empty());
+        assert_eq!(diff.unchanged, 2);
+    }
+
+    #[test]
+    fn test_keyed_diff() {
+        let as_entry = |asn: u32, name: &str| AsEntry {
+            asn,
+            name: name.to_string(),
+        };
+        let old = vec![
+            as_entry(1, "Old Name"),
+            as_entry(2, "Gone"),
+            as_entry(3, "Same"),
+        ];
+        let new = vec![
+            as_entry(4, "New"),
+            as_entry(3, "Same"),
+            as_entry(1, "New Name"),
+        ];
+        let diff = keyed_diff(old, new, |e| e.asn);
+        assert_eq!(diff.added, vec![as_entry(4, "New")]);
+        assert_eq!(diff.removed, vec![as_entry(2, "Gone")]);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].new, as_entry(1, "New Name"));
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(diff.addresses, None);
+    }
+}
@@ -0,0 +1,352 @@
+use crate::db;
+use crate::output::{OutputFormat, Record, RecordWriter};
+use anyhow::Result;
+use clap::ArgMatches;
+use ndb::NetDb;
+use ndb_oui::{MacAddr, OuiDb};
+use ndb_tcp_service::TcpServiceDb;
+use ndb_udp_service::UdpServiceDb;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+/// Kind of values read from the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputKind {
+    Ip,
+    Mac,
+    Port,
+}
+
+impl InputKind {
+    pub const NAMES: [&'static str; 3] = ["ip", "mac", "port"];
+
+    pub fn from_str(s: &str) -> InputKind {
+        match s {
+            "mac" => InputKind::Mac,
+            "port" => InputKind::Port,
+            _ => InputKind::Ip,
+        }
+    }
+}
+
+pub struct EnrichConfig {
+    pub kind: InputKind,
+    pub input: Option<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub format: OutputFormat,
+    pub data_dir: Option<PathBuf>,
+}
+
+impl EnrichConfig {
+    pub fn from_cli_arg(matches: &ArgMatches) -> Self {
+        let kind = matches
+            .get_one::<String>("type")
+            .map(|s| InputKind::from_str(s))
+            .unwrap_or(InputKind::Ip);
+        let input = matches.get_one::<PathBuf>("input").cloned();
+        let output = matches.get_one::<PathBuf>("output").cloned();
+        let format = matches
+            .get_one::<String>("format")
+            .map(|s| OutputFormat::from_str(s))
+            .unwrap_or(OutputFormat::Jsonl);
+        let data_dir = matches.get_one::<PathBuf>("data-dir").cloned();
+        EnrichConfig {
+            kind,
+            input,
+            output,
+            format,
+            data_dir,
+        }
+    }
+}
+
+/// Enriched IP address, or the error for an unparseable line
+#[derive(Debug, Serialize)]
+pub struct IpRow {
+    pub input: String,
+    pub asn: Option<u32>,
+    pub as_name: Option<String>,
+    pub country_code: Option<String>,
+    pub country_name: Option<String>,
+    pub error: Option<String>,
+}
+
+impl Record for IpRow {
+    fn header() -> &'static [&'static str] {
+        &[
+            "input",
+            "asn",
+            "as_name",
+            "country_code",
+            "country_name",
+            "error",
+        ]
+    }
+
+    fn fields(&self) -> Vec<Option<String>> {
+        vec![
+            Some(self.input.clone()),
+            self.asn.map(|asn| asn.to_string()),
+            self.as_name.clone(),
+            self.country_code.clone(),
+            self.country_name.clone(),
+            self.error.clone(),
+        ]
+    }
+}
+
+/// Enriched MAC address, or the error for an unparseable line
+#[derive(Debug, Serialize)]
+pub struct MacRow {
+    pub input: String,
+    pub mac_prefix: Option<String>,
+    pub vendor: Option<String>,
+    pub vendor_detail: Option<String>,
+    pub error: Option<String>,
+}
+
+impl Record for MacRow {
+    fn header() -> &'static [&'static str] {
+        &["input", "mac_prefix", "vendor", "vendor_detail", "error"]
+    }
+
+    fn fields(&self) -> Vec<Option<String>> {
+        vec![
+            Some(self.input.clone()),
+            self.mac_prefix.clone(),
+            self.vendor.clone(),
+            self.vendor_detail.clone(),
+            self.error.clone(),
+        ]
+    }
+}
+
+/// Enriched port number, or the error for an unparseable line
+#[derive(Debug, Serialize)]
+pub struct PortRow {
+    pub input: String,
+    pub port: Option<u16>,
+    pub tcp_service: Option<String>,
+    pub udp_service: Option<String>,
+    pub description: Option<String>,
+    pub error: Option<String>,
+}
+
+impl Record for PortRow {
+    fn header() -> &'static [&'static str] {
+        &[
+            "input",
+            "port",
+            "tcp_service",
+            "udp_service",
+            "description",
+            "error",
+        ]
+    }
+
+    fn fields(&self) -> Vec<Option<String>> {
+        vec![
+            Some(self.input.clone()),
+            self.port.map(|port| port.to_string()),
+            self.tcp_service.clone(),
+            self.udp_service.clone(),
+            self.description.clone(),
+            self.error.clone(),
+        ]
+    }
+}
+
+/// Databases loaded for the selected input kind
+enum Enricher {
+    Ip(Box<NetDb>),
+    Mac(OuiDb),
+    Port(TcpServiceDb, UdpServiceDb),
+}
+
+impl Enricher {
+    fn load(kind: InputKind, data_dir: Option<&std::path::Path>) -> Result<Self> {
+        let enricher = match kind {
+            InputKind::Ip => Enricher::Ip(Box::new(db::load_net_db(data_dir)?)),
+            InputKind::Mac => Enricher::Mac(db::load_db(data_dir)?),
+            InputKind::Port => Enricher::Port(db::load_db(data_dir)?, db::load_db(data_dir)?),
+        };
+        Ok(enricher)
+    }
+
+    fn write_header<W: Write>(&self, format: OutputFormat, out: W) -> Result<RecordWriter<W>> {
+        let header = match self {
+            Enricher::Ip(_) => IpRow::header(),
+            Enricher::Mac(_) => MacRow::header(),
+            Enricher::Port(_, _) => PortRow::header(),
+        };
+        RecordWriter::new(format, header, out)
+    }
+
+    fn enrich<W: Write>(&self, line: &str, writer: &mut RecordWriter<W>) -> Result<()> {
+        match self {
+            Enricher::Ip(net_db) => writer.write(&enrich_ip(net_db, line)),
+            Enricher::Mac(oui_db) => writer.write(&enrich_mac(oui_db, line)),
+            Enricher::Port(tcp_db, udp_db) => writer.write(&enrich_port(tcp_db, udp_db, line)),
+        }
+    }
+}
+
+pub fn enrich(config: EnrichConfig) -> Result<()> {
+    let enricher = Enricher::load(config.kind, config.data_dir.as_deref())?;
+
+    let reader: Box<dyn BufRead> = match &config.input {
+        Some(path) => Box::new(BufReader::new(File::open(path).map_err(|e| {
+            anyhow::anyhow!("Failed to open input file {}: {}", path.display(), e)
+        })?)),
+        None => Box::new(io::stdin().lock()),
+    };
+    let out: Box<dyn Write> = match &config.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path).map_err(|e| {
+            anyhow::anyhow!("Failed to create output file {}: {}", path.display(), e)
+        })?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+
+    let mut writer = enricher.write_header(config.format, out)?;
+    let mut count: usize = 0;
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        enricher.enrich(line, &mut writer)?;
+        count += 1;
+    }
+    writer.finish()?;
+    tracing::debug!("Enriched {} records", count);
+    Ok(())
+}
+
+fn enrich_ip(net_db: &NetDb, input: &str) -> IpRow {
+    match input.parse::<IpAddr>() {
+        Ok(ip) => {
+            let info = net_db.lookup_ip(ip);
+            IpRow {
+                input: input.to_string(),
+                asn: info.asn,
+                as_name: info.as_name,
+                country_code: info.country_code,
+                country_name: info.country_name,
+                error: None,
+            }
+        }
+        Err(e) => IpRow {
+            input: input.to_string(),
+            asn: None,
+            as_name: None,
+            country_code: None,
+            country_name: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+fn enrich_mac(oui_db: &OuiDb, input: &str) -> MacRow {
+    match parse_mac(input) {
+        Ok(mac) => {
+            let entry = oui_db.lookup_mac(&mac);
+            MacRow {
+                input: input.to_string(),
+                mac_prefix: entry.map(|e| e.mac_prefix.clone()),
+                vendor: entry.map(|e| e.vendor.clone()),
+                vendor_detail: entry.and_then(|e| e.vendor_detail.clone()),
+                error: None,
+            }
+        }
+        Err(e) => MacRow {
+            input: input.to_string(),
+            mac_prefix: None,
+            vendor: None,
+            vendor_detail: None,
+            error: Some(e),
+        },
+    }
+}
+
+fn enrich_port(tcp_db: &TcpServiceDb, udp_db: &UdpServiceDb, input: &str) -> PortRow {
+    match parse_port(input) {
+        Ok((port, proto)) => {
+            let tcp = if proto != Some("udp") {
+                tcp_db.get(port)
+            } else {
+                None
+            };
+            let udp = if proto != Some("tcp") {
+                udp_db.get(port)
+            } else {
+                None
+            };
+            let description = tcp
+                .and_then(|e| e.description.clone())
+                .or_else(|| udp.and_then(|e| e.description.clone()));
+            PortRow {
+                input: input.to_string(),
+                port: Some(port),
+                tcp_service: tcp.map(|e| e.name.clone()),
+                udp_service: udp.map(|e| e.name.clone()),
+                description,
+                error: None,
+            }
+        }
+        Err(e) => PortRow {
+            input: input.to_string(),
+            port: None,
+            tcp_service: None,
+            udp_service: None,
+            description: None,
+            error: Some(e),
+        },
+    }
+}
+
+/// Parse a MAC address separated by `:` or `-`
+fn parse_mac(s: &str) -> Result<MacAddr, String> {
+    s.replace('-', ":")
+        .parse::<MacAddr>()
+        .map_err(|e| format!("invalid MAC address: {}", e))
+}
+
+/// Parse a port number with an optional `/tcp` or `/udp` suffix
+fn parse_port(s: &str) -> Result<(u16, Option<&str>), String> {
+    let (port, proto) = match s.split_once('/') {
+        Some((port, proto)) => match proto {
+            "tcp" | "udp" => (port, Some(proto)),
+            _ => return Err(format!("invalid protocol: {}", proto)),
+        },
+        None => (s, None),
+    };
+    let port = port
+        .parse::<u16>()
+        .map_err(|e| format!("invalid port number: {}", e))?;
+    Ok((port, proto))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mac() {
+        let mac = parse_mac("ac-4a-56-12-34-56").unwrap();
+        assert_eq!(mac.octets(), [0xac, 0x4a, 0x56, 0x12, 0x34, 0x56]);
+        assert!(parse_mac("ac:4a:56").is_err());
+        assert!(parse_mac("not a mac").is_err());
+    }
+
+    #[test]
+    fn test_parse_port() {
+        assert_eq!(parse_port("443"), Ok((443, None)));
+        assert_eq!(parse_port("53/udp"), Ok((53, Some("udp"))));
+        assert!(parse_port("53/sctp").is_err());
+        assert!(parse_port("70000").is_err());
+    }
+}
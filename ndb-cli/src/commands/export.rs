@@ -0,0 +1,293 @@
+use crate::db::{self, with_dataset};
+use anyhow::Result;
+use clap::ArgMatches;
+use ndb_core::bin::DatasetKind;
+use ndb_core::NdbDataset;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+
+/// Output format of exported entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    Jsonl,
+    /// Wireshark `manuf` file, for the OUI dataset only
+    Manuf,
+    /// `/etc/services` file, for the service datasets only
+    Services,
+}
+
+impl ExportFormat {
+    pub const NAMES: [&'static str; 5] = ["csv", "json", "jsonl", "manuf", "services"];
+
+    pub fn from_str(s: &str) -> ExportFormat {
+        match s {
+            "json" => ExportFormat::Json,
+            "jsonl" => ExportFormat::Jsonl,
+            "manuf" => ExportFormat::Manuf,
+            "services" => ExportFormat::Services,
+            _ => ExportFormat::Csv,
+        }
+    }
+}
+
+pub struct ExportConfig {
+    pub kind: DatasetKind,
+    pub format: ExportFormat,
+    /// BIN file to export instead of the one in `data_dir` or the bundled one
+    pub input: Option<PathBuf>,
+    pub data_dir: Option<PathBuf>,
+    pub output: Option<PathBuf>,
+    /// Render `ip_from` and `ip_to` as IP addresses instead of integers
+    pub ip_notation: bool,
+}
+
+impl ExportConfig {
+    pub fn from_cli_arg(matches: &ArgMatches) -> Self {
+        let kind = matches
+            .get_one::<String>("dataset")
+            .and_then(|name| DatasetKind::from_name(name))
+            .unwrap_or(DatasetKind::As);
+        let format = matches
+            .get_one::<String>("format")
+            .map(|s| ExportFormat::from_str(s))
+            .unwrap_or(ExportFormat::Csv);
+        let input = matches.get_one::<PathBuf>("input").cloned();
+        let data_dir = matches.get_one::<PathBuf>("data-dir").cloned();
+        let output = matches.get_one::<PathBuf>("output").cloned();
+        let ip_notation = matches.get_flag("ip-notation");
+        ExportConfig {
+            kind,
+            format,
+            input,
+            data_dir,
+            output,
+            ip_notation,
+        }
+    }
+}
+
+/// IP to ASN entry with the range bounds rendered as addresses
+#[derive(Debug, Serialize)]
+struct AsnRangeRow<A> {
+    ip_from: A,
+    ip_to: A,
+    asn: u32,
+}
+
+/// IP to country entry with the range bounds rendered as addresses
+#[derive(Debug, Serialize)]
+struct CountryRangeRow<A> {
+    ip_from: A,
+    ip_to: A,
+    country_code: String,
+}
+
+pub fn export(config: ExportConfig) -> Result<()> {
+    let out: Box<dyn Write> = match &config.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path).map_err(|e| {
+            anyhow::anyhow!("Failed to create output file {}: {}", path.display(), e)
+        })?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    let count = if config.format == ExportFormat::Manuf {
+        export_manuf(&config, out)?
+    } else if config.format == ExportFormat::Services {
+        export_etc_services(&config, out)?
+    } else if config.ip_notation {
+        export_ip_notation(&config, out)?
+    } else {
+        with_dataset!(config.kind, export_dataset, &config, out)?
+    };
+    tracing::debug!("Exported {} {} entries", count, config.kind);
+    Ok(())
+}
+
+/// Export the entries of dataset `D` as they are stored
+fn export_dataset<D: NdbDataset>(config: &ExportConfig, out: Box<dyn Write>) -> Result<usize> {
+    let db = load::<D>(config)?;
+    write_entries(config.format, db.entries(), out)
+}
+
+/// Export an IP range dataset with `ip_from` and `ip_to` rendered as addresses
+fn export_ip_notation(config: &ExportConfig, out: Box<dyn Write>) -> Result<usize> {
+    match config.kind {
+        DatasetKind::Ipv4Asn => {
+            let db = load::<ndb_ipv4_asn::Ipv4AsnDb>(config)?;
+            let rows = db.entries().into_iter().map(|entry| AsnRangeRow {
+                ip_from: Ipv4Addr::from(entry.ip_from),
+                ip_to: Ipv4Addr::from(entry.ip_to),
+                asn: entry.asn,
+            });
+            write_entries(config.format, rows, out)
+        }
+        DatasetKind::Ipv6Asn => {
+            let db = load::<ndb_ipv6_asn::Ipv6AsnDb>(config)?;
+            let rows = db.entries().into_iter().map(|entry| AsnRangeRow {
+                ip_from: Ipv6Addr::from(entry.ip_from),
+                ip_to: Ipv6Addr::from(entry.ip_to),
+                asn: entry.asn,
+            });
+            write_entries(config.format, rows, out)
+        }
+        DatasetKind::Ipv4Country => {
+            let db = load::<ndb_ipv4_country::Ipv4CountryDb>(config)?;
+            let rows = db.entries().into_iter().map(|entry| CountryRangeRow {
+                ip_from: Ipv4Addr::from(entry.ip_from),
+                ip_to: Ipv4Addr::from(entry.ip_to),
+                country_code: entry.country_code,
+            });
+            write_entries(config.format, rows, out)
+        }
+        DatasetKind::Ipv6Country => {
+            let db = load::<ndb_ipv6_country::Ipv6CountryDb>(config)?;
+            let rows = db.entries().into_iter().map(|entry| CountryRangeRow {
+                ip_from: Ipv6Addr::from(entry.ip_from),
+                ip_to: Ipv6Addr::from(entry.ip_to),
+                country_code: entry.country_code,
+            });
+            write_entries(config.format, rows, out)
+        }
+        kind => Err(anyhow::anyhow!(
+            "--ip-notation only applies to IP range datasets, not {}",
+            kind
+        )),
+    }
+}
+
+/// Export the OUI dataset as a Wireshark `manuf` file
+fn export_manuf(config: &ExportConfig, out: Box<dyn Write>) -> Result<usize> {
+    if config.kind != DatasetKind::Oui {
+        anyhow::bail!(
+            "The manuf format only applies to the oui dataset, not {}",
+            config.kind
+        );
+    }
+    let db = load::<ndb_oui::OuiDb>(config)?;
+    Ok(db.write_manuf(out)?)
+}
+
+/// Export a service dataset as an `/etc/services` file
+fn export_etc_services(config: &ExportConfig, out: Box<dyn Write>) -> Result<usize> {
+    match config.kind {
+        DatasetKind::TcpService => {
+            Ok(load::<ndb_tcp_service::TcpServiceDb>(config)?.write_etc_services(out)?)
+        }
+        DatasetKind::UdpService => {
+            Ok(load::<ndb_udp_service::UdpServiceDb>(config)?.write_etc_services(out)?)
+        }
+        kind => anyhow::bail!(
+            "The services format only applies to the tcp-service and udp-service datasets, not {}",
+            kind
+        ),
+    }
+}
+
+/// Load dataset `D` from `--input`, or from `--data-dir` falling back to the bundled data
+fn load<D: NdbDataset>(config: &ExportConfig) -> Result<D> {
+    match &config.input {
+        Some(path) => load_file(path),
+        None => db::load_db(config.data_dir.as_deref()),
+    }
+}
+
+fn load_file<D: NdbDataset>(path: &Path) -> Result<D> {
+    D::from_path(path).map_err(|e| anyhow::anyhow!("Failed to load {}: {}", path.display(), e))
+}
+
+/// Write `entries` in `format`, returning the number of entries written
+fn write_entries<E, I, W>(format: ExportFormat, entries: I, mut out: W) -> Result<usize>
+where
+    E: Serialize,
+    I: IntoIterator<Item = E>,
+    W: Write,
+{
+    let mut count = 0;
+    match format {
+        ExportFormat::Csv => {
+            let mut wtr = csv::Writer::from_writer(out);
+            for entry in entries {
+                wtr.serialize(entry)?;
+                count += 1;
+            }
+            wtr.flush()?;
+        }
+        ExportFormat::Json => {
+            let entries: Vec<E> = entries.into_iter().collect();
+            count = entries.len();
+            serde_json::to_writer_pretty(&mut out, &entries)?;
+            out.write_all(b"\n")?;
+            out.flush()?;
+        }
+        ExportFormat::Jsonl => {
+            for entry in entries {
+                serde_json::to_writer(&mut out, &entry)?;
+                out.write_all(b"\n")?;
+                count += 1;
+            }
+            out.flush()?;
+        }
+        ExportFormat::Manuf => anyhow::bail!("The manuf format only applies to OUI entries"),
+        ExportFormat::Services => {
+            anyhow::bail!("The services format only applies to service entries")
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows() -> Vec<AsnRangeRow<Ipv4Addr>> {
+        vec![
+            AsnRangeRow {
+                ip_from: Ipv4Addr::new(10, 0, 0, 0),
+                ip_to: Ipv4Addr::new(10, 0, 0, 255),
+                asn: 64500,
+            },
+            AsnRangeRow {
+                ip_from: Ipv4Addr::new(10, 0, 1, 0),
+                ip_to: Ipv4Addr::new(10, 0, 1, 255),
+                asn: 64501,
+            },
+        ]
+    }
+
+    fn render(format: ExportFormat) -> String {
+        let mut buf = Vec::new();
+        let count = write_entries(format, rows(), &mut buf).unwrap();
+        assert_eq!(count, 2);
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_export_csv() {
+        assert_eq!(
+            render(ExportFormat::Csv),
+            "ip_from,ip_to,asn\n10.0.0.0,10.0.0.255,64500\n10.0.1.0,10.0.1.255,64501\n"
+        );
+    }
+
+    #[test]
+    fn test_export_jsonl() {
+        let out = render(ExportFormat::Jsonl);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            lines[0],
+            r#"{"ip_from":"10.0.0.0","ip_to":"10.0.0.255","asn":64500}"#
+        );
+        assert_eq!(lines.len(), 2);
+    }
+
+    #[test]
+    fn test_export_json() {
+        let value: serde_json::Value = serde_json::from_str(&render(ExportFormat::Json)).unwrap();
+        assert_eq!(value[1]["asn"], 64501);
+        assert_eq!(value.as_array().map(Vec::len), Some(2));
+    }
+}
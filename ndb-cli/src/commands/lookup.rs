@@ -0,0 +1,308 @@
+use crate::db;
+use crate::output::{OutputFormat, Record, RecordWriter};
+use anyhow::Result;
+use clap::ArgMatches;
+use ndb::IpInfo;
+use ndb_core::utils::net;
+use ndb_ipv4_asn::{Ipv4AsnDb, Ipv4Net};
+use ndb_ipv4_country::Ipv4CountryDb;
+use ndb_ipv6_asn::{Ipv6AsnDb, Ipv6Net};
+use ndb_ipv6_country::Ipv6CountryDb;
+use serde::Serialize;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+pub struct LookupConfig {
+    pub addrs: Vec<IpAddr>,
+    pub format: OutputFormat,
+    pub data_dir: Option<PathBuf>,
+}
+
+impl LookupConfig {
+    pub fn from_cli_arg(matches: &ArgMatches) -> Self {
+        let addrs = matches
+            .get_many::<IpAddr>("addr")
+            .map(|values| values.copied().collect())
+            .unwrap_or_default();
+        let format = matches
+            .get_one::<String>("format")
+            .map(|s| OutputFormat::from_str(s))
+            .unwrap_or(OutputFormat::Table);
+        let data_dir = matches.get_one::<PathBuf>("data-dir").cloned();
+        LookupConfig {
+            addrs,
+            format,
+            data_dir,
+        }
+    }
+}
+
+impl Record for IpInfo {
+    fn header() -> &'static [&'static str] {
+        &["ip", "asn", "as_name", "country_code", "country_name"]
+    }
+
+    fn fields(&self) -> Vec<Option<String>> {
+        vec![
+            Some(self.ip.to_string()),
+            self.asn.map(|asn| asn.to_string()),
+            self.as_name.clone(),
+            self.country_code.clone(),
+            self.country_name.clone(),
+        ]
+    }
+}
+
+pub fn lookup_ip(config: LookupConfig) -> Result<()> {
+    let net_db = db::load_net_db(config.data_dir.as_deref())?;
+    let mut writer = RecordWriter::new(config.format, IpInfo::header(), io::stdout().lock())?;
+    for addr in config.addrs {
+        writer.write(&net_db.lookup_ip(addr))?;
+    }
+    writer.finish()
+}
+
+pub struct AsnLookupConfig {
+    pub asns: Vec<u32>,
+    pub cidr: bool,
+    pub format: OutputFormat,
+    pub data_dir: Option<PathBuf>,
+}
+
+impl AsnLookupConfig {
+    pub fn from_cli_arg(matches: &ArgMatches) -> Self {
+        let asns = matches
+            .get_many::<u32>("asn")
+            .map(|values| values.copied().collect())
+            .unwrap_or_default();
+        let cidr = matches.get_flag("cidr");
+        let format = matches
+            .get_one::<String>("format")
+            .map(|s| OutputFormat::from_str(s))
+            .unwrap_or(OutputFormat::Table);
+        let data_dir = matches.get_one::<PathBuf>("data-dir").cloned();
+        AsnLookupConfig {
+            asns,
+            cidr,
+            format,
+            data_dir,
+        }
+    }
+}
+
+pub struct CountryLookupConfig {
+    pub country_codes: Vec<String>,
+    pub cidr: bool,
+    pub format: OutputFormat,
+    pub data_dir: Option<PathBuf>,
+}
+
+impl CountryLookupConfig {
+    pub fn from_cli_arg(matches: &ArgMatches) -> Self {
+        let country_codes = matches
+            .get_many::<String>("country-code")
+            .map(|values| values.map(|code| code.to_ascii_uppercase()).collect())
+            .unwrap_or_default();
+        let cidr = matches.get_flag("cidr");
+        let format = matches
+            .get_one::<String>("format")
+            .map(|s| OutputFormat::from_str(s))
+            .unwrap_or(OutputFormat::Table);
+        let data_dir = matches.get_one::<PathBuf>("data-dir").cloned();
+        CountryLookupConfig {
+            country_codes,
+            cidr,
+            format,
+            data_dir,
+        }
+    }
+}
+
+/// IP range, or CIDR prefix and its range, with the number of addresses
+#[derive(Debug, Serialize)]
+pub struct RangeFields {
+    pub start: IpAddr,
+    pub end: IpAddr,
+    pub prefix: Option<String>,
+    pub addresses: u128,
+}
+
+impl RangeFields {
+    const HEADER: [&'static str; 4] = ["start", "end", "prefix", "addresses"];
+
+    fn from_ipv4_range(range: RangeInclusive<Ipv4Addr>) -> Self {
+        let addresses = net::ipv4_range_len(&range).into();
+        let (start, end) = range.into_inner();
+        RangeFields {
+            start: start.into(),
+            end: end.into(),
+            prefix: None,
+            addresses,
+        }
+    }
+
+    fn from_ipv6_range(range: RangeInclusive<Ipv6Addr>) -> Self {
+        let addresses = net::ipv6_range_len(&range);
+        let (start, end) = range.into_inner();
+        RangeFields {
+            start: start.into(),
+            end: end.into(),
+            prefix: None,
+            addresses,
+        }
+    }
+
+    fn from_ipv4_net(prefix: Ipv4Net) -> Self {
+        RangeFields {
+            prefix: Some(prefix.to_string()),
+            ..Self::from_ipv4_range(prefix.network()..=prefix.broadcast())
+        }
+    }
+
+    fn from_ipv6_net(prefix: Ipv6Net) -> Self {
+        RangeFields {
+            prefix: Some(prefix.to_string()),
+            ..Self::from_ipv6_range(prefix.network()..=prefix.broadcast())
+        }
+    }
+
+    fn fields(&self) -> Vec<Option<String>> {
+        vec![
+            Some(self.start.to_string()),
+            Some(self.end.to_string()),
+            self.prefix.clone(),
+            Some(self.addresses.to_string()),
+        ]
+    }
+}
+
+/// IP range or CIDR prefix announced by an ASN
+#[derive(Debug, Serialize)]
+pub struct AsnRangeRow {
+    pub asn: u32,
+    #[serde(flatten)]
+    pub range: RangeFields,
+}
+
+impl Record for AsnRangeRow {
+    fn header() -> &'static [&'static str] {
+        const HEADER: [&str; 5] = {
+            let [start, end, prefix, addresses] = RangeFields::HEADER;
+            ["asn", start, end, prefix, addresses]
+        };
+        &HEADER
+    }
+
+    fn fields(&self) -> Vec<Option<String>> {
+        let mut fields = vec![Some(self.asn.to_string())];
+        fields.extend(self.range.fields());
+        fields
+    }
+}
+
+/// IP range or CIDR prefix allocated to a country
+#[derive(Debug, Serialize)]
+pub struct CountryRangeRow {
+    pub country_code: String,
+    #[serde(flatten)]
+    pub range: RangeFields,
+}
+
+impl Record for CountryRangeRow {
+    fn header() -> &'static [&'static str] {
+        const HEADER: [&str; 5] = {
+            let [start, end, prefix, addresses] = RangeFields::HEADER;
+            ["country_code", start, end, prefix, addresses]
+        };
+        &HEADER
+    }
+
+    fn fields(&self) -> Vec<Option<String>> {
+        let mut fields = vec![Some(self.country_code.clone())];
+        fields.extend(self.range.fields());
+        fields
+    }
+}
+
+pub fn lookup_asn(config: AsnLookupConfig) -> Result<()> {
+    let ipv4_db: Ipv4AsnDb = db::load_db(config.data_dir.as_deref())?;
+    let ipv6_db: Ipv6AsnDb = db::load_db(config.data_dir.as_deref())?;
+    let mut writer = RecordWriter::new(config.format, AsnRangeRow::header(), io::stdout().lock())?;
+    for asn in config.asns {
+        let ranges: Vec<RangeFields> = if config.cidr {
+            ipv4_db
+                .cidrs_for_asn(asn)
+                .map(RangeFields::from_ipv4_net)
+                .chain(ipv6_db.cidrs_for_asn(asn).map(RangeFields::from_ipv6_net))
+                .collect()
+        } else {
+            ipv4_db
+                .ranges_for_asn(asn)
+                .map(RangeFields::from_ipv4_range)
+                .chain(
+                    ipv6_db
+                        .ranges_for_asn(asn)
+                        .map(RangeFields::from_ipv6_range),
+                )
+                .collect()
+        };
+        for range in ranges {
+            writer.write(&AsnRangeRow { asn, range })?;
+        }
+        tracing::info!(
+            "AS{}: {} IPv4 addresses, {} IPv6 addresses",
+            asn,
+            ipv4_db.address_count_for_asn(asn),
+            ipv6_db.address_count_for_asn(asn)
+        );
+    }
+    writer.finish()
+}
+
+pub fn lookup_country(config: CountryLookupConfig) -> Result<()> {
+    let ipv4_db: Ipv4CountryDb = db::load_db(config.data_dir.as_deref())?;
+    let ipv6_db: Ipv6CountryDb = db::load_db(config.data_dir.as_deref())?;
+    let mut writer = RecordWriter::new(
+        config.format,
+        CountryRangeRow::header(),
+        io::stdout().lock(),
+    )?;
+    for code in config.country_codes {
+        let ranges: Vec<RangeFields> = if config.cidr {
+            ipv4_db
+                .cidrs_for_country(&code)
+                .map(RangeFields::from_ipv4_net)
+                .chain(
+                    ipv6_db
+                        .cidrs_for_country(&code)
+                        .map(RangeFields::from_ipv6_net),
+                )
+                .collect()
+        } else {
+            ipv4_db
+                .ranges_for_country(&code)
+                .map(RangeFields::from_ipv4_range)
+                .chain(
+                    ipv6_db
+                        .ranges_for_country(&code)
+                        .map(RangeFields::from_ipv6_range),
+                )
+                .collect()
+        };
+        for range in ranges {
+            writer.write(&CountryRangeRow {
+                country_code: code.clone(),
+                range,
+            })?;
+        }
+        tracing::info!(
+            "{}: {} IPv4 addresses, {} IPv6 addresses",
+            code,
+            ipv4_db.address_count_for_country(&code),
+            ipv6_db.address_count_for_country(&code)
+        );
+    }
+    writer.finish()
+}
@@ -1,11 +1,25 @@
 use std::path::PathBuf;
 
 use clap::ArgMatches;
+use ndb_core::bin::DatasetKind;
+use ndb_core::validate::ValidationMode;
 
+pub mod diff;
+pub mod enrich;
+pub mod export;
+pub mod lookup;
+pub mod stats;
 pub mod update;
+pub mod verify;
 
 pub enum AppCommands {
     Update,
+    Lookup,
+    Enrich,
+    Export,
+    Stats,
+    Diff,
+    Verify,
     Default,
 }
 
@@ -13,6 +27,12 @@ impl AppCommands {
     pub fn from_str(s: &str) -> AppCommands {
         match s {
             "update" => AppCommands::Update,
+            "lookup" => AppCommands::Lookup,
+            "enrich" => AppCommands::Enrich,
+            "export" => AppCommands::Export,
+            "stats" => AppCommands::Stats,
+            "diff" => AppCommands::Diff,
+            "verify" => AppCommands::Verify,
             _ => AppCommands::Default,
         }
     }
@@ -22,6 +42,16 @@ pub struct AppConfig {
     pub input_dir: PathBuf,
     pub output_dir: PathBuf,
     pub dry_run: bool,
+    pub source: Option<String>,
+    pub flat: bool,
+    /// Also write zstd compressed copies of the BIN files
+    pub zstd: bool,
+    /// Validate IP range CSV files before building the BIN files
+    pub strict: Option<ValidationMode>,
+    /// Datasets to build; all when empty
+    pub only: Vec<DatasetKind>,
+    /// Datasets not to build
+    pub skip: Vec<DatasetKind>,
 }
 
 impl AppConfig {
@@ -35,10 +65,43 @@ impl AppConfig {
             .cloned()
             .unwrap_or_default();
         let dry_run = matches.get_flag("dry-run");
+        let source = matches.get_one::<String>("source").cloned();
+        let flat = matches.get_flag("flat");
+        let zstd = matches.get_flag("zstd");
+        let strict = matches
+            .get_one::<String>("strict")
+            .map(|mode| match mode.as_str() {
+                "repair" => ValidationMode::Repair,
+                _ => ValidationMode::Reject,
+            });
+        let only = dataset_kinds(matches, "only");
+        let skip = dataset_kinds(matches, "skip");
         AppConfig {
             input_dir,
             output_dir,
             dry_run,
+            source,
+            flat,
+            zstd,
+            strict,
+            only,
+            skip,
         }
     }
+
+    /// Whether `--only` and `--skip` select dataset `kind`
+    pub fn selects(&self, kind: DatasetKind) -> bool {
+        (self.only.is_empty() || self.only.contains(&kind)) && !self.skip.contains(&kind)
+    }
+}
+
+fn dataset_kinds(matches: &ArgMatches, id: &str) -> Vec<DatasetKind> {
+    matches
+        .get_many::<String>(id)
+        .map(|names| {
+            names
+                .filter_map(|name| DatasetKind::from_name(name))
+                .collect()
+        })
+        .unwrap_or_default()
 }
@@ -0,0 +1,594 @@
+use crate::alloc;
+use crate::db::with_dataset;
+use anyhow::Result;
+use clap::ArgMatches;
+use ndb_as::AsDb;
+use ndb_core::bin::{self, DatasetKind};
+use ndb_core::{compress, NdbDataset};
+use ndb_country::CountryDb;
+use ndb_ipv4_asn::Ipv4AsnDb;
+use ndb_ipv4_country::Ipv4CountryDb;
+use ndb_ipv6_asn::Ipv6AsnDb;
+use ndb_ipv6_country::Ipv6CountryDb;
+use ndb_oui::OuiDb;
+use ndb_tcp_service::TcpServiceDb;
+use ndb_udp_service::UdpServiceDb;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// IPv4 blocks that are not routable on the public Internet (RFC 6890)
+const IPV4_RESERVED: [(u32, u8); 14] = [
+    (0x0000_0000, 8),  // 0.0.0.0/8
+    (0x0A00_0000, 8),  // 10.0.0.0/8
+    (0x6440_0000, 10), // 100.64.0.0/10
+    (0x7F00_0000, 8),  // 127.0.0.0/8
+    (0xA9FE_0000, 16), // 169.254.0.0/16
+    (0xAC10_0000, 12), // 172.16.0.0/12
+    (0xC000_0000, 24), // 192.0.0.0/24
+    (0xC000_0200, 24), // 192.0.2.0/24
+    (0xC0A8_0000, 16), // 192.168.0.0/16
+    (0xC612_0000, 15), // 198.18.0.0/15
+    (0xC633_6400, 24), // 198.51.100.0/24
+    (0xCB00_7100, 24), // 203.0.113.0/24
+    (0xE000_0000, 4),  // 224.0.0.0/4
+    (0xF000_0000, 4),  // 240.0.0.0/4
+];
+
+/// IPv6 global unicast space (2000::/3)
+const IPV6_GLOBAL_UNICAST: (u128, u128) = (0x2000 << 112, (0x4000 << 112) - 1);
+
+/// Output format of the stats report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatsFormat {
+    Text,
+    Json,
+}
+
+impl StatsFormat {
+    pub const NAMES: [&'static str; 2] = ["text", "json"];
+
+    pub fn from_str(s: &str) -> StatsFormat {
+        match s {
+            "json" => StatsFormat::Json,
+            _ => StatsFormat::Text,
+        }
+    }
+}
+
+pub struct StatsConfig {
+    pub kinds: Vec<DatasetKind>,
+    pub data_dir: Option<PathBuf>,
+    /// Number of top countries or ASNs to list
+    pub top: usize,
+    pub format: StatsFormat,
+}
+
+impl StatsConfig {
+    pub fn from_cli_arg(matches: &ArgMatches) -> Self {
+        let kinds = match matches.get_many::<String>("dataset") {
+            Some(names) => names
+                .filter_map(|name| DatasetKind::from_name(name))
+                .collect(),
+            None => DatasetKind::ALL.to_vec(),
+        };
+        let data_dir = matches.get_one::<PathBuf>("data-dir").cloned();
+        let top = matches.get_one::<usize>("top").copied().unwrap_or(10);
+        let format = matches
+            .get_one::<String>("format")
+            .map(|s| StatsFormat::from_str(s))
+            .unwrap_or(StatsFormat::Text);
+        StatsConfig {
+            kinds,
+            data_dir,
+            top,
+            format,
+        }
+    }
+}
+
+/// Statistics of a single dataset
+#[derive(Debug, Serialize)]
+pub struct DatasetStats {
+    pub dataset: &'static str,
+    /// BIN file path, or `bundled`
+    pub source: String,
+    /// Size of the BIN data in bytes (compressed size for zstd data)
+    pub bytes: usize,
+    pub compressed: bool,
+    pub schema_version: Option<u16>,
+    /// Build time recorded in the header, in seconds since the Unix epoch
+    pub built_at: Option<u64>,
+    /// Source recorded in the header
+    pub built_from: Option<String>,
+    pub entries: usize,
+    pub decode_ms: f64,
+    /// Heap bytes held by the loaded database (only measured with the `heap-stats` feature)
+    pub heap_bytes: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coverage: Option<Coverage>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub top: Vec<TopEntry>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub block_sizes: Vec<BlockSize>,
+}
+
+/// Address space covered by an IP range dataset
+#[derive(Debug, Serialize)]
+pub struct Coverage {
+    /// Number of addresses mapped by the dataset
+    pub addresses: u128,
+    /// Number of routable addresses (public IPv4 space, or 2000::/3 for IPv6)
+    pub routable: u128,
+    /// Share of the routable addresses that are mapped, in percent
+    pub routable_percent: f64,
+}
+
+/// Country or ASN ranked by the number of addresses mapped to it
+#[derive(Debug, Serialize)]
+pub struct TopEntry {
+    /// `AS<number>` or country code
+    pub key: String,
+    pub name: Option<String>,
+    pub addresses: u128,
+    /// Share of the mapped addresses, in percent
+    pub percent: f64,
+}
+
+/// Number of OUI assignments of a block size
+#[derive(Debug, Serialize)]
+pub struct BlockSize {
+    /// `MA-L`, `MA-M`, `MA-S` or `other`
+    pub block: &'static str,
+    pub prefix_len: Option<u8>,
+    pub count: usize,
+}
+
+/// AS and country names used to label the top lists
+#[derive(Default)]
+struct Names {
+    as_db: Option<AsDb>,
+    country: Option<CountryDb>,
+}
+
+/// Dataset specific statistics
+trait Inspect: NdbDataset {
+    fn inspect(&self, _stats: &mut DatasetStats, _top: usize, _names: &Names) {}
+}
+
+impl Inspect for AsDb {}
+impl Inspect for CountryDb {}
+impl Inspect for TcpServiceDb {}
+impl Inspect for UdpServiceDb {}
+
+impl Inspect for Ipv4AsnDb {
+    fn inspect(&self, stats: &mut DatasetStats, top: usize, names: &Names) {
+        let ranges = self
+            .all()
+            .map(|e| ((u128::from(e.ip_from), u128::from(e.ip_to)), e.asn));
+        let (coverage, counts) = ipv4_coverage(ranges);
+        stats.coverage = Some(coverage);
+        stats.top = top_asns(counts, top, names);
+    }
+}
+
+impl Inspect for Ipv6AsnDb {
+    fn inspect(&self, stats: &mut DatasetStats, top: usize, names: &Names) {
+        let ranges = self.all().map(|e| ((e.ip_from, e.ip_to), e.asn));
+        let (coverage, counts) = ipv6_coverage(ranges);
+        stats.coverage = Some(coverage);
+        stats.top = top_asns(counts, top, names);
+    }
+}
+
+impl Inspect for Ipv4CountryDb {
+    fn inspect(&self, stats: &mut DatasetStats, top: usize, names: &Names) {
+        let ranges = self.all().map(|e| {
+            let bounds = (u128::from(e.ip_from), u128::from(e.ip_to));
+            (bounds, e.country_code)
+        });
+        let (coverage, counts) = ipv4_coverage(ranges);
+        stats.coverage = Some(coverage);
+        stats.top = top_countries(counts, top, names);
+    }
+}
+
+impl Inspect for Ipv6CountryDb {
+    fn inspect(&self, stats: &mut DatasetStats, top: usize, names: &Names) {
+        let ranges = self.all().map(|e| ((e.ip_from, e.ip_to), e.country_code));
+        let (coverage, counts) = ipv6_coverage(ranges);
+        stats.coverage = Some(coverage);
+        stats.top = top_countries(counts, top, names);
+    }
+}
+
+impl Inspect for OuiDb {
+    fn inspect(&self, stats: &mut DatasetStats, _top: usize, _names: &Names) {
+        let mut counts: HashMap<(Option<u8>, &'static str), usize> = HashMap::new();
+        for (prefix, entry) in self.all() {
+            let prefix_len = match prefix.split_once('/') {
+                Some((_, bits)) => bits.parse().ok(),
+                // Plain 3-octet prefixes are MA-L assignments
+                None => Some(24),
+            };
+            // Prefer the recorded registry, which tells IAB and MA-S blocks apart
+            let block = match (entry.registry, prefix_len) {
+                (Some(registry), _) => registry.name(),
+                (None, Some(24)) => "MA-L",
+                (None, Some(28)) => "MA-M",
+                (None, Some(36)) => "MA-S",
+                _ => "other",
+            };
+            *counts.entry((prefix_len, block)).or_default() += 1;
+        }
+        let mut block_sizes: Vec<BlockSize> = counts
+            .into_iter()
+            .map(|((prefix_len, block), count)| BlockSize {
+                block,
+                prefix_len,
+                count,
+            })
+            .collect();
+        block_sizes.sort_by_key(|size| (size.prefix_len.is_none(), size.prefix_len, size.block));
+        stats.block_sizes = block_sizes;
+    }
+}
+
+pub fn stats(config: StatsConfig) -> Result<()> {
+    let ip_kinds = [
+        DatasetKind::Ipv4Asn,
+        DatasetKind::Ipv6Asn,
+        DatasetKind::Ipv4Country,
+        DatasetKind::Ipv6Country,
+    ];
+    let names = if config.kinds.iter().any(|kind| ip_kinds.contains(kind)) {
+        load_names(config.data_dir.as_deref())
+    } else {
+        Names::default()
+    };
+
+    let mut all_stats = Vec::new();
+    for &kind in &config.kinds {
+        match with_dataset!(kind, dataset_stats, &config, &names)? {
+            Some(stats) => all_stats.push(stats),
+            None => tracing::warn!("No {} database found", kind),
+        }
+    }
+
+    let mut out = io::stdout().lock();
+    match config.format {
+        StatsFormat::Text => {
+            for stats in &all_stats {
+                write_text(&mut out, stats)?;
+            }
+        }
+        StatsFormat::Json => {
+            serde_json::to_writer_pretty(&mut out, &all_stats)?;
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+fn load_names(data_dir: Option<&Path>) -> Names {
+    let as_db = crate::db::load_db::<AsDb>(data_dir);
+    let country = crate::db::load_db::<CountryDb>(data_dir);
+    for err in [as_db.as_ref().err(), country.as_ref().err()]
+        .into_iter()
+        .flatten()
+    {
+        tracing::debug!("Top lists will not be labeled: {}", err);
+    }
+    Names {
+        as_db: as_db.ok(),
+        country: country.ok(),
+    }
+}
+
+/// Read the BIN data of `D` from `data_dir` or the bundled data
+fn read_bin<D: NdbDataset>(
+    data_dir: Option<&Path>,
+) -> Result<Option<(String, Cow<'static, [u8]>)>> {
+    if let Some(dir) = data_dir {
+        let path = dir.join(D::BIN_NAME);
+        if path.exists() {
+            let bytes = std::fs::read(&path)
+                .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+            return Ok(Some((path.display().to_string(), Cow::Owned(bytes))));
+        }
+    }
+    Ok(D::bundled_bin().map(|bytes| ("bundled".to_string(), Cow::Borrowed(bytes))))
+}
+
+fn dataset_stats<D: Inspect>(config: &StatsConfig, names: &Names) -> Result<Option<DatasetStats>> {
+    let Some((source, bytes)) = read_bin::<D>(config.data_dir.as_deref())? else {
+        return Ok(None);
+    };
+    let compressed = compress::is_zstd(&bytes);
+    let header = if compressed {
+        let data = compress::decompress(&bytes)?;
+        bin::decode_header(&data)?.0
+    } else {
+        bin::decode_header(&bytes)?.0
+    };
+
+    let heap_before = alloc::allocated();
+    let started = Instant::now();
+    let db = D::from_slice(&bytes)
+        .map_err(|e| anyhow::anyhow!("Failed to load {} from {}: {}", D::KIND, source, e))?;
+    let decode_ms = started.elapsed().as_secs_f64() * 1000.0;
+    let heap_bytes = heap_before
+        .zip(alloc::allocated())
+        .map(|(before, after)| after.saturating_sub(before));
+
+    let mut stats = DatasetStats {
+        dataset: D::KIND.name(),
+        source,
+        bytes: bytes.len(),
+        compressed,
+        schema_version: header.as_ref().map(|h| h.schema_version),
+        built_at: header.as_ref().map(|h| h.built_at),
+        built_from: header.map(|h| h.source),
+        entries: db.entries().len(),
+        decode_ms,
+        heap_bytes,
+        coverage: None,
+        top: Vec::new(),
+        block_sizes: Vec::new(),
+    };
+    db.inspect(&mut stats, config.top, names);
+    Ok(Some(stats))
+}
+
+/// Number of addresses in `a` that are also in `b`
+fn overlap(a: (u128, u128), b: (u128, u128)) -> u128 {
+    let start = a.0.max(b.0);
+    let end = a.1.min(b.1);
+    if start > end {
+        0
+    } else {
+        (end - start).saturating_add(1)
+    }
+}
+
+fn range_len(bounds: (u128, u128)) -> u128 {
+    (bounds.1 - bounds.0).saturating_add(1)
+}
+
+fn ipv4_coverage<K, I>(ranges: I) -> (Coverage, HashMap<K, u128>)
+where
+    K: Eq + Hash,
+    I: Iterator<Item = ((u128, u128), K)>,
+{
+    let reserved: Vec<(u128, u128)> = IPV4_RESERVED
+        .iter()
+        .map(|&(start, prefix_len)| {
+            let start = u128::from(start);
+            (start, start + (1u128 << (32 - prefix_len)) - 1)
+        })
+        .collect();
+    let routable = (1u128 << 32) - reserved.iter().map(|&block| range_len(block)).sum::<u128>();
+    coverage(ranges, routable, |bounds| {
+        range_len(bounds)
+            - reserved
+                .iter()
+                .map(|&block| overlap(bounds, block))
+                .sum::<u128>()
+    })
+}
+
+fn ipv6_coverage<K, I>(ranges: I) -> (Coverage, HashMap<K, u128>)
+where
+    K: Eq + Hash,
+    I: Iterator<Item = ((u128, u128), K)>,
+{
+    coverage(ranges, range_len(IPV6_GLOBAL_UNICAST), |bounds| {
+        overlap(bounds, IPV6_GLOBAL_UNICAST)
+    })
+}
+
+/// Sum the addresses of `ranges` in total, within the routable space and per key
+fn coverage<K, I, F>(ranges: I, routable: u128, routable_len: F) -> (Coverage, HashMap<K, u128>)
+where
+    K: Eq + Hash,
+    I: Iterator<Item = ((u128, u128), K)>,
+    F: Fn((u128, u128)) -> u128,
+{
+    let mut addresses: u128 = 0;
+    let mut routable_addresses: u128 = 0;
+    let mut counts: HashMap<K, u128> = HashMap::new();
+    for (bounds, key) in ranges {
+        let len = range_len(bounds);
+        addresses = addresses.saturating_add(len);
+        routable_addresses = routable_addresses.saturating_add(routable_len(bounds));
+        let count = counts.entry(key).or_default();
+        *count = count.saturating_add(len);
+    }
+    let coverage = Coverage {
+        addresses,
+        routable,
+        routable_percent: percent(routable_addresses, routable),
+    };
+    (coverage, counts)
+}
+
+fn percent(part: u128, total: u128) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 / total as f64 * 100.0
+    }
+}
+
+/// Rank `counts` by address count, largest first
+fn ranked<K: Ord>(counts: HashMap<K, u128>, top: usize) -> Vec<(K, u128, f64)> {
+    let total = counts.values().fold(0u128, |sum, &n| sum.saturating_add(n));
+    let mut counts: Vec<(K, u128)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+        .into_iter()
+        .take(top)
+        .map(|(key, n)| (key, n, percent(n, total)))
+        .collect()
+}
+
+fn top_asns(counts: HashMap<u32, u128>, top: usize, names: &Names) -> Vec<TopEntry> {
+    ranked(counts, top)
+        .into_iter()
+        .map(|(asn, addresses, percent)| TopEntry {
+            key: format!("AS{}", asn),
+            name: names
+                .as_db
+                .as_ref()
+                .and_then(|db| db.get_name(asn))
+                .map(str::to_string),
+            addresses,
+            percent,
+        })
+        .collect()
+}
+
+fn top_countries(counts: HashMap<String, u128>, top: usize, names: &Names) -> Vec<TopEntry> {
+    ranked(counts, top)
+        .into_iter()
+        .map(|(code, addresses, percent)| TopEntry {
+            name: names
+                .country
+                .as_ref()
+                .and_then(|db| db.get_name(&code))
+                .map(str::to_string),
+            key: code,
+            addresses,
+            percent,
+        })
+        .collect()
+}
+
+/// Format a byte count with a binary unit (e.g. `3.08 MiB`)
+fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.2} {}", value, UNITS[unit])
+    }
+}
+
+fn write_text<W: Write>(out: &mut W, stats: &DatasetStats) -> Result<()> {
+    writeln!(out, "{} ({})", stats.dataset, stats.source)?;
+    let compressed = if stats.compressed { ", zstd" } else { "" };
+    writeln!(
+        out,
+        "  size:        {} ({} bytes{})",
+        format_bytes(stats.bytes),
+        stats.bytes,
+        compressed
+    )?;
+    if let (Some(version), Some(built_at)) = (stats.schema_version, stats.built_at) {
+        let built_from = stats.built_from.as_deref().unwrap_or("-");
+        writeln!(
+            out,
+            "  header:      schema v{}, built at {} from {}",
+            version, built_at, built_from
+        )?;
+    } else {
+        writeln!(out, "  header:      none (legacy file)")?;
+    }
+    writeln!(out, "  entries:     {}", stats.entries)?;
+    writeln!(out, "  decode time: {:.1} ms", stats.decode_ms)?;
+    match stats.heap_bytes {
+        Some(heap_bytes) => writeln!(out, "  memory:      {}", format_bytes(heap_bytes))?,
+        None => writeln!(
+            out,
+            "  memory:      n/a (build with the `heap-stats` feature)"
+        )?,
+    }
+    if let Some(coverage) = &stats.coverage {
+        writeln!(
+            out,
+            "  coverage:    {} addresses ({:.2}% of routable space)",
+            coverage.addresses, coverage.routable_percent
+        )?;
+    }
+    if !stats.top.is_empty() {
+        writeln!(out, "  top by address count:")?;
+        let width = stats
+            .top
+            .iter()
+            .map(|entry| entry.addresses.to_string().len())
+            .max()
+            .unwrap_or(0);
+        for (rank, entry) in stats.top.iter().enumerate() {
+            writeln!(
+                out,
+                "    {:>2}. {:<10} {:>width$} {:>6.2}%  {}",
+                rank + 1,
+                entry.key,
+                entry.addresses,
+                entry.percent,
+                entry.name.as_deref().unwrap_or("-"),
+                width = width
+            )?;
+        }
+    }
+    if !stats.block_sizes.is_empty() {
+        writeln!(out, "  block sizes:")?;
+        for size in &stats.block_sizes {
+            let prefix_len = size
+                .prefix_len
+                .map(|len| format!("/{}", len))
+                .unwrap_or_else(|| "-".to_string());
+            writeln!(
+                out,
+                "    {:<6} {:<4} {}",
+                size.block, prefix_len, size.count
+            )?;
+        }
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ipv4_coverage() {
+        // 8.8.8.0/24 and 10.0.0.0/24, which is private
+        let ranges = vec![
+            ((0x0808_0800, 0x0808_08FF), 15169),
+            ((0x0A00_0000, 0x0A00_00FF), 64500),
+        ];
+        let (coverage, counts) = ipv4_coverage(ranges.into_iter());
+        assert_eq!(coverage.addresses, 512);
+        assert_eq!(coverage.routable, 3_702_258_688);
+        assert!((coverage.routable_percent - percent(256, 3_702_258_688)).abs() < 1e-12);
+        assert_eq!(counts[&15169], 256);
+    }
+
+    #[test]
+    fn test_ranked() {
+        let counts = HashMap::from([("JP", 10), ("US", 30), ("DE", 10)]);
+        let ranked = ranked(counts, 2);
+        assert_eq!(ranked.len(), 2);
+        assert_eq!((ranked[0].0, ranked[0].1), ("US", 30));
+        assert_eq!((ranked[1].0, ranked[1].2), ("DE", 20.0));
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(3 * 1024 * 1024 + 1024 * 80), "3.08 MiB");
+    }
+}
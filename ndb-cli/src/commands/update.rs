@@ -1,141 +1,465 @@
 use crate::commands::AppConfig;
+use crate::db::{csv_name, with_dataset};
 use anyhow::Result;
+use ndb_core::bin::DatasetKind;
+use ndb_core::compress;
+use ndb_core::manifest::{Manifest, ManifestEntry, MANIFEST_NAME};
+use ndb_core::service;
+use ndb_core::validate::ValidationReport;
+use ndb_core::{Error, NdbDataset};
+use ndb_tcp_service::TcpServiceDb;
+use ndb_udp_service::UdpServiceDb;
+use serde::Serialize;
 use std::fs::{self, File};
-use std::path::PathBuf;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Number of validation issues logged per dataset before they are summarized
+const MAX_LOGGED_ISSUES: usize = 20;
 
 pub fn update_bin_db(config: AppConfig) -> Result<()> {
-    // Enumrate through the input directory and process files
-    for entry in config.input_dir.read_dir()? {
-        let entry = entry?;
-        match entry.file_name().to_str().unwrap_or_default() {
-            ndb_as::CSV_NAME => {
-                // Process AS CSV file
-                tracing::info!("Processing AS file: {}", entry.path().display());
-                let file = File::open(entry.path())
-                    .map_err(|e| anyhow::anyhow!("Failed to open AS CSV file: {}", e))?;
-                let db = ndb_as::AsDb::from_csv(file)
-                    .map_err(|e| anyhow::anyhow!("Failed to process AS CSV: {}", e))?;
-                let bin_path = config.output_dir.join(ndb_as::BIN_NAME);
-                save_bin(db.entries(), bin_path, config.dry_run)?;
-                tracing::info!("AS database updated successfully.");
-            }
-            ndb_country::CSV_NAME => {
-                // Process Country CSV file
-                tracing::info!("Processing Country file: {}", entry.path().display());
-                let file = File::open(entry.path())
-                    .map_err(|e| anyhow::anyhow!("Failed to open Country CSV file: {}", e))?;
-                let db = ndb_country::CountryDb::from_csv(file)
-                    .map_err(|e| anyhow::anyhow!("Failed to process Country CSV: {}", e))?;
-                let bin_path = config.output_dir.join(ndb_country::BIN_NAME);
-                save_bin(db.entries(), bin_path, config.dry_run)?;
-                tracing::info!("Country database updated successfully.");
-            }
-            ndb_ipv4_asn::CSV_NAME => {
-                // Process IPv4 ASN CSV file
-                tracing::info!("Processing IPv4 ASN file: {}", entry.path().display());
-                let file = File::open(entry.path())
-                    .map_err(|e| anyhow::anyhow!("Failed to open IPv4 ASN CSV file: {}", e))?;
-                let db = ndb_ipv4_asn::Ipv4AsnDb::from_csv(file)
-                    .map_err(|e| anyhow::anyhow!("Failed to process IPv4 ASN CSV: {}", e))?;
-                let bin_path = config.output_dir.join(ndb_ipv4_asn::BIN_NAME);
-                save_bin(db.entries(), bin_path, config.dry_run)?;
-                tracing::info!("IPv4 ASN database updated successfully.");
+    let manifest_path = config.output_dir.join(MANIFEST_NAME);
+    // Keep the records of datasets that are not rebuilt this time
+    let mut manifest = if manifest_path.exists() {
+        Manifest::from_path(&manifest_path).unwrap_or_else(|e| {
+            tracing::warn!("Ignoring invalid {}: {}", manifest_path.display(), e);
+            Manifest::default()
+        })
+    } else {
+        Manifest::default()
+    };
+    // Enumrate through the input directory and process files in name order
+    let mut paths = config
+        .input_dir
+        .read_dir()?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    paths.sort();
+    // Group the input files by dataset. OUI can be built from several IEEE registry
+    // exports, and the IANA registry and nmap-services feed both service datasets.
+    let mut inputs: Vec<(DatasetKind, Vec<PathBuf>)> = Vec::new();
+    for path in paths {
+        let kinds = datasets_of(&file_name_of(&path));
+        if kinds.is_empty() {
+            tracing::warn!("Skipping unknown file: {}", path.display());
+        }
+        for kind in kinds {
+            if !config.selects(kind) {
+                tracing::debug!("Skipping {} for {} (not selected)", path.display(), kind);
+                continue;
             }
-            ndb_ipv4_country::CSV_NAME => {
-                // Process IPv4 Country CSV file
-                tracing::info!("Processing IPv4 Country file: {}", entry.path().display());
-                let file = File::open(entry.path())
-                    .map_err(|e| anyhow::anyhow!("Failed to open IPv4 Country CSV file: {}", e))?;
-                let db = ndb_ipv4_country::Ipv4CountryDb::from_csv(file)
-                    .map_err(|e| anyhow::anyhow!("Failed to process IPv4 Country CSV: {}", e))?;
-                let bin_path = config.output_dir.join(ndb_ipv4_country::BIN_NAME);
-                save_bin(db.entries(), bin_path, config.dry_run)?;
-                tracing::info!("IPv4 Country database updated successfully.");
+            match inputs.iter_mut().find(|(k, _)| *k == kind) {
+                Some((_, paths)) => paths.push(path.clone()),
+                None => inputs.push((kind, vec![path.clone()])),
             }
-            ndb_ipv6_asn::CSV_NAME => {
-                // Process IPv6 ASN CSV file
-                tracing::info!("Processing IPv6 ASN file: {}", entry.path().display());
-                let file = File::open(entry.path())
-                    .map_err(|e| anyhow::anyhow!("Failed to open IPv6 ASN CSV file: {}", e))?;
-                let db = ndb_ipv6_asn::Ipv6AsnDb::from_csv(file)
-                    .map_err(|e| anyhow::anyhow!("Failed to process IPv6 ASN CSV: {}", e))?;
-                let bin_path = config.output_dir.join(ndb_ipv6_asn::BIN_NAME);
-                save_bin(db.entries(), bin_path, config.dry_run)?;
-                tracing::info!("IPv6 ASN database updated successfully.");
+        }
+    }
+    let mut built = Vec::new();
+    let mut failures = Vec::new();
+    for (kind, paths) in inputs {
+        let file_names: Vec<String> = paths
+            .iter()
+            .filter_map(|path| path.file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .collect();
+        let source = match &config.source {
+            Some(source) => source.clone(),
+            None => file_names.join(","),
+        };
+        let result = read_input(kind, &paths)
+            .and_then(|input| with_dataset!(kind, update_dataset, &input, &source, &config));
+        match result {
+            Ok(record) => {
+                manifest.insert(record);
+                built.push(kind);
             }
-            ndb_ipv6_country::CSV_NAME => {
-                // Process IPv6 Country CSV file
-                tracing::info!("Processing IPv6 Country file: {}", entry.path().display());
-                let file = File::open(entry.path())
-                    .map_err(|e| anyhow::anyhow!("Failed to open IPv6 Country CSV file: {}", e))?;
-                let db = ndb_ipv6_country::Ipv6CountryDb::from_csv(file)
-                    .map_err(|e| anyhow::anyhow!("Failed to process IPv6 Country CSV: {}", e))?;
-                let bin_path = config.output_dir.join(ndb_ipv6_country::BIN_NAME);
-                save_bin(db.entries(), bin_path, config.dry_run)?;
-                tracing::info!("IPv6 Country database updated successfully.");
+            // Keep going so that one bad CSV does not block the other datasets
+            Err(e) => {
+                tracing::error!("{}", e);
+                failures.push((kind, e));
             }
-            ndb_oui::CSV_NAME => {
-                // Process OUI CSV file
-                tracing::info!("Processing OUI file: {}", entry.path().display());
-                let file = File::open(entry.path())
-                    .map_err(|e| anyhow::anyhow!("Failed to open OUI CSV file: {}", e))?;
-                let db = ndb_oui::OuiDb::from_csv(file)
-                    .map_err(|e| anyhow::anyhow!("Failed to process OUI CSV: {}", e))?;
-                let bin_path = config.output_dir.join(ndb_oui::BIN_NAME);
-                save_bin(db.entries(), bin_path, config.dry_run)?;
+        }
+    }
+    for kind in &config.only {
+        if !built.contains(kind) && !failures.iter().any(|(failed, _)| failed == kind) {
+            tracing::warn!("No {} CSV file in {}", kind, config.input_dir.display());
+        }
+    }
+    if config.dry_run {
+        tracing::info!("[dry-run] Would write {}", manifest_path.display());
+    } else if !built.is_empty() {
+        let json = manifest
+            .to_vec()
+            .map_err(|e| anyhow::anyhow!("Failed to serialize manifest: {}", e))?;
+        write_file(&manifest_path, &json)?;
+    }
+    if failures.is_empty() {
+        tracing::info!("Updated {} datasets", built.len());
+        return Ok(());
+    }
+    tracing::error!(
+        "Updated {} datasets, {} failed:",
+        built.len(),
+        failures.len()
+    );
+    for (kind, e) in &failures {
+        tracing::error!("  {}: {}", kind, e);
+    }
+    anyhow::bail!(
+        "Failed to update {}",
+        failures
+            .iter()
+            .map(|(kind, _)| kind.name())
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+
+/// CSV data of a dataset read from one or more input files
+struct Input {
+    /// CSV data in the format of the dataset
+    csv: Vec<u8>,
+    /// Contents of the input files, used for the source checksum
+    raw: Vec<u8>,
+}
+
+/// Datasets built from the input file `file_name`
+fn datasets_of(file_name: &str) -> Vec<DatasetKind> {
+    if let Some(kind) = DatasetKind::ALL
+        .into_iter()
+        .find(|&kind| with_dataset!(kind, csv_name) == file_name)
+    {
+        return vec![kind];
+    }
+    if ndb_oui::IEEE_CSV_NAMES.contains(&file_name) || file_name == ndb_oui::MANUF_NAME {
+        return vec![DatasetKind::Oui];
+    }
+    if file_name == service::IANA_CSV_NAME || is_service_layer(file_name) {
+        return vec![DatasetKind::TcpService, DatasetKind::UdpService];
+    }
+    Vec::new()
+}
+
+/// Read the input files of dataset `kind`, converting files in other formats
+/// to the CSV format of the dataset and merging multiple files
+fn read_input(kind: DatasetKind, paths: &[PathBuf]) -> Result<Input> {
+    // Local `services` and nmap-services files are layered on top of the other service
+    // files. Without any, the first of them is the base.
+    let (mut layers, mut base): (Vec<&PathBuf>, Vec<&PathBuf>) = paths
+        .iter()
+        .partition(|path| is_service_layer(&file_name_of(path)));
+    if base.is_empty() && !layers.is_empty() {
+        base.push(layers.remove(0));
+    }
+    let mut raw = Vec::new();
+    let mut csvs = Vec::new();
+    for path in base {
+        let data = read_file(kind, path)?;
+        raw.extend_from_slice(&data);
+        let csv = convert_file(kind, path, &data)
+            .map_err(|e| anyhow::anyhow!("Failed to process {}: {}", path.display(), e))?;
+        csvs.push(csv.unwrap_or(data));
+    }
+    let mut csv = match csvs.len() {
+        1 => csvs.pop().unwrap_or_default(),
+        _ => with_dataset!(kind, merge_csv, &csvs)
+            .map_err(|e| anyhow::anyhow!("Failed to merge {} input files: {}", kind, e))?,
+    };
+    for path in layers {
+        let data = read_file(kind, path)?;
+        raw.extend_from_slice(&data);
+        csv = apply_layer(kind, &csv, path, &data)
+            .map_err(|e| anyhow::anyhow!("Failed to process {}: {}", path.display(), e))?;
+    }
+    Ok(Input { csv, raw })
+}
+
+/// Read an input file of dataset `kind`
+fn read_file(kind: DatasetKind, path: &Path) -> Result<Vec<u8>> {
+    tracing::info!("Processing {} file: {}", kind, path.display());
+    fs::read(path).map_err(|e| anyhow::anyhow!("Failed to read {} CSV file: {}", kind, e))
+}
+
+/// Whether `file_name` is a service file layered on top of the others
+fn is_service_layer(file_name: &str) -> bool {
+    file_name == service::ETC_SERVICES_NAME || file_name == service::NMAP_SERVICES_NAME
+}
+
+/// File name of `path` as a string
+fn file_name_of(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Convert an input file that is not in the CSV format of dataset `kind`:
+/// IEEE registry exports (MA-L, MA-M, MA-S and IAB) and the Wireshark `manuf` file
+/// for OUI, and the IANA registry, nmap-services and `/etc/services` for the service datasets
+fn convert_file(kind: DatasetKind, path: &Path, data: &[u8]) -> Result<Option<Vec<u8>>> {
+    let file_name = file_name_of(path);
+    let csv = match kind {
+        DatasetKind::Oui if file_name == ndb_oui::MANUF_NAME => to_csv(ndb_oui::read_manuf(data)?)?,
+        DatasetKind::Oui if ndb_oui::is_ieee_csv(data) => to_csv(ndb_oui::read_ieee_csv(data)?)?,
+        DatasetKind::TcpService if file_name == service::IANA_CSV_NAME => {
+            to_csv(ndb_tcp_service::read_iana_csv(data)?)?
+        }
+        DatasetKind::UdpService if file_name == service::IANA_CSV_NAME => {
+            to_csv(ndb_udp_service::read_iana_csv(data)?)?
+        }
+        DatasetKind::TcpService if file_name == service::NMAP_SERVICES_NAME => {
+            to_csv(TcpServiceDb::from_nmap_services(data)?.entries())?
+        }
+        DatasetKind::UdpService if file_name == service::NMAP_SERVICES_NAME => {
+            to_csv(UdpServiceDb::from_nmap_services(data)?.entries())?
+        }
+        DatasetKind::TcpService if file_name == service::ETC_SERVICES_NAME => {
+            to_csv(ndb_tcp_service::read_etc_services(data)?)?
+        }
+        DatasetKind::UdpService if file_name == service::ETC_SERVICES_NAME => {
+            to_csv(ndb_udp_service::read_etc_services(data)?)?
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(csv))
+}
+
+/// Layer a service file on top of the service CSV data `csv`: an `nmap-services` file
+/// adds port frequencies and derives `common` from them, and a local `services` file
+/// overrides the entries of its ports
+fn apply_layer(kind: DatasetKind, csv: &[u8], path: &Path, data: &[u8]) -> Result<Vec<u8>> {
+    let nmap = file_name_of(path) == service::NMAP_SERVICES_NAME;
+    match kind {
+        DatasetKind::TcpService => {
+            let mut db = TcpServiceDb::from_csv(csv)?;
+            if nmap {
+                db.merge_frequencies(ndb_tcp_service::read_nmap_services(data)?);
+                db.derive_common(service::COMMON_FREQUENCY);
+            } else {
+                db.merge(TcpServiceDb::from_etc_services(data)?);
             }
-            ndb_tcp_service::CSV_NAME => {
-                // Process TCP Service CSV file
-                tracing::info!("Processing TCP Service file: {}", entry.path().display());
-                let file = File::open(entry.path())
-                    .map_err(|e| anyhow::anyhow!("Failed to open TCP Service CSV file: {}", e))?;
-                let db = ndb_tcp_service::TcpServiceDb::from_csv(file)
-                    .map_err(|e| anyhow::anyhow!("Failed to process TCP Service CSV: {}", e))?;
-                let bin_path = config.output_dir.join(ndb_tcp_service::BIN_NAME);
-                save_bin(db.entries(), bin_path, config.dry_run)?;
-                tracing::info!("TCP Service database updated successfully.");
+            to_csv(db.entries())
+        }
+        DatasetKind::UdpService => {
+            let mut db = UdpServiceDb::from_csv(csv)?;
+            if nmap {
+                db.merge_frequencies(ndb_udp_service::read_nmap_services(data)?);
+                db.derive_common(service::COMMON_FREQUENCY);
+            } else {
+                db.merge(UdpServiceDb::from_etc_services(data)?);
             }
-            ndb_udp_service::CSV_NAME => {
-                // Process UDP Service CSV file
-                tracing::info!("Processing UDP Service file: {}", entry.path().display());
-                let file = File::open(entry.path())
-                    .map_err(|e| anyhow::anyhow!("Failed to open UDP Service CSV file: {}", e))?;
-                let db = ndb_udp_service::UdpServiceDb::from_csv(file)
-                    .map_err(|e| anyhow::anyhow!("Failed to process UDP Service CSV: {}", e))?;
-                let bin_path = config.output_dir.join(ndb_udp_service::BIN_NAME);
-                save_bin(db.entries(), bin_path, config.dry_run)?;
-                tracing::info!("UDP Service database updated successfully.");
+            to_csv(db.entries())
+        }
+        _ => Ok(csv.to_vec()),
+    }
+}
+
+/// Serialize `entries` as CSV with a header
+fn to_csv<E: Serialize>(entries: Vec<E>) -> Result<Vec<u8>> {
+    let mut wtr = csv::Writer::from_writer(Vec::new());
+    for entry in entries {
+        wtr.serialize(entry)?;
+    }
+    wtr.into_inner()
+        .map_err(|e| anyhow::anyhow!("Failed to write CSV data: {}", e))
+}
+
+/// Concatenate the entries of several CSV files of dataset `D`
+fn merge_csv<D: NdbDataset>(csvs: &[Vec<u8>]) -> Result<Vec<u8>> {
+    let mut entries = Vec::new();
+    for csv in csvs {
+        for entry in csv::Reader::from_reader(csv.as_slice()).deserialize::<D::Entry>() {
+            entries.push(entry.map_err(ndb_core::Error::from)?);
+        }
+    }
+    to_csv(entries)
+}
+
+/// Build the BIN DB file of dataset `D` from its CSV data, returning its manifest record
+fn update_dataset<D: NdbDataset>(
+    input: &Input,
+    source: &str,
+    config: &AppConfig,
+) -> Result<ManifestEntry> {
+    let csv = &input.csv;
+    let db = match config.strict {
+        Some(mode) => match D::from_csv_validated(csv.as_slice(), mode) {
+            Ok((db, report)) => {
+                log_report::<D>(&report);
+                db
             }
-            _ => {
-                tracing::warn!("Skipping unknown file: {}", entry.path().display());
+            Err(Error::Validation(report)) => {
+                log_report::<D>(&report);
+                anyhow::bail!("Rejected {} CSV: {}", D::KIND, report);
             }
-        }
+            Err(e) => anyhow::bail!("Failed to process {} CSV: {}", D::KIND, e),
+        },
+        None => D::from_csv(csv.as_slice())
+            .map_err(|e| anyhow::anyhow!("Failed to process {} CSV: {}", D::KIND, e))?,
+    };
+    let bin_path = config.output_dir.join(D::BIN_NAME);
+    let bin = save_bin(&db, source, &bin_path, config.dry_run)?;
+    if config.zstd {
+        save_zst(&bin, &bin_path, config.dry_run)?;
     }
-    Ok(())
+    if let (true, Some(flat_name)) = (config.flat, D::FLAT_NAME) {
+        save_flat(&db, config.output_dir.join(flat_name), config.dry_run)?;
+    }
+    tracing::info!("{} database updated successfully.", D::KIND);
+    ManifestEntry::new(D::KIND, D::BIN_NAME, &bin, Some(&input.raw))
+        .map_err(|e| anyhow::anyhow!("Failed to create {} manifest record: {}", D::KIND, e))
+}
+
+/// Log the issues found by strict validation, followed by a summary
+fn log_report<D: NdbDataset>(report: &ValidationReport) {
+    for issue in report.issues.iter().take(MAX_LOGGED_ISSUES) {
+        tracing::warn!("{}: {}", D::KIND, issue);
+    }
+    if report.issues.len() > MAX_LOGGED_ISSUES {
+        tracing::warn!(
+            "{}: ... and {} more issues",
+            D::KIND,
+            report.issues.len() - MAX_LOGGED_ISSUES
+        );
+    }
+    tracing::info!("{} validation: {}", D::KIND, report);
 }
 
-pub fn save_bin<T: serde::Serialize>(value: T, file_path: PathBuf, dry_run: bool) -> Result<()> {
+/// Write the BIN file of `db`, returning the BIN file image
+pub fn save_bin<D: NdbDataset>(
+    db: &D,
+    source: &str,
+    file_path: &Path,
+    dry_run: bool,
+) -> Result<Vec<u8>> {
+    let bin = db
+        .to_bin(source)
+        .map_err(|e| anyhow::anyhow!("Failed to serialize data: {}", e))?;
     if dry_run {
-        let size = bincode::serde::encode_to_vec(&value, bincode::config::standard())?.len();
         tracing::info!(
             "[dry-run] Would serialize {} bytes to {}",
-            size,
+            bin.len(),
             file_path.display()
         );
+        return Ok(bin);
+    }
+    write_file(file_path, &bin)?;
+    Ok(bin)
+}
+
+/// Write the zstd compressed copy of the BIN file image `bin` next to `file_path`
+/// (e.g. `as.bin.zst`), as used by the `bundled-zstd` feature
+pub fn save_zst(bin: &[u8], file_path: &Path, dry_run: bool) -> Result<()> {
+    let zst =
+        compress::compress(bin).map_err(|e| anyhow::anyhow!("Failed to compress data: {}", e))?;
+    let mut zst_path = file_path.as_os_str().to_owned();
+    zst_path.push(compress::ZSTD_SUFFIX);
+    let zst_path = PathBuf::from(zst_path);
+    if dry_run {
+        tracing::info!(
+            "[dry-run] Would write {} bytes to {}",
+            zst.len(),
+            zst_path.display()
+        );
         return Ok(());
     }
-    if file_path.exists() {
-        fs::remove_file(&file_path)?;
+    write_file(&zst_path, &zst)
+}
+
+/// Write `data` to `file_path` atomically
+fn write_file(file_path: &Path, data: &[u8]) -> Result<()> {
+    write_atomic(file_path, |writer| Ok(writer.write_all(data)?))?;
+    tracing::debug!("Wrote {} bytes to {}", data.len(), file_path.display());
+    Ok(())
+}
+
+/// Write a file through a temporary file in the same directory that is then
+/// renamed over `file_path`, so readers never see a missing or truncated file
+fn write_atomic<F>(file_path: &Path, write: F) -> Result<()>
+where
+    F: FnOnce(&mut BufWriter<File>) -> Result<()>,
+{
+    let tmp_path = temp_path(file_path);
+    let result = write_and_rename(&tmp_path, file_path, write);
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result.map_err(|e| anyhow::anyhow!("Failed to write {}: {}", file_path.display(), e))
+}
+
+fn write_and_rename<F>(tmp_path: &Path, file_path: &Path, write: F) -> Result<()>
+where
+    F: FnOnce(&mut BufWriter<File>) -> Result<()>,
+{
+    let mut writer = BufWriter::new(File::create(tmp_path)?);
+    write(&mut writer)?;
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    fs::rename(tmp_path, file_path)?;
+    Ok(())
+}
+
+/// Hidden temporary file next to `file_path` (e.g. `.as.bin.1234.tmp`)
+fn temp_path(file_path: &Path) -> PathBuf {
+    let file_name = file_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    file_path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()))
+}
+
+/// Write the flat (memory-mappable) layout of dataset `D`
+pub fn save_flat<D: NdbDataset>(db: &D, file_path: PathBuf, dry_run: bool) -> Result<()> {
+    if dry_run {
+        if let Some(size) = db.write_flat(io::sink()) {
+            tracing::info!(
+                "[dry-run] Would write {} bytes to {}",
+                size?,
+                file_path.display()
+            );
+        }
+        return Ok(());
     }
-    let mut f: fs::File = fs::File::create(file_path.clone())?;
-    match bincode::serde::encode_into_std_write(value, &mut f, bincode::config::standard()) {
-        Ok(size) => {
-            f.sync_all()?;
-            let file_metadata = f.metadata()?;
-            tracing::debug!("Serialized {} bytes to {}", size, file_path.display());
-            tracing::debug!("File size: {} bytes", file_metadata.len());
+    write_atomic(&file_path, |writer| match db.write_flat(writer) {
+        Some(Ok(size)) => {
+            tracing::debug!("Wrote {} bytes to {}", size, file_path.display());
             Ok(())
         }
-        Err(e) => Err(anyhow::anyhow!("Failed to serialize data: {}", e)),
+        Some(Err(e)) => Err(anyhow::anyhow!("Failed to write flat database: {}", e)),
+        None => Ok(()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_file_replaces_atomically() {
+        let dir = std::env::temp_dir().join(format!("ndb-update-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("as.bin");
+        write_file(&path, b"old").unwrap();
+        write_file(&path, b"new data").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new data");
+        // Only the target file is left behind
+        let files: Vec<_> = fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(files.len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_atomic_keeps_old_file_on_error() {
+        let dir = std::env::temp_dir().join(format!("ndb-update-err-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("oui.bin");
+        write_file(&path, b"old").unwrap();
+        let result = write_atomic(&path, |writer| {
+            writer.write_all(b"partial")?;
+            Err(anyhow::anyhow!("interrupted"))
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"old");
+        assert!(!temp_path(&path).exists());
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
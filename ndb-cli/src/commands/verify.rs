@@ -0,0 +1,57 @@
+use crate::db::with_dataset;
+use anyhow::Result;
+use clap::ArgMatches;
+use ndb_core::bin::DatasetKind;
+use ndb_core::manifest::{Manifest, MANIFEST_NAME};
+use ndb_core::NdbDataset;
+use std::path::PathBuf;
+
+pub struct VerifyConfig {
+    /// Directory containing the BIN files and `manifest.json`
+    pub data_dir: PathBuf,
+}
+
+impl VerifyConfig {
+    pub fn from_cli_arg(matches: &ArgMatches) -> Self {
+        let data_dir = matches
+            .get_one::<PathBuf>("data-dir")
+            .cloned()
+            .unwrap_or_default();
+        VerifyConfig { data_dir }
+    }
+}
+
+/// Check every BIN file listed in `manifest.json` of the data directory,
+/// failing if any file is missing, truncated or corrupted
+pub fn verify(config: VerifyConfig) -> Result<()> {
+    let manifest_path = config.data_dir.join(MANIFEST_NAME);
+    let manifest = Manifest::from_path(&manifest_path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", manifest_path.display(), e))?;
+    let results = manifest.verify_dir(&config.data_dir);
+    let mut failed = 0;
+    for (entry, result) in &results {
+        match result {
+            Ok(()) => println!("OK      {} ({})", entry.file, entry.sha256),
+            Err(e) => {
+                failed += 1;
+                println!("FAILED  {}: {}", entry.file, e);
+            }
+        }
+    }
+    for kind in DatasetKind::ALL {
+        let bin_name = with_dataset!(kind, bin_name);
+        if manifest.get(kind).is_none() && config.data_dir.join(bin_name).exists() {
+            tracing::warn!("{} is not listed in {}", bin_name, MANIFEST_NAME);
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!("{} of {} files failed verification", failed, results.len());
+    }
+    tracing::info!("{} files verified", results.len());
+    Ok(())
+}
+
+/// BIN file name of dataset `D`
+fn bin_name<D: NdbDataset>() -> &'static str {
+    D::BIN_NAME
+}
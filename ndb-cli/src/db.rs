@@ -0,0 +1,66 @@
+use anyhow::Result;
+use ndb::{DataSource, NetDb};
+use ndb_core::NdbDataset;
+use std::path::Path;
+
+/// Load the IP databases from `data_dir`, falling back to the bundled
+/// datasets for any BIN file that is not present there.
+pub fn load_net_db(data_dir: Option<&Path>) -> Result<NetDb> {
+    let source = match data_dir {
+        Some(dir) => DataSource::DirOrBundled(dir.to_path_buf()),
+        None => DataSource::Bundled,
+    };
+    NetDb::load(&source).map_err(|e| anyhow::anyhow!("Failed to load IP databases: {}", e))
+}
+
+/// Call the generic function `$func::<D>($args)` with the database type `D` of a `DatasetKind`
+macro_rules! with_dataset {
+    ($kind:expr, $func:ident $(, $args:expr)* $(,)?) => {
+        match $kind {
+            ndb_core::bin::DatasetKind::As => $func::<ndb_as::AsDb>($($args),*),
+            ndb_core::bin::DatasetKind::Country => $func::<ndb_country::CountryDb>($($args),*),
+            ndb_core::bin::DatasetKind::Ipv4Asn => $func::<ndb_ipv4_asn::Ipv4AsnDb>($($args),*),
+            ndb_core::bin::DatasetKind::Ipv4Country => {
+                $func::<ndb_ipv4_country::Ipv4CountryDb>($($args),*)
+            }
+            ndb_core::bin::DatasetKind::Ipv6Asn => $func::<ndb_ipv6_asn::Ipv6AsnDb>($($args),*),
+            ndb_core::bin::DatasetKind::Ipv6Country => {
+                $func::<ndb_ipv6_country::Ipv6CountryDb>($($args),*)
+            }
+            ndb_core::bin::DatasetKind::Oui => $func::<ndb_oui::OuiDb>($($args),*),
+            ndb_core::bin::DatasetKind::TcpService => {
+                $func::<ndb_tcp_service::TcpServiceDb>($($args),*)
+            }
+            ndb_core::bin::DatasetKind::UdpService => {
+                $func::<ndb_udp_service::UdpServiceDb>($($args),*)
+            }
+        }
+    };
+}
+pub(crate) use with_dataset;
+
+/// CSV file name of dataset `D`
+pub fn csv_name<D: NdbDataset>() -> &'static str {
+    D::CSV_NAME
+}
+
+/// Load dataset `D` from `data_dir` if its BIN file exists, otherwise use the bundled one
+pub fn load_db<D: NdbDataset>(data_dir: Option<&Path>) -> Result<D> {
+    if let Some(dir) = data_dir {
+        let bin_path = dir.join(D::BIN_NAME);
+        if bin_path.exists() {
+            tracing::debug!("Loading {}", bin_path.display());
+            return D::from_path(&bin_path)
+                .map_err(|e| anyhow::anyhow!("Failed to load {}: {}", bin_path.display(), e));
+        }
+        tracing::debug!(
+            "{} not found in {}, using bundled data",
+            D::BIN_NAME,
+            dir.display()
+        );
+    }
+    match D::load_bundled() {
+        Some(db) => db.map_err(|e| anyhow::anyhow!("Failed to load bundled {}: {}", D::KIND, e)),
+        None => Err(anyhow::anyhow!("No bundled {} database available", D::KIND)),
+    }
+}
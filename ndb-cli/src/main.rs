@@ -1,15 +1,29 @@
+mod alloc;
 mod commands;
+mod db;
+mod output;
 
+use std::net::IpAddr;
 use std::path::PathBuf;
 
 use anyhow::Result;
 use clap::{crate_description, crate_name, crate_version, value_parser};
 use clap::{Arg, ArgMatches, Command};
+use commands::diff::DiffFormat;
+use commands::enrich::InputKind;
+use commands::export::ExportFormat;
+use commands::stats::StatsFormat;
 use commands::AppCommands;
+use ndb_core::bin::DatasetKind;
+use output::OutputFormat;
 use tracing::Level;
 use tracing_subscriber::fmt::time::ChronoLocal;
 use tracing_subscriber::FmtSubscriber;
 
+#[cfg(feature = "heap-stats")]
+#[global_allocator]
+static GLOBAL: alloc::CountingAlloc = alloc::CountingAlloc;
+
 fn main() -> Result<()> {
     // Parse command line arguments
     let args: ArgMatches = parse_args();
@@ -22,10 +36,47 @@ fn main() -> Result<()> {
     let app_command = AppCommands::from_str(subcommand_name);
 
     let sub_matches = args.subcommand_matches(subcommand_name).unwrap_or(&args);
-    let config = commands::AppConfig::from_cli_arg(&sub_matches);
 
     match app_command {
-        AppCommands::Update => commands::update::update_bin_db(config),
+        AppCommands::Update => {
+            let config = commands::AppConfig::from_cli_arg(sub_matches);
+            commands::update::update_bin_db(config)
+        }
+        AppCommands::Lookup => match sub_matches.subcommand() {
+            Some(("ip", ip_matches)) => {
+                let config = commands::lookup::LookupConfig::from_cli_arg(ip_matches);
+                commands::lookup::lookup_ip(config)
+            }
+            Some(("asn", asn_matches)) => {
+                let config = commands::lookup::AsnLookupConfig::from_cli_arg(asn_matches);
+                commands::lookup::lookup_asn(config)
+            }
+            Some(("country", country_matches)) => {
+                let config = commands::lookup::CountryLookupConfig::from_cli_arg(country_matches);
+                commands::lookup::lookup_country(config)
+            }
+            _ => Ok(()),
+        },
+        AppCommands::Enrich => {
+            let config = commands::enrich::EnrichConfig::from_cli_arg(sub_matches);
+            commands::enrich::enrich(config)
+        }
+        AppCommands::Export => {
+            let config = commands::export::ExportConfig::from_cli_arg(sub_matches);
+            commands::export::export(config)
+        }
+        AppCommands::Stats => {
+            let config = commands::stats::StatsConfig::from_cli_arg(sub_matches);
+            commands::stats::stats(config)
+        }
+        AppCommands::Diff => {
+            let config = commands::diff::DiffConfig::from_cli_arg(sub_matches);
+            commands::diff::diff(config)
+        }
+        AppCommands::Verify => {
+            let config = commands::verify::VerifyConfig::from_cli_arg(sub_matches);
+            commands::verify::verify(config)
+        }
         AppCommands::Default => {
             println!("Unknown command: {}", subcommand_name);
             println!("Use --help to see available commands.");
@@ -40,6 +91,7 @@ pub fn init_logger(verbose: bool) {
     let subscriber = FmtSubscriber::builder()
         .with_max_level(level)
         .with_target(false)
+        .with_writer(std::io::stderr)
         .with_timer(ChronoLocal::rfc_3339())
         .finish();
     tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
@@ -84,6 +136,388 @@ fn parse_args() -> ArgMatches {
                         .value_name("dir_path")
                         .value_parser(value_parser!(PathBuf))
                         .required(true),
+                )
+                .arg(
+                    Arg::new("source")
+                        .help("Source description recorded in the BIN headers (defaults to the CSV file name)")
+                        .long("source")
+                        .value_name("text")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("flat")
+                        .help("Also write flat memory-mappable files for the IP range datasets")
+                        .long("flat")
+                        .num_args(0)
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("zstd")
+                        .help("Also write zstd compressed copies of the BIN files (.bin.zst)")
+                        .long("zstd")
+                        .num_args(0)
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("strict")
+                        .help("Validate IP range CSV files: reject (default) or repair overlapping, inverted and duplicate ranges, non-ISO country codes and ASN 0")
+                        .long("strict")
+                        .value_name("mode")
+                        .value_parser(["reject", "repair"])
+                        .num_args(0..=1)
+                        .default_missing_value("reject")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("only")
+                        .help("Only build these datasets (comma separated, e.g. ipv4-asn,oui)")
+                        .long("only")
+                        .value_name("datasets")
+                        .value_parser(DatasetKind::ALL.map(|kind| kind.name()))
+                        .value_delimiter(',')
+                        .num_args(1..)
+                        .conflicts_with("skip")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("skip")
+                        .help("Do not build these datasets (comma separated)")
+                        .long("skip")
+                        .value_name("datasets")
+                        .value_parser(DatasetKind::ALL.map(|kind| kind.name()))
+                        .value_delimiter(',')
+                        .num_args(1..)
+                        .required(false),
+                ),
+        )
+        // Sub-command for lookup.
+        .subcommand(
+            Command::new("lookup")
+                .about("Lookup information from the databases")
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("ip")
+                        .about("Lookup ASN, AS name and country of IP addresses")
+                        .arg(
+                            Arg::new("addr")
+                                .help("IPv4 or IPv6 addresses to lookup")
+                                .value_name("addr")
+                                .value_parser(value_parser!(IpAddr))
+                                .num_args(1..)
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::new("data-dir")
+                                .help(
+                                    "Directory containing BIN DB files (defaults to bundled data)",
+                                )
+                                .short('d')
+                                .long("data-dir")
+                                .value_name("dir_path")
+                                .value_parser(value_parser!(PathBuf))
+                                .required(false),
+                        )
+                        .arg(
+                            Arg::new("format")
+                                .help("Output format")
+                                .short('f')
+                                .long("format")
+                                .value_name("format")
+                                .value_parser(OutputFormat::NAMES)
+                                .default_value("table"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("asn")
+                        .about("Lookup IP ranges announced by ASNs")
+                        .arg(
+                            Arg::new("asn")
+                                .help("AS numbers to lookup")
+                                .value_name("asn")
+                                .value_parser(value_parser!(u32))
+                                .num_args(1..)
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::new("cidr")
+                                .help("Print aggregated CIDR prefixes instead of ranges")
+                                .long("cidr")
+                                .num_args(0)
+                                .required(false),
+                        )
+                        .arg(
+                            Arg::new("data-dir")
+                                .help(
+                                    "Directory containing BIN DB files (defaults to bundled data)",
+                                )
+                                .short('d')
+                                .long("data-dir")
+                                .value_name("dir_path")
+                                .value_parser(value_parser!(PathBuf))
+                                .required(false),
+                        )
+                        .arg(
+                            Arg::new("format")
+                                .help("Output format")
+                                .short('f')
+                                .long("format")
+                                .value_name("format")
+                                .value_parser(OutputFormat::NAMES)
+                                .default_value("table"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("country")
+                        .about("Lookup IP ranges allocated to countries")
+                        .arg(
+                            Arg::new("country-code")
+                                .help("ISO 3166-1 alpha-2 country codes to lookup (e.g. JP)")
+                                .value_name("country_code")
+                                .num_args(1..)
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::new("cidr")
+                                .help("Print aggregated CIDR prefixes instead of merged ranges")
+                                .long("cidr")
+                                .num_args(0)
+                                .required(false),
+                        )
+                        .arg(
+                            Arg::new("data-dir")
+                                .help(
+                                    "Directory containing BIN DB files (defaults to bundled data)",
+                                )
+                                .short('d')
+                                .long("data-dir")
+                                .value_name("dir_path")
+                                .value_parser(value_parser!(PathBuf))
+                                .required(false),
+                        )
+                        .arg(
+                            Arg::new("format")
+                                .help("Output format")
+                                .short('f')
+                                .long("format")
+                                .value_name("format")
+                                .value_parser(OutputFormat::NAMES)
+                                .default_value("table"),
+                        ),
+                ),
+        )
+        // Sub-command for bulk enrichment.
+        .subcommand(
+            Command::new("enrich")
+                .about("Enrich newline-separated IPs, MACs or ports read from stdin or a file")
+                .arg(
+                    Arg::new("type")
+                        .help("Type of values in the input")
+                        .short('t')
+                        .long("type")
+                        .value_name("type")
+                        .value_parser(InputKind::NAMES)
+                        .default_value("ip"),
+                )
+                .arg(
+                    Arg::new("input")
+                        .help("Input file (defaults to stdin)")
+                        .short('i')
+                        .long("input")
+                        .value_name("file_path")
+                        .value_parser(value_parser!(PathBuf))
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("output")
+                        .help("Output file (defaults to stdout)")
+                        .short('o')
+                        .long("output")
+                        .value_name("file_path")
+                        .value_parser(value_parser!(PathBuf))
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("format")
+                        .help("Output format")
+                        .short('f')
+                        .long("format")
+                        .value_name("format")
+                        .value_parser(OutputFormat::NAMES)
+                        .default_value("jsonl"),
+                )
+                .arg(
+                    Arg::new("data-dir")
+                        .help("Directory containing BIN DB files (defaults to bundled data)")
+                        .short('d')
+                        .long("data-dir")
+                        .value_name("dir_path")
+                        .value_parser(value_parser!(PathBuf))
+                        .required(false),
+                ),
+        )
+        // Sub-command for exporting BIN DB files.
+        .subcommand(
+            Command::new("export")
+                .about("Export the entries of a BIN DB file as CSV, JSON or JSON Lines")
+                .arg(
+                    Arg::new("dataset")
+                        .help("Dataset to export")
+                        .long("dataset")
+                        .value_name("dataset")
+                        .value_parser(DatasetKind::ALL.map(|kind| kind.name()))
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("format")
+                        .help("Output format")
+                        .short('f')
+                        .long("format")
+                        .value_name("format")
+                        .value_parser(ExportFormat::NAMES)
+                        .default_value("csv"),
+                )
+                .arg(
+                    Arg::new("input")
+                        .help("BIN file to export (defaults to the one in --data-dir or the bundled data)")
+                        .short('i')
+                        .long("input")
+                        .value_name("file_path")
+                        .value_parser(value_parser!(PathBuf))
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("data-dir")
+                        .help("Directory containing BIN DB files (defaults to bundled data)")
+                        .short('d')
+                        .long("data-dir")
+                        .value_name("dir_path")
+                        .value_parser(value_parser!(PathBuf))
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("output")
+                        .help("Output file (defaults to stdout)")
+                        .short('o')
+                        .long("output")
+                        .value_name("file_path")
+                        .value_parser(value_parser!(PathBuf))
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("ip-notation")
+                        .help("Render ip_from and ip_to of IP range datasets as IP addresses")
+                        .long("ip-notation")
+                        .num_args(0)
+                        .required(false),
+                ),
+        )
+        // Sub-command for dataset statistics.
+        .subcommand(
+            Command::new("stats")
+                .visible_alias("inspect")
+                .about("Report metadata, size, decode time, memory and coverage of the databases")
+                .arg(
+                    Arg::new("dataset")
+                        .help("Datasets to inspect (defaults to all)")
+                        .long("dataset")
+                        .value_name("dataset")
+                        .value_parser(DatasetKind::ALL.map(|kind| kind.name()))
+                        .num_args(1..)
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("data-dir")
+                        .help("Directory containing BIN DB files (defaults to bundled data)")
+                        .short('d')
+                        .long("data-dir")
+                        .value_name("dir_path")
+                        .value_parser(value_parser!(PathBuf))
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("top")
+                        .help("Number of top countries and ASNs by address count to list")
+                        .long("top")
+                        .value_name("n")
+                        .value_parser(value_parser!(usize))
+                        .default_value("10"),
+                )
+                .arg(
+                    Arg::new("format")
+                        .help("Output format")
+                        .short('f')
+                        .long("format")
+                        .value_name("format")
+                        .value_parser(StatsFormat::NAMES)
+                        .default_value("text"),
+                ),
+        )
+        // Sub-command for comparing two builds of a dataset.
+        .subcommand(
+            Command::new("diff")
+                .about("Compare two builds of a dataset and report added, removed and changed entries")
+                .arg(
+                    Arg::new("old")
+                        .help("Old BIN or CSV file")
+                        .value_name("old")
+                        .value_parser(value_parser!(PathBuf))
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("new")
+                        .help("New BIN or CSV file")
+                        .value_name("new")
+                        .value_parser(value_parser!(PathBuf))
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("dataset")
+                        .help("Dataset of both files (detected from the BIN header or file name by default)")
+                        .long("dataset")
+                        .value_name("dataset")
+                        .value_parser(DatasetKind::ALL.map(|kind| kind.name()))
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("format")
+                        .help("Output format")
+                        .short('f')
+                        .long("format")
+                        .value_name("format")
+                        .value_parser(DiffFormat::NAMES)
+                        .default_value("text"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .help("Output file (defaults to stdout)")
+                        .short('o')
+                        .long("output")
+                        .value_name("file_path")
+                        .value_parser(value_parser!(PathBuf))
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("limit")
+                        .help("Maximum number of entries listed per section in text output")
+                        .long("limit")
+                        .value_name("n")
+                        .value_parser(value_parser!(usize))
+                        .default_value("20"),
+                ),
+        )
+        // Sub-command for verifying BIN DB files against the manifest.
+        .subcommand(
+            Command::new("verify")
+                .about("Verify the BIN DB files of a directory against its manifest.json")
+                .arg(
+                    Arg::new("data-dir")
+                        .help("Directory containing the BIN DB files and manifest.json")
+                        .short('d')
+                        .long("data-dir")
+                        .value_name("dir_path")
+                        .value_parser(value_parser!(PathBuf))
+                        .required(true),
                 ),
         );
     app.get_matches()
@@ -0,0 +1,186 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::io::Write;
+
+/// Output format for records
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Jsonl,
+    Csv,
+    Table,
+}
+
+impl OutputFormat {
+    pub const NAMES: [&'static str; 3] = ["jsonl", "csv", "table"];
+
+    pub fn from_str(s: &str) -> OutputFormat {
+        match s {
+            "jsonl" => OutputFormat::Jsonl,
+            "csv" => OutputFormat::Csv,
+            _ => OutputFormat::Table,
+        }
+    }
+}
+
+/// A flat record that can be written in any `OutputFormat`
+pub trait Record: Serialize {
+    /// Column names, in the same order as `fields`
+    fn header() -> &'static [&'static str];
+    /// Column values, `None` for missing values
+    fn fields(&self) -> Vec<Option<String>>;
+}
+
+enum Sink<W: Write> {
+    Jsonl(W),
+    Csv(Box<csv::Writer<W>>),
+    /// Rows are buffered until `finish` to compute column widths
+    Table(W, Vec<Vec<String>>),
+}
+
+/// Writes records to `W` in the selected format.
+/// JSON Lines and CSV are streamed, the table is written on `finish`.
+pub struct RecordWriter<W: Write> {
+    header: &'static [&'static str],
+    sink: Sink<W>,
+}
+
+impl<W: Write> RecordWriter<W> {
+    pub fn new(format: OutputFormat, header: &'static [&'static str], out: W) -> Result<Self> {
+        let sink = match format {
+            OutputFormat::Jsonl => Sink::Jsonl(out),
+            OutputFormat::Csv => {
+                let mut wtr = csv::Writer::from_writer(out);
+                wtr.write_record(header)?;
+                Sink::Csv(Box::new(wtr))
+            }
+            OutputFormat::Table => Sink::Table(out, Vec::new()),
+        };
+        Ok(Self { header, sink })
+    }
+
+    pub fn write<R: Record>(&mut self, record: &R) -> Result<()> {
+        match &mut self.sink {
+            Sink::Jsonl(out) => {
+                serde_json::to_writer(&mut *out, record)?;
+                out.write_all(b"\n")?;
+            }
+            Sink::Csv(wtr) => {
+                wtr.write_record(record.fields().into_iter().map(Option::unwrap_or_default))?;
+            }
+            Sink::Table(_, rows) => {
+                rows.push(
+                    record
+                        .fields()
+                        .into_iter()
+                        .map(|v| v.unwrap_or_else(|| "-".to_string()))
+                        .collect(),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<()> {
+        match self.sink {
+            Sink::Jsonl(mut out) => out.flush()?,
+            Sink::Csv(mut wtr) => wtr.flush()?,
+            Sink::Table(mut out, rows) => {
+                write_table(&mut out, self.header, &rows)?;
+                out.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_table<W: Write>(out: &mut W, header: &[&str], rows: &[Vec<String>]) -> Result<()> {
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let header: Vec<String> = header.iter().map(|h| h.to_uppercase()).collect();
+    write_row(out, &widths, &header)?;
+    for row in rows {
+        write_row(out, &widths, row)?;
+    }
+    Ok(())
+}
+
+fn write_row<W: Write>(out: &mut W, widths: &[usize], cells: &[String]) -> Result<()> {
+    let last = cells.len().saturating_sub(1);
+    let mut line = String::new();
+    for (i, (cell, width)) in cells.iter().zip(widths).enumerate() {
+        if i == last {
+            line.push_str(cell);
+        } else {
+            let pad = width - cell.chars().count();
+            line.push_str(cell);
+            line.push_str(&" ".repeat(pad + 2));
+        }
+    }
+    writeln!(out, "{}", line)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct TestRecord {
+        name: String,
+        value: Option<u32>,
+    }
+
+    impl Record for TestRecord {
+        fn header() -> &'static [&'static str] {
+            &["name", "value"]
+        }
+
+        fn fields(&self) -> Vec<Option<String>> {
+            vec![Some(self.name.clone()), self.value.map(|v| v.to_string())]
+        }
+    }
+
+    fn render(format: OutputFormat) -> String {
+        let mut buf = Vec::new();
+        let mut writer = RecordWriter::new(format, TestRecord::header(), &mut buf).unwrap();
+        writer
+            .write(&TestRecord {
+                name: "alpha".into(),
+                value: Some(1),
+            })
+            .unwrap();
+        writer
+            .write(&TestRecord {
+                name: "b".into(),
+                value: None,
+            })
+            .unwrap();
+        writer.finish().unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_write_jsonl() {
+        assert_eq!(
+            render(OutputFormat::Jsonl),
+            "{\"name\":\"alpha\",\"value\":1}\n{\"name\":\"b\",\"value\":null}\n"
+        );
+    }
+
+    #[test]
+    fn test_write_csv() {
+        assert_eq!(render(OutputFormat::Csv), "name,value\nalpha,1\nb,\n");
+    }
+
+    #[test]
+    fn test_write_table() {
+        assert_eq!(
+            render(OutputFormat::Table),
+            "NAME   VALUE\nalpha  1\nb      -\n"
+        );
+    }
+}
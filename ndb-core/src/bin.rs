@@ -0,0 +1,342 @@
+use crate::compress;
+use crate::error::{Error, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Magic bytes at the start of every BIN database file.
+/// `0xFF` is never a valid first byte of a bincode encoded `Vec`,
+/// so files with a header can be told apart from legacy files.
+pub const MAGIC: [u8; 4] = [0xFF, b'N', b'D', b'B'];
+
+/// Schema version assumed for legacy BIN files without a header
+pub const LEGACY_SCHEMA_VERSION: u16 = 1;
+
+/// Kind of dataset stored in a BIN file
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DatasetKind {
+    As,
+    Country,
+    Ipv4Asn,
+    Ipv4Country,
+    Ipv6Asn,
+    Ipv6Country,
+    Oui,
+    TcpService,
+    UdpService,
+}
+
+impl DatasetKind {
+    pub const ALL: [DatasetKind; 9] = [
+        DatasetKind::As,
+        DatasetKind::Country,
+        DatasetKind::Ipv4Asn,
+        DatasetKind::Ipv4Country,
+        DatasetKind::Ipv6Asn,
+        DatasetKind::Ipv6Country,
+        DatasetKind::Oui,
+        DatasetKind::TcpService,
+        DatasetKind::UdpService,
+    ];
+
+    /// Short name of the dataset (e.g. `ipv4-asn`)
+    pub fn name(&self) -> &'static str {
+        match self {
+            DatasetKind::As => "as",
+            DatasetKind::Country => "country",
+            DatasetKind::Ipv4Asn => "ipv4-asn",
+            DatasetKind::Ipv4Country => "ipv4-country",
+            DatasetKind::Ipv6Asn => "ipv6-asn",
+            DatasetKind::Ipv6Country => "ipv6-country",
+            DatasetKind::Oui => "oui",
+            DatasetKind::TcpService => "tcp-service",
+            DatasetKind::UdpService => "udp-service",
+        }
+    }
+
+    /// Get the dataset kind by its short name
+    pub fn from_name(name: &str) -> Option<DatasetKind> {
+        DatasetKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == name)
+    }
+}
+
+impl fmt::Display for DatasetKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Header written in front of the entries of a BIN database file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BinHeader {
+    pub kind: DatasetKind,
+    pub schema_version: u16,
+    /// Build time in seconds since the Unix epoch
+    pub built_at: u64,
+    /// Description of the data source (e.g. the CSV file name)
+    pub source: String,
+    pub entry_count: u64,
+}
+
+impl BinHeader {
+    /// Create a new header with the current time as build time.
+    /// `SOURCE_DATE_EPOCH` overrides the build time for reproducible builds.
+    pub fn new(
+        kind: DatasetKind,
+        schema_version: u16,
+        source: impl Into<String>,
+        entry_count: usize,
+    ) -> Self {
+        let built_at = std::env::var("SOURCE_DATE_EPOCH")
+            .ok()
+            .and_then(|epoch| epoch.parse().ok())
+            .unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or_default()
+            });
+        Self {
+            kind,
+            schema_version,
+            built_at,
+            source: source.into(),
+            entry_count: entry_count as u64,
+        }
+    }
+
+    /// Check that the header describes the expected dataset and schema version
+    pub fn check(&self, kind: DatasetKind, schema_version: u16) -> Result<()> {
+        if self.kind != kind {
+            return Err(Error::DatasetMismatch {
+                expected: kind,
+                found: self.kind,
+            });
+        }
+        if self.schema_version != schema_version {
+            return Err(Error::SchemaVersionMismatch {
+                kind,
+                expected: schema_version,
+                found: self.schema_version,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Decode the header at the start of `slice`.
+/// Returns `None` for legacy files without a header, along with the remaining bytes.
+pub fn decode_header(slice: &[u8]) -> Result<(Option<BinHeader>, &[u8])> {
+    match slice.strip_prefix(&MAGIC) {
+        Some(rest) => {
+            let (header, len): (BinHeader, _) =
+                bincode::serde::decode_from_slice(rest, bincode::config::standard())?;
+            Ok((Some(header), &rest[len..]))
+        }
+        None => Ok((None, slice)),
+    }
+}
+
+/// Decode the entries of a BIN file holding `kind` with `schema_version`.
+/// zstd compressed files are decompressed first.
+pub fn decode_entries<T: DeserializeOwned>(
+    slice: &[u8],
+    kind: DatasetKind,
+    schema_version: u16,
+) -> Result<Vec<T>> {
+    if compress::is_zstd(slice) {
+        #[cfg(feature = "zstd")]
+        return decode_entries(&compress::decompress(slice)?, kind, schema_version);
+        #[cfg(not(feature = "zstd"))]
+        return Err(Error::ZstdUnsupported);
+    }
+    let (header, body) = decode_header(slice)?;
+    match &header {
+        Some(header) => header.check(kind, schema_version)?,
+        None if schema_version != LEGACY_SCHEMA_VERSION => {
+            return Err(Error::SchemaVersionMismatch {
+                kind,
+                expected: schema_version,
+                found: LEGACY_SCHEMA_VERSION,
+            });
+        }
+        None => {}
+    }
+    let (entries, _): (Vec<T>, _) =
+        bincode::serde::decode_from_slice(body, bincode::config::standard())?;
+    if let Some(header) = header {
+        if header.entry_count != entries.len() as u64 {
+            return Err(Error::EntryCountMismatch {
+                expected: header.entry_count,
+                found: entries.len() as u64,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+/// Read and decode the entries of a BIN file holding `kind` from `reader`
+pub fn read_entries<T: DeserializeOwned, R: Read>(
+    mut reader: R,
+    kind: DatasetKind,
+    schema_version: u16,
+) -> Result<Vec<T>> {
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+    decode_entries(&buf, kind, schema_version)
+}
+
+/// Read and decode the entries of the BIN file at `path` holding `kind`
+pub fn read_entries_from_path<T: DeserializeOwned, P: AsRef<Path>>(
+    path: P,
+    kind: DatasetKind,
+    schema_version: u16,
+) -> Result<Vec<T>> {
+    let buf = std::fs::read(path)?;
+    decode_entries(&buf, kind, schema_version)
+}
+
+/// Encode `header` and `entries` into a BIN file image
+pub fn encode_entries<T: Serialize>(header: &BinHeader, entries: &[T]) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    write_entries(header, entries, &mut buf)?;
+    Ok(buf)
+}
+
+/// Write `header` and `entries` to `writer`, returning the number of bytes written
+pub fn write_entries<T: Serialize, W: Write>(
+    header: &BinHeader,
+    entries: &[T],
+    mut writer: W,
+) -> Result<usize> {
+    writer.write_all(&MAGIC)?;
+    let header_len =
+        bincode::serde::encode_into_std_write(header, &mut writer, bincode::config::standard())?;
+    let entries_len =
+        bincode::serde::encode_into_std_write(entries, &mut writer, bincode::config::standard())?;
+    Ok(MAGIC.len() + header_len + entries_len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct TestEntry {
+        id: u32,
+        name: String,
+    }
+
+    fn test_entries() -> Vec<TestEntry> {
+        vec![
+            TestEntry {
+                id: 1,
+                name: "one".into(),
+            },
+            TestEntry {
+                id: 2,
+                name: "two".into(),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_header_roundtrip() {
+        let entries = test_entries();
+        let header = BinHeader::new(DatasetKind::As, 1, "as.csv", entries.len());
+        let buf = encode_entries(&header, &entries).unwrap();
+
+        let (decoded_header, _) = decode_header(&buf).unwrap();
+        assert_eq!(decoded_header, Some(header));
+        let decoded: Vec<TestEntry> = decode_entries(&buf, DatasetKind::As, 1).unwrap();
+        assert_eq!(decoded, entries);
+    }
+
+    #[test]
+    fn test_legacy_without_header() {
+        let entries = test_entries();
+        let buf = bincode::serde::encode_to_vec(&entries, bincode::config::standard()).unwrap();
+
+        let (header, _) = decode_header(&buf).unwrap();
+        assert!(header.is_none());
+        let decoded: Vec<TestEntry> = decode_entries(&buf, DatasetKind::As, 1).unwrap();
+        assert_eq!(decoded, entries);
+        assert!(matches!(
+            decode_entries::<TestEntry>(&buf, DatasetKind::As, 2),
+            Err(Error::SchemaVersionMismatch { found: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_dataset_mismatch() {
+        let entries = test_entries();
+        let header = BinHeader::new(DatasetKind::As, 1, "as.csv", entries.len());
+        let buf = encode_entries(&header, &entries).unwrap();
+
+        assert!(matches!(
+            decode_entries::<TestEntry>(&buf, DatasetKind::Ipv4Asn, 1),
+            Err(Error::DatasetMismatch {
+                expected: DatasetKind::Ipv4Asn,
+                found: DatasetKind::As,
+            })
+        ));
+        assert!(matches!(
+            decode_entries::<TestEntry>(&buf, DatasetKind::As, 2),
+            Err(Error::SchemaVersionMismatch {
+                expected: 2,
+                found: 1,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_entry_count_mismatch() {
+        let entries = test_entries();
+        let header = BinHeader::new(DatasetKind::As, 1, "as.csv", 3);
+        let buf = encode_entries(&header, &entries).unwrap();
+
+        assert!(matches!(
+            decode_entries::<TestEntry>(&buf, DatasetKind::As, 1),
+            Err(Error::EntryCountMismatch {
+                expected: 3,
+                found: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn test_decode_compressed() {
+        let entries = test_entries();
+        let header = BinHeader::new(DatasetKind::As, 1, "as.csv", entries.len());
+        let buf = encode_entries(&header, &entries).unwrap();
+
+        #[cfg(feature = "zstd")]
+        {
+            let compressed = compress::compress(&buf).unwrap();
+            let decoded: Vec<TestEntry> = decode_entries(&compressed, DatasetKind::As, 1).unwrap();
+            assert_eq!(decoded, entries);
+        }
+        #[cfg(not(feature = "zstd"))]
+        {
+            let compressed = [&compress::ZSTD_MAGIC[..], &buf].concat();
+            assert!(matches!(
+                decode_entries::<TestEntry>(&compressed, DatasetKind::As, 1),
+                Err(Error::ZstdUnsupported)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_dataset_kind_names() {
+        for kind in DatasetKind::ALL {
+            assert_eq!(DatasetKind::from_name(kind.name()), Some(kind));
+        }
+        assert_eq!(DatasetKind::from_name("unknown"), None);
+    }
+}
@@ -0,0 +1,58 @@
+#[cfg(feature = "zstd")]
+use crate::error::Result;
+
+/// Magic bytes at the start of a zstd frame
+pub const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// zstd compression level of BIN files, fixed so that the output is reproducible
+pub const ZSTD_LEVEL: i32 = 19;
+
+/// File name suffix of zstd compressed BIN files (e.g. `as.bin.zst`)
+pub const ZSTD_SUFFIX: &str = ".zst";
+
+/// Check whether `slice` starts with a zstd frame
+pub fn is_zstd(slice: &[u8]) -> bool {
+    slice.starts_with(&ZSTD_MAGIC)
+}
+
+/// Compress `data` with zstd at `ZSTD_LEVEL`
+#[cfg(feature = "zstd")]
+pub fn compress(data: &[u8]) -> Result<Vec<u8>> {
+    Ok(zstd::bulk::compress(data, ZSTD_LEVEL)?)
+}
+
+/// Compress the bundled BIN file `data/<bin_name>` of a dataset crate to
+/// `<bin_name>.zst` in `OUT_DIR`. Used by the build scripts of the dataset crates
+/// for their `bundled-zstd` feature.
+#[cfg(feature = "zstd")]
+pub fn compress_bundled(bin_name: &str) -> Result<()> {
+    let src = std::path::Path::new("data").join(bin_name);
+    println!("cargo:rerun-if-changed={}", src.display());
+    let compressed = compress(&std::fs::read(&src)?)?;
+    let out_dir = std::env::var_os("OUT_DIR")
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "OUT_DIR is not set"))?;
+    let dst = std::path::Path::new(&out_dir).join(format!("{}{}", bin_name, ZSTD_SUFFIX));
+    std::fs::write(dst, compressed)?;
+    Ok(())
+}
+
+/// Decompress zstd compressed `data`
+#[cfg(feature = "zstd")]
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    Ok(zstd::stream::decode_all(data)?)
+}
+
+#[cfg(all(test, feature = "zstd"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zstd_roundtrip() {
+        let data = b"asn,name\n13335,Cloudflare\n".repeat(100);
+        let compressed = compress(&data).unwrap();
+        assert!(is_zstd(&compressed));
+        assert!(compressed.len() < data.len());
+        assert_eq!(compress(&data).unwrap(), compressed);
+        assert_eq!(decompress(&compressed).unwrap(), data);
+    }
+}
@@ -0,0 +1,112 @@
+use crate::bin::{self, BinHeader, DatasetKind};
+use crate::error::Result;
+#[cfg(feature = "manifest")]
+use crate::manifest::Manifest;
+use crate::validate::{self, ValidationMode, ValidationReport};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// Common interface of the ndb database types
+pub trait NdbDataset: Sized {
+    /// Entry type stored in CSV and BIN files
+    type Entry: Serialize + DeserializeOwned;
+
+    const KIND: DatasetKind;
+    const CSV_NAME: &'static str;
+    const BIN_NAME: &'static str;
+    const SCHEMA_VERSION: u16;
+    /// File name of the flat (memory-mappable) layout, if the dataset supports it
+    const FLAT_NAME: Option<&'static str> = None;
+
+    /// Create a new database from a vector of entries
+    fn from_entries(entries: Vec<Self::Entry>) -> Result<Self>;
+
+    /// Get all entries as a vector
+    fn entries(&self) -> Vec<Self::Entry>;
+
+    /// Embedded BIN data, if the crate was built with the `bundled` feature
+    fn bundled_bin() -> Option<&'static [u8]> {
+        None
+    }
+
+    /// Load the embedded (bundled) database, if available
+    fn load_bundled() -> Option<Result<Self>> {
+        Self::bundled_bin().map(Self::from_slice)
+    }
+
+    /// Load database from a CSV reader
+    fn from_csv<R: Read>(reader: R) -> Result<Self> {
+        let mut rdr = csv::Reader::from_reader(reader);
+        let entries = rdr
+            .deserialize::<Self::Entry>()
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::from_entries(entries)
+    }
+
+    /// Check entries read from CSV, keyed by line number.
+    /// Datasets without checks return the entries unchanged.
+    fn validate_entries(
+        entries: Vec<(u64, Self::Entry)>,
+        _mode: ValidationMode,
+    ) -> Result<(Vec<Self::Entry>, ValidationReport)> {
+        let entries = entries.into_iter().map(|(_, entry)| entry).collect();
+        Ok((entries, ValidationReport::default()))
+    }
+
+    /// Load database from a CSV reader, validating the entries first
+    fn from_csv_validated<R: Read>(
+        reader: R,
+        mode: ValidationMode,
+    ) -> Result<(Self, ValidationReport)> {
+        let entries = validate::read_csv_lines(reader)?;
+        let (entries, report) = Self::validate_entries(entries, mode)?;
+        Ok((Self::from_entries(entries)?, report))
+    }
+
+    /// Create a new database from a binary slice
+    fn from_slice(slice: &[u8]) -> Result<Self> {
+        let entries = bin::decode_entries(slice, Self::KIND, Self::SCHEMA_VERSION)?;
+        Self::from_entries(entries)
+    }
+
+    /// Load database from a reader of a BIN file
+    fn from_bin_reader<R: Read>(reader: R) -> Result<Self> {
+        let entries = bin::read_entries(reader, Self::KIND, Self::SCHEMA_VERSION)?;
+        Self::from_entries(entries)
+    }
+
+    /// Load database from a BIN file
+    fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let entries = bin::read_entries_from_path(path, Self::KIND, Self::SCHEMA_VERSION)?;
+        Self::from_entries(entries)
+    }
+
+    /// Load database from a BIN file after checking its size and SHA-256 against `manifest`
+    #[cfg(feature = "manifest")]
+    fn from_path_verified<P: AsRef<Path>>(path: P, manifest: &Manifest) -> Result<Self> {
+        let data = std::fs::read(path)?;
+        manifest.verify(Self::KIND, &data)?;
+        Self::from_slice(&data)
+    }
+
+    /// Write the database as a BIN file to `writer`, returning the number of bytes written
+    fn write_bin<W: Write>(&self, writer: W, source: &str) -> Result<usize> {
+        let entries = self.entries();
+        let header = BinHeader::new(Self::KIND, Self::SCHEMA_VERSION, source, entries.len());
+        bin::write_entries(&header, &entries, writer)
+    }
+
+    /// Encode the database into a BIN file image
+    fn to_bin(&self, source: &str) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.write_bin(&mut buf, source)?;
+        Ok(buf)
+    }
+
+    /// Write the database in the flat layout to `writer`, if the dataset supports it
+    fn write_flat<W: Write>(&self, _writer: W) -> Option<Result<usize>> {
+        None
+    }
+}
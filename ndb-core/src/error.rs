@@ -0,0 +1,201 @@
+use crate::bin::DatasetKind;
+use crate::validate::ValidationReport;
+use std::fmt;
+use std::net::IpAddr;
+
+/// Errors returned by ndb databases
+#[derive(Debug)]
+pub enum Error {
+    /// I/O error
+    Io(std::io::Error),
+    /// CSV data could not be parsed
+    Csv {
+        /// Line of the record that failed, if known
+        line: Option<u64>,
+        /// Zero-based index of the field that failed to deserialize, if known
+        field: Option<u64>,
+        source: csv::Error,
+    },
+    /// Binary data could not be decoded
+    Decode(bincode::error::DecodeError),
+    /// Binary data could not be encoded
+    Encode(bincode::error::EncodeError),
+    /// The BIN file holds a different dataset than the one being loaded
+    DatasetMismatch {
+        expected: DatasetKind,
+        found: DatasetKind,
+    },
+    /// The BIN file was written with a different schema version
+    SchemaVersionMismatch {
+        kind: DatasetKind,
+        expected: u16,
+        found: u16,
+    },
+    /// The number of decoded entries does not match the header
+    EntryCountMismatch { expected: u64, found: u64 },
+    /// The flat database file is malformed or a value does not fit its layout
+    InvalidFlat(String),
+    /// An IP range whose start is greater than its end
+    InvalidRange { start: IpAddr, end: IpAddr },
+    /// A prefix (e.g. a MAC address prefix like `70:B3:D5:00:00:00/36`) that could not be parsed
+    InvalidPrefix(String),
+    /// A line of a text data file (e.g. Wireshark `manuf`) could not be parsed
+    Parse { line: u64, message: String },
+    /// Strict validation found issues in the data
+    Validation(Box<ValidationReport>),
+    /// The data is zstd compressed but the `zstd` feature is not enabled
+    ZstdUnsupported,
+    /// The BIN data has no header (legacy file)
+    MissingHeader,
+    /// The manifest could not be parsed or serialized
+    #[cfg(feature = "manifest")]
+    Json(serde_json::Error),
+    /// The manifest has no record of the dataset
+    NotInManifest(DatasetKind),
+    /// The size of a BIN file does not match the manifest
+    SizeMismatch {
+        file: String,
+        expected: u64,
+        found: u64,
+    },
+    /// The SHA-256 of a BIN file does not match the manifest
+    ChecksumMismatch {
+        file: String,
+        expected: String,
+        found: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::Csv {
+                line,
+                field,
+                source,
+            } => {
+                write!(f, "failed to parse CSV data")?;
+                if let Some(line) = line {
+                    write!(f, " at line {}", line)?;
+                }
+                if let Some(field) = field {
+                    write!(f, ", field {}", field)?;
+                }
+                write!(f, ": {}", source)
+            }
+            Error::Decode(e) => write!(f, "failed to decode binary data: {}", e),
+            Error::Encode(e) => write!(f, "failed to encode binary data: {}", e),
+            Error::DatasetMismatch { expected, found } => write!(
+                f,
+                "dataset mismatch: expected {} but the file holds {}",
+                expected, found
+            ),
+            Error::SchemaVersionMismatch {
+                kind,
+                expected,
+                found,
+            } => write!(
+                f,
+                "unsupported {} schema version: expected {} but found {}",
+                kind, expected, found
+            ),
+            Error::EntryCountMismatch { expected, found } => write!(
+                f,
+                "entry count mismatch: header says {} but decoded {}",
+                expected, found
+            ),
+            Error::InvalidFlat(reason) => write!(f, "invalid flat database: {}", reason),
+            Error::InvalidRange { start, end } => {
+                write!(f, "invalid range: {} is greater than {}", start, end)
+            }
+            Error::InvalidPrefix(prefix) => write!(f, "invalid prefix: {}", prefix),
+            Error::Parse { line, message } => {
+                write!(f, "parse error at line {}: {}", line, message)
+            }
+            Error::Validation(report) => write!(f, "validation failed: {}", report),
+            Error::ZstdUnsupported => write!(
+                f,
+                "the data is zstd compressed; enable the `zstd` feature to read it"
+            ),
+            Error::MissingHeader => write!(f, "the BIN data has no header"),
+            #[cfg(feature = "manifest")]
+            Error::Json(e) => write!(f, "invalid manifest: {}", e),
+            Error::NotInManifest(kind) => write!(f, "{} is not listed in the manifest", kind),
+            Error::SizeMismatch {
+                file,
+                expected,
+                found,
+            } => write!(
+                f,
+                "size mismatch for {}: manifest says {} bytes but found {}",
+                file, expected, found
+            ),
+            Error::ChecksumMismatch {
+                file,
+                expected,
+                found,
+            } => write!(
+                f,
+                "checksum mismatch for {}: manifest says {} but found {}",
+                file, expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Csv { source, .. } => Some(source),
+            Error::Decode(e) => Some(e),
+            Error::Encode(e) => Some(e),
+            #[cfg(feature = "manifest")]
+            Error::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(e: csv::Error) -> Self {
+        let line = e.position().map(|pos| pos.line());
+        let field = match e.kind() {
+            csv::ErrorKind::Deserialize { err, .. } => err.field(),
+            _ => None,
+        };
+        Error::Csv {
+            line,
+            field,
+            source: e,
+        }
+    }
+}
+
+impl From<bincode::error::DecodeError> for Error {
+    fn from(e: bincode::error::DecodeError) -> Self {
+        Error::Decode(e)
+    }
+}
+
+impl From<bincode::error::EncodeError> for Error {
+    fn from(e: bincode::error::EncodeError) -> Self {
+        Error::Encode(e)
+    }
+}
+
+#[cfg(feature = "manifest")]
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
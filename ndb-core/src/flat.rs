@@ -0,0 +1,351 @@
+//! Flat database layout: a fixed header followed by fixed-width range records
+//! sorted by start key, looked up with binary search and no deserialization.
+//!
+//! Header (little-endian): magic (4), version (u16), dataset kind (u16),
+//! key size (u16), value size (u16), record count (u64), reserved (4).
+//! Each record is `start` and `end` (key size bytes each) followed by the value.
+
+use crate::bin::DatasetKind;
+use crate::error::{Error, Result};
+use memmap2::Mmap;
+use std::fs::File;
+use std::io::Write;
+use std::ops::{Deref, RangeInclusive};
+use std::path::Path;
+
+/// Magic bytes at the start of every flat database file
+pub const FLAT_MAGIC: [u8; 4] = [0xFF, b'N', b'D', b'F'];
+
+/// Version of the flat layout
+pub const FLAT_VERSION: u16 = 1;
+
+/// Length of the flat file header in bytes
+pub const FLAT_HEADER_LEN: usize = 24;
+
+/// Stable numeric id of a dataset kind stored in the flat header
+fn kind_id(kind: DatasetKind) -> u16 {
+    match kind {
+        DatasetKind::As => 1,
+        DatasetKind::Country => 2,
+        DatasetKind::Ipv4Asn => 3,
+        DatasetKind::Ipv4Country => 4,
+        DatasetKind::Ipv6Asn => 5,
+        DatasetKind::Ipv6Country => 6,
+        DatasetKind::Oui => 7,
+        DatasetKind::TcpService => 8,
+        DatasetKind::UdpService => 9,
+    }
+}
+
+fn kind_from_id(id: u16) -> Option<DatasetKind> {
+    DatasetKind::ALL
+        .into_iter()
+        .find(|&kind| kind_id(kind) == id)
+}
+
+/// Bytes backing a flat table
+pub enum FlatBytes {
+    /// Static bytes (e.g. from `include_bytes!`)
+    Static(&'static [u8]),
+    /// Bytes owned in memory
+    Owned(Vec<u8>),
+    /// Memory-mapped file
+    Mmap(Mmap),
+}
+
+impl Deref for FlatBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            FlatBytes::Static(bytes) => bytes,
+            FlatBytes::Owned(bytes) => bytes,
+            FlatBytes::Mmap(mmap) => mmap,
+        }
+    }
+}
+
+/// Layout of the records of a flat table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlatLayout {
+    pub kind: DatasetKind,
+    /// Size of the `start` and `end` keys in bytes (4 for IPv4, 16 for IPv6)
+    pub key_size: usize,
+    /// Size of the value in bytes
+    pub value_size: usize,
+    /// Whether values are printable ASCII text (e.g. country codes),
+    /// checked once when a table is opened
+    pub ascii_values: bool,
+}
+
+impl FlatLayout {
+    fn record_size(&self) -> usize {
+        self.key_size * 2 + self.value_size
+    }
+}
+
+/// Read-only view of a flat database file
+pub struct FlatTable {
+    bytes: FlatBytes,
+    layout: FlatLayout,
+    len: usize,
+}
+
+impl FlatTable {
+    /// Create a table from `bytes`, checking the header against `layout`
+    pub fn new(bytes: FlatBytes, layout: FlatLayout) -> Result<Self> {
+        let header = bytes
+            .get(..FLAT_HEADER_LEN)
+            .ok_or_else(|| Error::InvalidFlat("file is shorter than the header".into()))?;
+        if header[..4] != FLAT_MAGIC {
+            return Err(Error::InvalidFlat("missing magic bytes".into()));
+        }
+        let read_u16 = |offset: usize| u16::from_le_bytes([header[offset], header[offset + 1]]);
+        let version = read_u16(4);
+        if version != FLAT_VERSION {
+            return Err(Error::InvalidFlat(format!(
+                "unsupported version {}",
+                version
+            )));
+        }
+        let kind = kind_from_id(read_u16(6))
+            .ok_or_else(|| Error::InvalidFlat("unknown dataset kind".into()))?;
+        if kind != layout.kind {
+            return Err(Error::DatasetMismatch {
+                expected: layout.kind,
+                found: kind,
+            });
+        }
+        if usize::from(read_u16(8)) != layout.key_size
+            || usize::from(read_u16(10)) != layout.value_size
+        {
+            return Err(Error::InvalidFlat("unexpected record layout".into()));
+        }
+        let mut count = [0u8; 8];
+        count.copy_from_slice(&header[12..20]);
+        let len = usize::try_from(u64::from_le_bytes(count))
+            .map_err(|_| Error::InvalidFlat("record count out of range".into()))?;
+        let expected_len = len
+            .checked_mul(layout.record_size())
+            .and_then(|records_len| records_len.checked_add(FLAT_HEADER_LEN));
+        if expected_len != Some(bytes.len()) {
+            return Err(Error::InvalidFlat(
+                "file size does not match the record count".into(),
+            ));
+        }
+        let table = Self { bytes, layout, len };
+        if layout.ascii_values {
+            if let Some(index) = (0..len).find(|&i| !is_ascii_value(table.record(i).1)) {
+                return Err(Error::InvalidFlat(format!(
+                    "value of record {} is not ASCII text",
+                    index
+                )));
+            }
+        }
+        Ok(table)
+    }
+
+    /// Memory-map the flat database file at `path`
+    pub fn open_mmap<P: AsRef<Path>>(path: P, layout: FlatLayout) -> Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: the map is read-only. Like any memory-mapped file, it must not be
+        // truncated or modified by another process while the table is alive.
+        let mmap = unsafe { Mmap::map(&file)? };
+        Self::new(FlatBytes::Mmap(mmap), layout)
+    }
+
+    /// Number of records
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get the key range and value bytes of record `index`
+    pub fn record(&self, index: usize) -> (RangeInclusive<u128>, &[u8]) {
+        let size = self.layout.record_size();
+        let offset = FLAT_HEADER_LEN + index * size;
+        let record = &self.bytes[offset..offset + size];
+        let (start, rest) = record.split_at(self.layout.key_size);
+        let (end, value) = rest.split_at(self.layout.key_size);
+        (read_key(start)..=read_key(end), value)
+    }
+
+    /// Find the record whose range contains `key`
+    pub fn find(&self, key: u128) -> Option<(RangeInclusive<u128>, &[u8])> {
+        // Index of the first record starting after `key`
+        let (mut low, mut high) = (0, self.len);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if *self.record(mid).0.start() <= key {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        let (range, value) = self.record(low.checked_sub(1)?);
+        range.contains(&key).then_some((range, value))
+    }
+
+    /// Iterate over all records in ascending order
+    pub fn iter(&self) -> impl Iterator<Item = (RangeInclusive<u128>, &[u8])> + '_ {
+        (0..self.len).map(|index| self.record(index))
+    }
+}
+
+fn is_ascii_value(value: &[u8]) -> bool {
+    value.iter().all(u8::is_ascii_graphic)
+}
+
+fn read_key(bytes: &[u8]) -> u128 {
+    let mut buf = [0u8; 16];
+    buf[..bytes.len()].copy_from_slice(bytes);
+    u128::from_le_bytes(buf)
+}
+
+/// Write a flat database file with `records` sorted by start key and not overlapping,
+/// returning the number of bytes written
+pub fn write_flat<W, V>(
+    layout: FlatLayout,
+    records: &[(RangeInclusive<u128>, V)],
+    mut writer: W,
+) -> Result<usize>
+where
+    W: Write,
+    V: AsRef<[u8]>,
+{
+    if let Some((_, value)) = records
+        .iter()
+        .find(|(_, value)| value.as_ref().len() != layout.value_size)
+    {
+        return Err(Error::InvalidFlat(format!(
+            "value of {} bytes does not fit the {} byte field",
+            value.as_ref().len(),
+            layout.value_size
+        )));
+    }
+    if layout.ascii_values {
+        if let Some((_, value)) = records
+            .iter()
+            .find(|(_, value)| !is_ascii_value(value.as_ref()))
+        {
+            return Err(Error::InvalidFlat(format!(
+                "value {:?} is not ASCII text",
+                String::from_utf8_lossy(value.as_ref())
+            )));
+        }
+    }
+
+    let mut header = Vec::with_capacity(FLAT_HEADER_LEN);
+    header.extend_from_slice(&FLAT_MAGIC);
+    header.extend_from_slice(&FLAT_VERSION.to_le_bytes());
+    header.extend_from_slice(&kind_id(layout.kind).to_le_bytes());
+    header.extend_from_slice(&(layout.key_size as u16).to_le_bytes());
+    header.extend_from_slice(&(layout.value_size as u16).to_le_bytes());
+    header.extend_from_slice(&(records.len() as u64).to_le_bytes());
+    header.resize(FLAT_HEADER_LEN, 0);
+    writer.write_all(&header)?;
+
+    let mut written = FLAT_HEADER_LEN;
+    let mut record = Vec::with_capacity(layout.record_size());
+    for (range, value) in records {
+        record.clear();
+        record.extend_from_slice(&range.start().to_le_bytes()[..layout.key_size]);
+        record.extend_from_slice(&range.end().to_le_bytes()[..layout.key_size]);
+        record.extend_from_slice(value.as_ref());
+        writer.write_all(&record)?;
+        written += record.len();
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYOUT: FlatLayout = FlatLayout {
+        kind: DatasetKind::Ipv4Asn,
+        key_size: 4,
+        value_size: 4,
+        ascii_values: false,
+    };
+
+    fn test_table() -> FlatTable {
+        let records = vec![
+            (10..=19, 1u32.to_le_bytes()),
+            (20..=29, 2u32.to_le_bytes()),
+            (40..=u32::MAX as u128, 3u32.to_le_bytes()),
+        ];
+        let mut buf = Vec::new();
+        let len = write_flat(LAYOUT, &records, &mut buf).unwrap();
+        assert_eq!(len, buf.len());
+        FlatTable::new(FlatBytes::Owned(buf), LAYOUT).unwrap()
+    }
+
+    #[test]
+    fn test_flat_find() {
+        let table = test_table();
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.find(9), None);
+        assert_eq!(table.find(10), Some((10..=19, &1u32.to_le_bytes()[..])));
+        assert_eq!(table.find(29), Some((20..=29, &2u32.to_le_bytes()[..])));
+        assert_eq!(table.find(30), None);
+        assert_eq!(
+            table.find(u32::MAX as u128).map(|(range, _)| range),
+            Some(40..=u32::MAX as u128)
+        );
+        assert_eq!(table.iter().count(), 3);
+    }
+
+    #[test]
+    fn test_flat_header_checks() {
+        let table = test_table();
+        let bytes = table.bytes.to_vec();
+
+        let layout = FlatLayout {
+            kind: DatasetKind::Ipv6Asn,
+            ..LAYOUT
+        };
+        assert!(matches!(
+            FlatTable::new(FlatBytes::Owned(bytes.clone()), layout),
+            Err(Error::DatasetMismatch { .. })
+        ));
+        let truncated = bytes[..bytes.len() - 1].to_vec();
+        assert!(matches!(
+            FlatTable::new(FlatBytes::Owned(truncated), LAYOUT),
+            Err(Error::InvalidFlat(_))
+        ));
+        assert!(matches!(
+            FlatTable::new(FlatBytes::Static(&[0u8; 4]), LAYOUT),
+            Err(Error::InvalidFlat(_))
+        ));
+    }
+
+    #[test]
+    fn test_flat_ascii_values() {
+        let layout = FlatLayout {
+            kind: DatasetKind::Ipv4Country,
+            key_size: 4,
+            value_size: 2,
+            ascii_values: true,
+        };
+        let mut buf = Vec::new();
+        write_flat(layout, &[(0..=9, *b"JP"), (10..=19, *b"US")], &mut buf).unwrap();
+        let table = FlatTable::new(FlatBytes::Owned(buf.clone()), layout).unwrap();
+        assert_eq!(table.find(15), Some((10..=19, &b"US"[..])));
+
+        // Corrupt the value of the second record
+        let last = buf.len() - 1;
+        buf[last] = 0xFF;
+        assert!(matches!(
+            FlatTable::new(FlatBytes::Owned(buf), layout),
+            Err(Error::InvalidFlat(_))
+        ));
+        assert!(matches!(
+            write_flat(layout, &[(0..=9, [0u8, 0u8])], Vec::new()),
+            Err(Error::InvalidFlat(_))
+        ));
+    }
+}
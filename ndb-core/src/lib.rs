@@ -1 +1,13 @@
+pub mod bin;
+pub mod compress;
+pub mod dataset;
+pub mod error;
+pub mod flat;
+#[cfg(feature = "manifest")]
+pub mod manifest;
+pub mod service;
 pub mod utils;
+pub mod validate;
+
+pub use dataset::NdbDataset;
+pub use error::{Error, Result};
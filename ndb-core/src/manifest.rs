@@ -0,0 +1,214 @@
+use crate::bin::{self, DatasetKind};
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+/// File name of the manifest written next to the BIN files
+pub const MANIFEST_NAME: &str = "manifest.json";
+
+/// Version of the manifest format
+pub const MANIFEST_VERSION: u16 = 1;
+
+/// Checksums and metadata of the BIN files in a data directory
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub version: u16,
+    pub datasets: Vec<ManifestEntry>,
+}
+
+/// Manifest record of one BIN file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Dataset name (e.g. `ipv4-asn`)
+    pub dataset: String,
+    /// BIN file name, relative to the manifest
+    pub file: String,
+    /// SHA-256 of the BIN file as lower-case hex
+    pub sha256: String,
+    /// Size of the BIN file in bytes
+    pub size: u64,
+    pub entry_count: u64,
+    pub schema_version: u16,
+    /// Data source recorded in the BIN header
+    pub source: String,
+    /// SHA-256 of the CSV file the BIN file was built from
+    pub source_sha256: Option<String>,
+    /// Build time in seconds since the Unix epoch
+    pub built_at: u64,
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            datasets: Vec::new(),
+        }
+    }
+}
+
+impl Manifest {
+    /// Parse a manifest from JSON
+    pub fn from_slice(slice: &[u8]) -> Result<Self> {
+        Ok(serde_json::from_slice(slice)?)
+    }
+
+    /// Read a manifest file
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_slice(&fs::read(path)?)
+    }
+
+    /// Read `manifest.json` of a data directory
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        Self::from_path(dir.as_ref().join(MANIFEST_NAME))
+    }
+
+    /// Serialize the manifest as pretty-printed JSON
+    pub fn to_vec(&self) -> Result<Vec<u8>> {
+        let mut json = serde_json::to_vec_pretty(self)?;
+        json.push(b'\n');
+        Ok(json)
+    }
+
+    /// Record of dataset `kind`
+    pub fn get(&self, kind: DatasetKind) -> Option<&ManifestEntry> {
+        self.datasets
+            .iter()
+            .find(|entry| entry.dataset == kind.name())
+    }
+
+    /// Add a record, replacing the one of the same dataset
+    pub fn insert(&mut self, entry: ManifestEntry) {
+        self.datasets.retain(|e| e.dataset != entry.dataset);
+        self.datasets.push(entry);
+        self.datasets.sort_by(|a, b| a.dataset.cmp(&b.dataset));
+    }
+
+    /// Verify the BIN data of dataset `kind` against its record
+    pub fn verify(&self, kind: DatasetKind, data: &[u8]) -> Result<()> {
+        self.get(kind)
+            .ok_or(Error::NotInManifest(kind))?
+            .verify(data)
+    }
+
+    /// Verify every file listed in the manifest, relative to `dir`
+    pub fn verify_dir<P: AsRef<Path>>(&self, dir: P) -> Vec<(&ManifestEntry, Result<()>)> {
+        self.datasets
+            .iter()
+            .map(|entry| {
+                let result = fs::read(dir.as_ref().join(&entry.file))
+                    .map_err(Error::from)
+                    .and_then(|data| entry.verify(&data));
+                (entry, result)
+            })
+            .collect()
+    }
+}
+
+impl ManifestEntry {
+    /// Create the record of a BIN file image, reading the metadata from its header.
+    /// `source_csv` is the CSV data it was built from, if known.
+    pub fn new(
+        kind: DatasetKind,
+        file: impl Into<String>,
+        bin: &[u8],
+        source_csv: Option<&[u8]>,
+    ) -> Result<Self> {
+        let header = bin::decode_header(bin)?.0.ok_or(Error::MissingHeader)?;
+        if header.kind != kind {
+            return Err(Error::DatasetMismatch {
+                expected: kind,
+                found: header.kind,
+            });
+        }
+        Ok(Self {
+            dataset: kind.name().to_string(),
+            file: file.into(),
+            sha256: sha256_hex(bin),
+            size: bin.len() as u64,
+            entry_count: header.entry_count,
+            schema_version: header.schema_version,
+            source: header.source,
+            source_sha256: source_csv.map(sha256_hex),
+            built_at: header.built_at,
+        })
+    }
+
+    /// Check the size and SHA-256 of `data` against this record
+    pub fn verify(&self, data: &[u8]) -> Result<()> {
+        if data.len() as u64 != self.size {
+            return Err(Error::SizeMismatch {
+                file: self.file.clone(),
+                expected: self.size,
+                found: data.len() as u64,
+            });
+        }
+        let sha256 = sha256_hex(data);
+        if sha256 != self.sha256 {
+            return Err(Error::ChecksumMismatch {
+                file: self.file.clone(),
+                expected: self.sha256.clone(),
+                found: sha256,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// SHA-256 of `data` as lower-case hex
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bin::BinHeader;
+
+    fn bin_image() -> Vec<u8> {
+        let header = BinHeader::new(DatasetKind::As, 1, "as.csv", 1);
+        bin::encode_entries(&header, &[(64500u32, "Test".to_string())]).unwrap()
+    }
+
+    #[test]
+    fn test_sha256_hex() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let bin = bin_image();
+        let entry =
+            ManifestEntry::new(DatasetKind::As, "as.bin", &bin, Some(b"asn,name\n")).unwrap();
+        assert_eq!(entry.entry_count, 1);
+        assert_eq!(entry.source, "as.csv");
+        let mut manifest = Manifest::default();
+        manifest.insert(entry);
+        assert!(manifest.verify(DatasetKind::As, &bin).is_ok());
+
+        let mut corrupted = bin.clone();
+        *corrupted.last_mut().unwrap() ^= 0xFF;
+        assert!(matches!(
+            manifest.verify(DatasetKind::As, &corrupted),
+            Err(Error::ChecksumMismatch { .. })
+        ));
+        assert!(matches!(
+            manifest.verify(DatasetKind::As, &bin[..bin.len() - 1]),
+            Err(Error::SizeMismatch { .. })
+        ));
+        assert!(matches!(
+            manifest.verify(DatasetKind::Oui, &bin),
+            Err(Error::NotInManifest(DatasetKind::Oui))
+        ));
+
+        let parsed = Manifest::from_slice(&manifest.to_vec().unwrap()).unwrap();
+        assert_eq!(parsed, manifest);
+    }
+}
@@ -0,0 +1,503 @@
+//! Parsers of the port/service registries shared by the TCP and UDP service crates.
+//!
+//! Registries list both protocols in one file; each crate keeps the records
+//! of its own protocol.
+
+use crate::error::{Error, Result};
+use serde::Deserialize;
+use std::io::{BufRead, BufReader, Read, Write};
+
+/// File name of the IANA Service Name and Transport Protocol Port Number Registry
+pub const IANA_CSV_NAME: &str = "service-names-port-numbers.csv";
+
+/// File name of the nmap `nmap-services` file
+pub const NMAP_SERVICES_NAME: &str = "nmap-services";
+
+/// File name of the `/etc/services` file
+pub const ETC_SERVICES_NAME: &str = "services";
+
+/// Default open frequency from which a port is considered common.
+/// About the 300 most frequently open TCP ports of `nmap-services` reach it.
+pub const COMMON_FREQUENCY: f64 = 0.001;
+
+/// Highest port number of the IANA System Ports (well-known ports)
+pub const WELLKNOWN_PORT_MAX: u16 = 1023;
+
+/// Transport protocol of a service record
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl Protocol {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+        }
+    }
+
+    /// Parse a protocol name, ignoring case. Protocols other than TCP and UDP return `None`.
+    pub fn from_name(name: &str) -> Option<Protocol> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "tcp" => Some(Protocol::Tcp),
+            "udp" => Some(Protocol::Udp),
+            _ => None,
+        }
+    }
+}
+
+/// Service assigned to a port and protocol
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceRecord {
+    pub port: u16,
+    pub protocol: Protocol,
+    /// Primary service name
+    pub name: String,
+    /// Other names registered for the same port and protocol
+    pub aliases: Vec<String>,
+    pub description: Option<String>,
+    /// Fraction of scanned hosts with the port open, from `nmap-services`
+    pub frequency: Option<f64>,
+    /// Assignee of the registration (e.g. `[Jon_Postel]`), from the IANA registry
+    pub assignee: Option<String>,
+    /// Reference of the registration (e.g. `[RFC9110]`), from the IANA registry
+    pub reference: Option<String>,
+}
+
+/// Whether `port` is one of the IANA System Ports (0-1023)
+pub fn is_wellknown(port: u16) -> bool {
+    port <= WELLKNOWN_PORT_MAX
+}
+
+/// Row of the IANA registry CSV. The contact, date, service code and
+/// note columns are not kept.
+#[derive(Debug, Deserialize)]
+struct IanaRow {
+    #[serde(rename = "Service Name")]
+    name: String,
+    #[serde(rename = "Port Number")]
+    port: String,
+    #[serde(rename = "Transport Protocol")]
+    protocol: String,
+    #[serde(rename = "Description", default)]
+    description: String,
+    #[serde(rename = "Assignee", default)]
+    assignee: String,
+    #[serde(rename = "Reference", default)]
+    reference: String,
+}
+
+/// Read the IANA `service-names-port-numbers.csv` registry.
+///
+/// Port ranges like `6000-6063` are expanded to one record per port. Rows without a
+/// service name (reserved or unassigned ports), without a port, or for protocols other
+/// than TCP and UDP are skipped. When several services are registered for the same port
+/// and protocol, the first one is the primary name and the others become aliases.
+/// Records are returned sorted by port and protocol.
+pub fn read_iana_csv<R: Read>(reader: R) -> Result<Vec<ServiceRecord>> {
+    let mut rdr = csv::Reader::from_reader(reader);
+    let mut records: Vec<ServiceRecord> = Vec::new();
+    // Index into `records` by (port, protocol)
+    let mut index = std::collections::HashMap::new();
+    for row in rdr.deserialize::<IanaRow>() {
+        let row = row?;
+        let name = row.name.trim();
+        let Some(protocol) = Protocol::from_name(&row.protocol) else {
+            continue;
+        };
+        let Some((start, end)) = parse_port_range(&row.port) else {
+            continue;
+        };
+        if name.is_empty() {
+            continue;
+        }
+        let description = normalize_description(&row.description);
+        let assignee = normalize_description(&row.assignee);
+        let reference = normalize_description(&row.reference);
+        for port in start..=end {
+            match index.get(&(port, protocol)) {
+                Some(&i) => {
+                    let record: &mut ServiceRecord = &mut records[i];
+                    if record.name != name && !record.aliases.iter().any(|alias| alias == name) {
+                        record.aliases.push(name.to_string());
+                    }
+                    if record.description.is_none() {
+                        record.description = description.clone();
+                    }
+                    if record.assignee.is_none() {
+                        record.assignee = assignee.clone();
+                    }
+                    if record.reference.is_none() {
+                        record.reference = reference.clone();
+                    }
+                }
+                None => {
+                    index.insert((port, protocol), records.len());
+                    records.push(ServiceRecord {
+                        port,
+                        protocol,
+                        name: name.to_string(),
+                        aliases: Vec::new(),
+                        description: description.clone(),
+                        frequency: None,
+                        assignee: assignee.clone(),
+                        reference: reference.clone(),
+                    });
+                }
+            }
+        }
+    }
+    records.sort_by_key(|record| (record.port, record.protocol.name()));
+    Ok(records)
+}
+
+/// Read an nmap `nmap-services` file.
+///
+/// Each line holds a service name, `port/protocol`, the open frequency and an optional
+/// `#` comment, which becomes the description. Protocols other than TCP and UDP are
+/// skipped. When several services share a port and protocol, the most frequent one is
+/// the primary name and the others become aliases.
+/// Records are returned sorted by port and protocol.
+pub fn read_nmap_services<R: Read>(reader: R) -> Result<Vec<ServiceRecord>> {
+    let mut records: Vec<ServiceRecord> = Vec::new();
+    let mut index = std::collections::HashMap::new();
+    for (i, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line?;
+        let line_no = i as u64 + 1;
+        let parse_error = |message: String| Error::Parse {
+            line: line_no,
+            message,
+        };
+        let (fields, comment) = match line.split_once('#') {
+            Some((fields, comment)) => (fields, Some(comment)),
+            None => (line.as_str(), None),
+        };
+        let fields: Vec<&str> = fields.split_whitespace().collect();
+        if fields.is_empty() {
+            continue;
+        }
+        let [name, port_proto, frequency] = fields[..] else {
+            return Err(parse_error(format!(
+                "expected name, port/protocol and frequency, found {:?}",
+                fields.join(" ")
+            )));
+        };
+        let (port, protocol) = port_proto
+            .split_once('/')
+            .ok_or_else(|| parse_error(format!("invalid port/protocol: {}", port_proto)))?;
+        let port: u16 = port
+            .parse()
+            .map_err(|_| parse_error(format!("invalid port: {}", port)))?;
+        let frequency: f64 = frequency
+            .parse()
+            .ok()
+            .filter(|f: &f64| (0.0..=1.0).contains(f))
+            .ok_or_else(|| parse_error(format!("invalid frequency: {}", frequency)))?;
+        let Some(protocol) = Protocol::from_name(protocol) else {
+            continue;
+        };
+        let record = ServiceRecord {
+            port,
+            protocol,
+            name: name.to_string(),
+            aliases: Vec::new(),
+            description: comment.and_then(normalize_description),
+            frequency: Some(frequency),
+            assignee: None,
+            reference: None,
+        };
+        match index.get(&(port, protocol)) {
+            Some(&i) => {
+                let existing: &mut ServiceRecord = &mut records[i];
+                if existing.frequency < record.frequency {
+                    let previous = std::mem::replace(existing, record);
+                    existing.aliases = previous.aliases;
+                    existing.aliases.insert(0, previous.name);
+                    if existing.description.is_none() {
+                        existing.description = previous.description;
+                    }
+                } else {
+                    existing.aliases.push(record.name);
+                    if existing.description.is_none() {
+                        existing.description = record.description;
+                    }
+                }
+            }
+            None => {
+                index.insert((port, protocol), records.len());
+                records.push(record);
+            }
+        }
+    }
+    records.sort_by_key(|record| (record.port, record.protocol.name()));
+    Ok(records)
+}
+
+/// Read an `/etc/services` file.
+///
+/// Each line holds a service name, `port/protocol`, optional aliases and an optional
+/// `#` comment, which becomes the description. Protocols other than TCP and UDP are
+/// skipped. As with `getservbyport`, the first service of a port and protocol is the
+/// primary one; the names of later lines for it become aliases.
+/// Records are returned sorted by port and protocol.
+pub fn read_etc_services<R: Read>(reader: R) -> Result<Vec<ServiceRecord>> {
+    let mut records: Vec<ServiceRecord> = Vec::new();
+    let mut index = std::collections::HashMap::new();
+    for (i, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line?;
+        let line_no = i as u64 + 1;
+        let parse_error = |message: String| Error::Parse {
+            line: line_no,
+            message,
+        };
+        let (fields, comment) = match line.split_once('#') {
+            Some((fields, comment)) => (fields, Some(comment)),
+            None => (line.as_str(), None),
+        };
+        let mut fields = fields.split_whitespace();
+        let Some(name) = fields.next() else {
+            continue;
+        };
+        let port_proto = fields
+            .next()
+            .ok_or_else(|| parse_error(format!("missing port/protocol for {}", name)))?;
+        let (port, protocol) = port_proto
+            .split_once('/')
+            .ok_or_else(|| parse_error(format!("invalid port/protocol: {}", port_proto)))?;
+        let port: u16 = port
+            .parse()
+            .map_err(|_| parse_error(format!("invalid port: {}", port)))?;
+        let Some(protocol) = Protocol::from_name(protocol) else {
+            continue;
+        };
+        let aliases = fields.map(str::to_string);
+        match index.get(&(port, protocol)) {
+            Some(&i) => {
+                let existing: &mut ServiceRecord = &mut records[i];
+                for alias in std::iter::once(name.to_string()).chain(aliases) {
+                    if existing.name != alias && !existing.aliases.contains(&alias) {
+                        existing.aliases.push(alias);
+                    }
+                }
+            }
+            None => {
+                index.insert((port, protocol), records.len());
+                records.push(ServiceRecord {
+                    port,
+                    protocol,
+                    name: name.to_string(),
+                    aliases: aliases.collect(),
+                    description: comment.and_then(normalize_description),
+                    frequency: None,
+                    assignee: None,
+                    reference: None,
+                });
+            }
+        }
+    }
+    records.sort_by_key(|record| (record.port, record.protocol.name()));
+    Ok(records)
+}
+
+/// Write `records` in the `/etc/services` format, sorted by port and protocol,
+/// returning the number of lines written.
+///
+/// Whitespace and `#` in names, which the format cannot hold, are replaced by `-`.
+/// Records without a name are skipped.
+pub fn write_etc_services<W: Write>(
+    mut writer: W,
+    records: impl IntoIterator<Item = ServiceRecord>,
+) -> Result<usize> {
+    let mut records: Vec<ServiceRecord> = records.into_iter().collect();
+    records.sort_by_key(|record| (record.port, record.protocol.name()));
+    let mut count = 0;
+    for record in &records {
+        let name = etc_services_name(&record.name);
+        if name.is_empty() {
+            continue;
+        }
+        let port_proto = format!("{}/{}", record.port, record.protocol.name());
+        let mut line = format!("{:<15} {:<15}", name, port_proto);
+        for alias in record.aliases.iter().map(|alias| etc_services_name(alias)) {
+            if !alias.is_empty() {
+                line.push(' ');
+                line.push_str(&alias);
+            }
+        }
+        if let Some(description) = &record.description {
+            line = format!("{:<47} # {}", line, description);
+        }
+        writeln!(writer, "{}", line.trim_end())?;
+        count += 1;
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+/// Make `name` a single `/etc/services` field
+fn etc_services_name(name: &str) -> String {
+    name.split(|c: char| c.is_whitespace() || c == '#')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Parse a port (`80`) or port range (`6000-6063`)
+fn parse_port_range(s: &str) -> Option<(u16, u16)> {
+    let s = s.trim();
+    let (start, end) = match s.split_once('-') {
+        Some((start, end)) => (start.trim().parse().ok()?, end.trim().parse().ok()?),
+        None => {
+            let port = s.parse().ok()?;
+            (port, port)
+        }
+    };
+    (start <= end).then_some((start, end))
+}
+
+/// Collapse the line breaks and runs of spaces found in registry descriptions
+fn normalize_description(s: &str) -> Option<String> {
+    let description = s.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!description.is_empty()).then_some(description)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IANA_CSV: &str = "\
+Service Name,Port Number,Transport Protocol,Description,Assignee,Contact,Registration Date,Modification Date,Reference,Service Code,Unauthorized Use Reported,Assignment Notes
+,0,tcp,Reserved,[Jon_Postel],[Jon_Postel],,,,,,
+http,80,tcp,World Wide Web HTTP,,,,,,,,
+www,80,tcp,World Wide Web HTTP,,,,,,,,
+www-http,80,tcp,\"World Wide Web
+  HTTP\",,,,,,,,
+http,80,udp,World Wide Web HTTP,,,,,,,,
+http,80,sctp,HTTP,[RFC9260],,,,[RFC9260],,,
+x11,6000-6002,tcp,X Window System,[Stuart_Anderson],,,,,,,
+smtp,,,,,,,,,,,
+";
+
+    #[test]
+    fn test_read_iana_csv() {
+        let records = read_iana_csv(IANA_CSV.as_bytes()).unwrap();
+        let ports: Vec<(u16, Protocol)> = records.iter().map(|r| (r.port, r.protocol)).collect();
+        assert_eq!(
+            ports,
+            vec![
+                (80, Protocol::Tcp),
+                (80, Protocol::Udp),
+                (6000, Protocol::Tcp),
+                (6001, Protocol::Tcp),
+                (6002, Protocol::Tcp),
+            ]
+        );
+        assert_eq!(records[0].name, "http");
+        assert_eq!(records[0].aliases, vec!["www", "www-http"]);
+        assert_eq!(
+            records[0].description.as_deref(),
+            Some("World Wide Web HTTP")
+        );
+        assert_eq!(records[0].assignee, None);
+        assert!(records[1].aliases.is_empty());
+        assert_eq!(records[4].assignee.as_deref(), Some("[Stuart_Anderson]"));
+        assert_eq!(records[4].name, "x11");
+    }
+
+    #[test]
+    fn test_read_nmap_services() {
+        let nmap = "\
+# Fields in this file are: Service name, portnum/protocol, open-frequency, optional comments
+#
+http\t80/tcp\t0.484143\t# World Wide Web HTTP
+www\t80/tcp\t0.000100
+http-alt\t80/tcp\t0.500000
+domain\t53/udp\t0.213496\t# Domain Name Server
+sctp-test\t80/sctp\t0.000001
+";
+        let records = read_nmap_services(nmap.as_bytes()).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].port, 53);
+        assert_eq!(records[0].protocol, Protocol::Udp);
+        assert_eq!(
+            records[0].description.as_deref(),
+            Some("Domain Name Server")
+        );
+        assert_eq!(records[1].name, "http-alt");
+        assert_eq!(records[1].aliases, vec!["http", "www"]);
+        assert_eq!(records[1].frequency, Some(0.5));
+        assert_eq!(
+            records[1].description.as_deref(),
+            Some("World Wide Web HTTP")
+        );
+
+        assert!(matches!(
+            read_nmap_services("http\t80\t0.1\n".as_bytes()),
+            Err(Error::Parse { line: 1, .. })
+        ));
+        assert!(matches!(
+            read_nmap_services("\nhttp\t80/tcp\n".as_bytes()),
+            Err(Error::Parse { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_etc_services() {
+        let services = "\
+# Network services, Internet style
+tcpmux\t\t1/tcp\t\t\t\t# TCP port service multiplexer
+http\t\t80/tcp\t\twww www-http\t# WorldWideWeb HTTP
+http-alt\t80/tcp
+http\t\t80/udp
+sctp-test\t80/sctp
+
+domain\t\t53/udp
+";
+        let records = read_etc_services(services.as_bytes()).unwrap();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0].name, "tcpmux");
+        assert_eq!(
+            records[0].description.as_deref(),
+            Some("TCP port service multiplexer")
+        );
+        assert_eq!(records[2].port, 80);
+        assert_eq!(records[2].aliases, vec!["www", "www-http", "http-alt"]);
+        assert_eq!(records[2].description.as_deref(), Some("WorldWideWeb HTTP"));
+
+        let mut out = Vec::new();
+        assert_eq!(write_etc_services(&mut out, records.clone()).unwrap(), 4);
+        assert_eq!(read_etc_services(out.as_slice()).unwrap(), records);
+        assert!(String::from_utf8(out).unwrap().contains(
+            "http            80/tcp          www www-http http-alt # WorldWideWeb HTTP\n"
+        ));
+
+        let mut out = Vec::new();
+        let record = ServiceRecord {
+            name: "Escale (Newton Dock)".to_string(),
+            ..records[0].clone()
+        };
+        let unnamed = ServiceRecord {
+            name: String::new(),
+            ..records[1].clone()
+        };
+        assert_eq!(write_etc_services(&mut out, [record, unnamed]).unwrap(), 1);
+        assert_eq!(
+            read_etc_services(out.as_slice()).unwrap()[0].name,
+            "Escale-(Newton-Dock)"
+        );
+        assert!(matches!(
+            read_etc_services("http\n".as_bytes()),
+            Err(Error::Parse { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_port_range() {
+        assert_eq!(parse_port_range("80"), Some((80, 80)));
+        assert_eq!(parse_port_range("6000-6063"), Some((6000, 6063)));
+        assert_eq!(parse_port_range("6063-6000"), None);
+        assert_eq!(parse_port_range(""), None);
+    }
+}
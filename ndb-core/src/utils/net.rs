@@ -0,0 +1,66 @@
+use ipnet::{Ipv4Net, Ipv4Subnets, Ipv6Net, Ipv6Subnets};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::ops::RangeInclusive;
+
+/// Split an IPv4 range into the minimal list of CIDR prefixes covering it
+pub fn ipv4_cidrs(range: &RangeInclusive<Ipv4Addr>) -> Vec<Ipv4Net> {
+    Ipv4Subnets::new(*range.start(), *range.end(), 0).collect()
+}
+
+/// Split an IPv6 range into the minimal list of CIDR prefixes covering it
+pub fn ipv6_cidrs(range: &RangeInclusive<Ipv6Addr>) -> Vec<Ipv6Net> {
+    Ipv6Subnets::new(*range.start(), *range.end(), 0).collect()
+}
+
+/// Number of addresses in an IPv4 range
+pub fn ipv4_range_len(range: &RangeInclusive<Ipv4Addr>) -> u64 {
+    u64::from(u32::from(*range.end())) - u64::from(u32::from(*range.start())) + 1
+}
+
+/// Number of addresses in an IPv6 range, saturating at `u128::MAX` for the full address space
+pub fn ipv6_range_len(range: &RangeInclusive<Ipv6Addr>) -> u128 {
+    (u128::from(*range.end()) - u128::from(*range.start())).saturating_add(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ipv4_cidrs() {
+        let range = Ipv4Addr::new(10, 0, 0, 0)..=Ipv4Addr::new(10, 0, 0, 255);
+        assert_eq!(ipv4_cidrs(&range), vec!["10.0.0.0/24".parse().unwrap()]);
+
+        let range = Ipv4Addr::new(10, 0, 0, 1)..=Ipv4Addr::new(10, 0, 0, 6);
+        let cidrs: Vec<String> = ipv4_cidrs(&range).iter().map(|n| n.to_string()).collect();
+        assert_eq!(
+            cidrs,
+            ["10.0.0.1/32", "10.0.0.2/31", "10.0.0.4/31", "10.0.0.6/32"]
+        );
+
+        let range = Ipv4Addr::UNSPECIFIED..=Ipv4Addr::BROADCAST;
+        assert_eq!(ipv4_cidrs(&range), vec!["0.0.0.0/0".parse().unwrap()]);
+        assert_eq!(ipv4_range_len(&range), 1 << 32);
+    }
+
+    #[test]
+    fn test_ipv6_cidrs() {
+        let start: Ipv6Addr = "2001:db8::".parse().unwrap();
+        let end: Ipv6Addr = "2001:db8:1:ffff:ffff:ffff:ffff:ffff".parse().unwrap();
+        assert_eq!(
+            ipv6_cidrs(&(start..=end)),
+            vec!["2001:db8::/47".parse().unwrap()]
+        );
+
+        let end: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        assert_eq!(
+            ipv6_cidrs(&(start..=end)),
+            vec!["2001:db8::/127".parse().unwrap()]
+        );
+        assert_eq!(ipv6_range_len(&(start..=end)), 2);
+        assert_eq!(
+            ipv6_range_len(&(Ipv6Addr::UNSPECIFIED..=end)),
+            u128::from(end) + 1
+        );
+    }
+}
@@ -1,10 +1,71 @@
+use serde::de::{self, Visitor};
 use serde::{Deserialize, Deserializer};
+use std::fmt;
 
-/// Deserialize a u8 value to a boolean
+/// Deserialize a u8 value to a boolean.
+/// Self-describing formats such as CSV also accept `true` and `false`,
+/// so that exported CSV files can be read back.
 pub fn de_u8_to_bool<'de, D>(deserializer: D) -> Result<bool, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let v: u8 = Deserialize::deserialize(deserializer)?;
-    Ok(v != 0)
+    if !deserializer.is_human_readable() {
+        let v: u8 = Deserialize::deserialize(deserializer)?;
+        return Ok(v != 0);
+    }
+    deserializer.deserialize_any(BoolVisitor)
+}
+
+struct BoolVisitor;
+
+impl<'de> Visitor<'de> for BoolVisitor {
+    type Value = bool;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("0, 1, true or false")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<bool, E> {
+        Ok(v)
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<bool, E> {
+        Ok(v != 0)
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<bool, E> {
+        Ok(v != 0)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<bool, E> {
+        match v.trim() {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            s => s
+                .parse::<u8>()
+                .map(|v| v != 0)
+                .map_err(|_| E::invalid_value(de::Unexpected::Str(v), &self)),
+        }
+    }
+}
+
+/// (De)serialize a list of words as a single space-separated string,
+/// so that it fits in one CSV column (e.g. service name aliases)
+pub mod space_separated {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(words: &[String], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&words.join(" "))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Ok(s.split_whitespace().map(str::to_string).collect())
+    }
 }
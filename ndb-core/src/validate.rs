@@ -0,0 +1,445 @@
+//! Validation of range entries read from CSV files.
+//!
+//! Plain loading inserts entries into a range map, which silently lets later
+//! ranges overwrite earlier ones. `validate_ranges` reports such problems and
+//! either rejects the data or repairs it.
+
+use crate::error::{Error, Result};
+use serde::de::DeserializeOwned;
+use std::fmt;
+use std::io::Read;
+
+/// Officially assigned ISO 3166-1 alpha-2 country codes
+pub const ISO_COUNTRY_CODES: [&str; 249] = [
+    "AD", "AE", "AF", "AG", "AI", "AL", "AM", "AO", "AQ", "AR", "AS", "AT", "AU", "AW", "AX", "AZ",
+    "BA", "BB", "BD", "BE", "BF", "BG", "BH", "BI", "BJ", "BL", "BM", "BN", "BO", "BQ", "BR", "BS",
+    "BT", "BV", "BW", "BY", "BZ", "CA", "CC", "CD", "CF", "CG", "CH", "CI", "CK", "CL", "CM", "CN",
+    "CO", "CR", "CU", "CV", "CW", "CX", "CY", "CZ", "DE", "DJ", "DK", "DM", "DO", "DZ", "EC", "EE",
+    "EG", "EH", "ER", "ES", "ET", "FI", "FJ", "FK", "FM", "FO", "FR", "GA", "GB", "GD", "GE", "GF",
+    "GG", "GH", "GI", "GL", "GM", "GN", "GP", "GQ", "GR", "GS", "GT", "GU", "GW", "GY", "HK", "HM",
+    "HN", "HR", "HT", "HU", "ID", "IE", "IL", "IM", "IN", "IO", "IQ", "IR", "IS", "IT", "JE", "JM",
+    "JO", "JP", "KE", "KG", "KH", "KI", "KM", "KN", "KP", "KR", "KW", "KY", "KZ", "LA", "LB", "LC",
+    "LI", "LK", "LR", "LS", "LT", "LU", "LV", "LY", "MA", "MC", "MD", "ME", "MF", "MG", "MH", "MK",
+    "ML", "MM", "MN", "MO", "MP", "MQ", "MR", "MS", "MT", "MU", "MV", "MW", "MX", "MY", "MZ", "NA",
+    "NC", "NE", "NF", "NG", "NI", "NL", "NO", "NP", "NR", "NU", "NZ", "OM", "PA", "PE", "PF", "PG",
+    "PH", "PK", "PL", "PM", "PN", "PR", "PS", "PT", "PW", "PY", "QA", "RE", "RO", "RS", "RU", "RW",
+    "SA", "SB", "SC", "SD", "SE", "SG", "SH", "SI", "SJ", "SK", "SL", "SM", "SN", "SO", "SR", "SS",
+    "ST", "SV", "SX", "SY", "SZ", "TC", "TD", "TF", "TG", "TH", "TJ", "TK", "TL", "TM", "TN", "TO",
+    "TR", "TT", "TV", "TW", "TZ", "UA", "UG", "UM", "US", "UY", "UZ", "VA", "VC", "VE", "VG", "VI",
+    "VN", "VU", "WF", "WS", "YE", "YT", "ZA", "ZM", "ZW",
+];
+
+/// Check whether `code` is an officially assigned ISO 3166-1 alpha-2 code (upper case)
+pub fn is_iso_country_code(code: &str) -> bool {
+    ISO_COUNTRY_CODES.binary_search(&code).is_ok()
+}
+
+/// What to do with entries that fail validation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationMode {
+    /// Fail with `Error::Validation` if any issue is found
+    Reject,
+    /// Fix or drop the offending entries and keep the rest
+    Repair,
+}
+
+/// Kind of problem found in a range entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IssueKind {
+    /// The range starts inside a range with a lower (or equal) start, which may be on
+    /// any line; it is reported against the range covering its start
+    Overlap,
+    /// `ip_from` is greater than `ip_to`
+    InvertedRange,
+    /// Same range and value as another row
+    Duplicate,
+    /// The country code is not an ISO 3166-1 alpha-2 code
+    InvalidCountryCode,
+    /// ASN 0, which is reserved and never announced (RFC 7607)
+    ZeroAsn,
+}
+
+impl IssueKind {
+    pub const ALL: [IssueKind; 5] = [
+        IssueKind::Overlap,
+        IssueKind::InvertedRange,
+        IssueKind::Duplicate,
+        IssueKind::InvalidCountryCode,
+        IssueKind::ZeroAsn,
+    ];
+
+    /// Short description used in reports
+    pub fn name(&self) -> &'static str {
+        match self {
+            IssueKind::Overlap => "overlapping range",
+            IssueKind::InvertedRange => "inverted range",
+            IssueKind::Duplicate => "duplicate row",
+            IssueKind::InvalidCountryCode => "non-ISO country code",
+            IssueKind::ZeroAsn => "ASN 0",
+        }
+    }
+}
+
+impl fmt::Display for IssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A single problem found during validation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationIssue {
+    /// CSV line of the offending entry
+    pub line: u64,
+    pub kind: IssueKind,
+    pub detail: String,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}: {}", self.line, self.kind, self.detail)
+    }
+}
+
+/// Issues found by `validate_ranges` and what was done about them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub issues: Vec<ValidationIssue>,
+    /// Entries fixed in repair mode
+    pub repaired: usize,
+    /// Entries dropped in repair mode
+    pub dropped: usize,
+}
+
+impl ValidationReport {
+    /// No issues were found
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Number of issues of `kind`
+    pub fn count(&self, kind: IssueKind) -> usize {
+        self.issues
+            .iter()
+            .filter(|issue| issue.kind == kind)
+            .count()
+    }
+
+    fn push(&mut self, line: u64, kind: IssueKind, detail: String) {
+        self.issues.push(ValidationIssue { line, kind, detail });
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} issues", self.issues.len())?;
+        let counts: Vec<String> = IssueKind::ALL
+            .into_iter()
+            .map(|kind| (kind, self.count(kind)))
+            .filter(|&(_, count)| count > 0)
+            .map(|(kind, count)| format!("{} {}", count, kind))
+            .collect();
+        if !counts.is_empty() {
+            write!(f, " ({})", counts.join(", "))?;
+        }
+        write!(f, ", {} repaired, {} dropped", self.repaired, self.dropped)
+    }
+}
+
+/// Range entry that can be checked by `validate_ranges`
+pub trait RangeRecord: Clone {
+    /// Start and end of the range (IPv4 addresses are widened to `u128`)
+    fn bounds(&self) -> (u128, u128);
+
+    fn set_bounds(&mut self, start: u128, end: u128);
+
+    /// Format a bound for reports (e.g. as an IP address)
+    fn format_bound(bound: u128) -> String;
+
+    /// Format the value for reports (e.g. `AS13335` or `JP`)
+    fn format_value(&self) -> String;
+
+    /// Check the value of the entry (e.g. the ASN or country code)
+    fn check_value(&self) -> Option<IssueKind>;
+
+    /// Fix the value in place, returning `false` if the entry has to be dropped
+    fn repair_value(&mut self) -> bool;
+
+    /// Whether both entries map to the same value
+    fn same_value(&self, other: &Self) -> bool;
+}
+
+/// Read entries from a CSV reader along with the line number of each record
+pub fn read_csv_lines<E, R>(reader: R) -> Result<Vec<(u64, E)>>
+where
+    E: DeserializeOwned,
+    R: Read,
+{
+    let mut rdr = csv::Reader::from_reader(reader);
+    let headers = rdr.headers()?.clone();
+    let mut record = csv::StringRecord::new();
+    let mut entries = Vec::new();
+    while rdr.read_record(&mut record)? {
+        let line = record.position().map_or(0, |pos| pos.line());
+        entries.push((line, record.deserialize(Some(&headers))?));
+    }
+    Ok(entries)
+}
+
+/// Check range entries for inverted, duplicate and overlapping ranges and invalid values.
+///
+/// In `Reject` mode any issue fails with `Error::Validation`. In `Repair` mode inverted
+/// ranges are swapped, duplicates and entries with an unfixable value are dropped, and
+/// overlapping ranges are trimmed to start after the ranges before them.
+pub fn validate_ranges<E: RangeRecord>(
+    entries: Vec<(u64, E)>,
+    mode: ValidationMode,
+) -> Result<(Vec<E>, ValidationReport)> {
+    let mut report = ValidationReport::default();
+    let mut checked = Vec::with_capacity(entries.len());
+    for (line, mut entry) in entries {
+        let (start, end) = entry.bounds();
+        if start > end {
+            report.push(
+                line,
+                IssueKind::InvertedRange,
+                format!("{} > {}", E::format_bound(start), E::format_bound(end)),
+            );
+            entry.set_bounds(end, start);
+            if mode == ValidationMode::Repair {
+                report.repaired += 1;
+            }
+        }
+        if let Some(kind) = entry.check_value() {
+            report.push(line, kind, format_entry(&entry));
+            if mode == ValidationMode::Repair {
+                if entry.repair_value() {
+                    report.repaired += 1;
+                } else {
+                    report.dropped += 1;
+                    continue;
+                }
+            }
+        }
+        checked.push((line, entry));
+    }
+    checked.sort_by_key(|(line, entry)| (entry.bounds(), *line));
+
+    // Entries with the same bounds are adjacent after sorting. Each one is compared with
+    // the earlier entries of its run, so that a duplicate is found even when a nested
+    // range sits between it and the original.
+    let mut duplicate_of: Vec<Option<u64>> = Vec::with_capacity(checked.len());
+    let mut run_start = 0;
+    for (i, (_, entry)) in checked.iter().enumerate() {
+        if entry.bounds() != checked[run_start].1.bounds() {
+            run_start = i;
+        }
+        let original = checked[run_start..i]
+            .iter()
+            .zip(&duplicate_of[run_start..i])
+            .find(|((_, prev), dup)| dup.is_none() && prev.same_value(entry))
+            .map(|((line, _), _)| *line);
+        duplicate_of.push(original);
+    }
+
+    let mut valid: Vec<(u64, E)> = Vec::with_capacity(checked.len());
+    // Highest end of the ranges kept so far and the line of the range ending there
+    let mut covered_end: Option<(u128, u64)> = None;
+    for ((line, mut entry), original) in checked.into_iter().zip(duplicate_of) {
+        let (start, end) = entry.bounds();
+        if let Some(original) = original {
+            report.push(
+                line,
+                IssueKind::Duplicate,
+                format!("{} (line {})", format_entry(&entry), original),
+            );
+            if mode == ValidationMode::Repair {
+                report.dropped += 1;
+            }
+            continue;
+        }
+        match covered_end {
+            Some((covered, prev_line)) if start <= covered => {
+                report.push(
+                    line,
+                    IssueKind::Overlap,
+                    format!(
+                        "{} overlaps the range ending at {} (line {})",
+                        format_entry(&entry),
+                        E::format_bound(covered),
+                        prev_line
+                    ),
+                );
+                if mode == ValidationMode::Repair {
+                    if end <= covered {
+                        report.dropped += 1;
+                        continue;
+                    }
+                    entry.set_bounds(covered + 1, end);
+                    report.repaired += 1;
+                }
+                if end > covered {
+                    covered_end = Some((end, line));
+                }
+            }
+            _ => covered_end = Some((end, line)),
+        }
+        valid.push((line, entry));
+    }
+
+    report.issues.sort_by_key(|issue| issue.line);
+    if mode == ValidationMode::Reject && !report.is_clean() {
+        return Err(Error::Validation(Box::new(report)));
+    }
+    let entries = valid.into_iter().map(|(_, entry)| entry).collect();
+    Ok((entries, report))
+}
+
+fn format_entry<E: RangeRecord>(entry: &E) -> String {
+    let (start, end) = entry.bounds();
+    format!(
+        "{}-{} {}",
+        E::format_bound(start),
+        E::format_bound(end),
+        entry.format_value()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct TestEntry {
+        start: u128,
+        end: u128,
+        value: u32,
+    }
+
+    impl RangeRecord for TestEntry {
+        fn bounds(&self) -> (u128, u128) {
+            (self.start, self.end)
+        }
+
+        fn set_bounds(&mut self, start: u128, end: u128) {
+            self.start = start;
+            self.end = end;
+        }
+
+        fn format_bound(bound: u128) -> String {
+            bound.to_string()
+        }
+
+        fn format_value(&self) -> String {
+            self.value.to_string()
+        }
+
+        fn check_value(&self) -> Option<IssueKind> {
+            (self.value == 0).then_some(IssueKind::ZeroAsn)
+        }
+
+        fn repair_value(&mut self) -> bool {
+            false
+        }
+
+        fn same_value(&self, other: &Self) -> bool {
+            self.value == other.value
+        }
+    }
+
+    fn entry(start: u128, end: u128, value: u32) -> TestEntry {
+        TestEntry { start, end, value }
+    }
+
+    fn test_entries() -> Vec<(u64, TestEntry)> {
+        vec![
+            (2, entry(10, 19, 1)),
+            (3, entry(29, 20, 2)),
+            (4, entry(10, 19, 1)),
+            (5, entry(15, 24, 3)),
+            (6, entry(40, 49, 0)),
+            (7, entry(21, 22, 4)),
+        ]
+    }
+
+    #[test]
+    fn test_validate_repair() {
+        let (entries, report) = validate_ranges(test_entries(), ValidationMode::Repair).unwrap();
+        assert_eq!(
+            entries,
+            vec![entry(10, 19, 1), entry(20, 24, 3), entry(25, 29, 2)]
+        );
+        assert_eq!(report.count(IssueKind::InvertedRange), 1);
+        assert_eq!(report.count(IssueKind::Duplicate), 1);
+        assert_eq!(report.count(IssueKind::Overlap), 3);
+        assert_eq!(report.count(IssueKind::ZeroAsn), 1);
+        assert_eq!(report.repaired, 3);
+        assert_eq!(report.dropped, 3);
+    }
+
+    #[test]
+    fn test_validate_reject() {
+        match validate_ranges(test_entries(), ValidationMode::Reject) {
+            Err(Error::Validation(report)) => assert_eq!(report.issues.len(), 6),
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+        let clean = vec![(2, entry(0, 9, 1)), (3, entry(10, 19, 1))];
+        let (entries, report) = validate_ranges(clean, ValidationMode::Reject).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(report.is_clean());
+    }
+
+    #[test]
+    fn test_validate_nested_range() {
+        let entries = vec![
+            (1, entry(0, 100, 1)),
+            (2, entry(10, 20, 2)),
+            (3, entry(50, 60, 3)),
+            (4, entry(10, 20, 2)),
+            (5, entry(90, 110, 4)),
+        ];
+        let report = match validate_ranges(entries.clone(), ValidationMode::Reject) {
+            Err(Error::Validation(report)) => report,
+            other => panic!("expected a validation error, got {:?}", other),
+        };
+        let messages: Vec<(u64, IssueKind, &str)> = report
+            .issues
+            .iter()
+            .map(|issue| (issue.line, issue.kind, issue.detail.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (
+                    2,
+                    IssueKind::Overlap,
+                    "10-20 2 overlaps the range ending at 100 (line 1)"
+                ),
+                (
+                    3,
+                    IssueKind::Overlap,
+                    "50-60 3 overlaps the range ending at 100 (line 1)"
+                ),
+                (4, IssueKind::Duplicate, "10-20 2 (line 2)"),
+                (
+                    5,
+                    IssueKind::Overlap,
+                    "90-110 4 overlaps the range ending at 100 (line 1)"
+                ),
+            ]
+        );
+
+        let (entries, report) = validate_ranges(entries, ValidationMode::Repair).unwrap();
+        assert_eq!(entries, vec![entry(0, 100, 1), entry(101, 110, 4)]);
+        assert_eq!(report.count(IssueKind::Duplicate), 1);
+        assert_eq!(report.count(IssueKind::Overlap), 3);
+    }
+
+    #[test]
+    fn test_iso_country_codes_sorted() {
+        assert!(ISO_COUNTRY_CODES.windows(2).all(|w| w[0] < w[1]));
+        assert!(is_iso_country_code("JP"));
+        assert!(!is_iso_country_code("EU"));
+        assert!(!is_iso_country_code("jp"));
+    }
+}
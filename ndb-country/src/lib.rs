@@ -1,10 +1,22 @@
-use anyhow::Result;
+use ndb_core::bin::DatasetKind;
+use ndb_core::{NdbDataset, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::io::Read;
+use std::path::Path;
+
+pub use ndb_core::Error;
 
 pub const CSV_NAME: &str = "country.csv";
 pub const BIN_NAME: &str = "country.bin";
+/// Version of the entry schema written to BIN files
+pub const SCHEMA_VERSION: u16 = 1;
+
+#[cfg(all(feature = "bundled", not(feature = "bundled-zstd")))]
+static BUNDLED_BIN: &[u8] = include_bytes!("../data/country.bin");
+
+#[cfg(feature = "bundled-zstd")]
+static BUNDLED_BIN: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/country.bin.zst"));
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct CountryEntry {
@@ -19,14 +31,8 @@ pub struct CountryDb {
 
 impl CountryDb {
     /// Load database from a CSV reader
-    pub fn from_csv<R: Read>(reader: R) -> Result<Self, csv::Error> {
-        let mut rdr = csv::Reader::from_reader(reader);
-        let mut map = HashMap::new();
-        for result in rdr.deserialize::<CountryEntry>() {
-            let entry = result?;
-            map.insert(entry.code.clone(), entry.name);
-        }
-        Ok(Self { inner: map })
+    pub fn from_csv<R: Read>(reader: R) -> Result<Self> {
+        <Self as NdbDataset>::from_csv(reader)
     }
 
     /// Create a new Country database from a vector of entries
@@ -39,17 +45,37 @@ impl CountryDb {
     }
 
     /// Create a new Country database from a binary slice
-    fn from_slice(slice: &[u8]) -> Result<Self> {
-        let (entries, _): (Vec<CountryEntry>, _) =
-            bincode::serde::decode_from_slice(slice, bincode::config::standard())?;
-        Ok(Self::from_entries(entries))
+    pub fn from_slice(slice: &[u8]) -> Result<Self> {
+        <Self as NdbDataset>::from_slice(slice)
+    }
+
+    /// Load database from a reader of a BIN file
+    pub fn from_bin_reader<R: Read>(reader: R) -> Result<Self> {
+        <Self as NdbDataset>::from_bin_reader(reader)
+    }
+
+    /// Load database from a BIN file (e.g. one written by `ndb-cli update`)
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        <Self as NdbDataset>::from_path(path)
     }
 
     /// Load embedded (bundled) database
     #[cfg(feature = "bundled")]
     pub fn bundled() -> Self {
-        static BIN_DATA: &[u8] = include_bytes!("../data/country.bin");
-        Self::from_slice(BIN_DATA).expect("Failed to load bundled countries.bin")
+        Self::try_bundled().expect("Failed to load bundled countries.bin")
+    }
+
+    /// Load embedded (bundled) database, returning an error instead of panicking
+    #[cfg(feature = "bundled")]
+    pub fn try_bundled() -> Result<Self> {
+        Self::from_slice(BUNDLED_BIN)
+    }
+
+    /// Get the process-wide shared bundled database, loaded once on first use
+    #[cfg(feature = "bundled")]
+    pub fn bundled_static() -> &'static Self {
+        static BUNDLED: std::sync::OnceLock<CountryDb> = std::sync::OnceLock::new();
+        BUNDLED.get_or_init(Self::bundled)
     }
 
     pub fn get_name(&self, code: &str) -> Option<&str> {
@@ -60,14 +86,40 @@ impl CountryDb {
         self.inner.iter()
     }
 
+    /// Get all entries as a vector, sorted by country code
     pub fn entries(&self) -> Vec<CountryEntry> {
-        self.inner
+        let mut entries: Vec<CountryEntry> = self
+            .inner
             .iter()
             .map(|(code, name)| CountryEntry {
                 code: code.clone(),
                 name: name.clone(),
             })
-            .collect()
+            .collect();
+        entries.sort_by(|a, b| a.code.cmp(&b.code));
+        entries
+    }
+}
+
+impl NdbDataset for CountryDb {
+    type Entry = CountryEntry;
+
+    const KIND: DatasetKind = DatasetKind::Country;
+    const CSV_NAME: &'static str = CSV_NAME;
+    const BIN_NAME: &'static str = BIN_NAME;
+    const SCHEMA_VERSION: u16 = SCHEMA_VERSION;
+
+    fn from_entries(entries: Vec<CountryEntry>) -> Result<Self> {
+        Ok(CountryDb::from_entries(entries))
+    }
+
+    fn entries(&self) -> Vec<CountryEntry> {
+        CountryDb::entries(self)
+    }
+
+    #[cfg(feature = "bundled")]
+    fn bundled_bin() -> Option<&'static [u8]> {
+        Some(BUNDLED_BIN)
     }
 }
 
@@ -118,4 +170,19 @@ mod tests {
 
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn test_country_dataset_bin_roundtrip() {
+        let csv = "code,name\nJP,Japan\n";
+        let db = <CountryDb as NdbDataset>::from_csv(csv.as_bytes()).unwrap();
+        let buf = db.to_bin("test").unwrap();
+
+        let (header, _) = ndb_core::bin::decode_header(&buf).unwrap();
+        let header = header.expect("BIN file should have a header");
+        assert_eq!(header.kind, DatasetKind::Country);
+        assert_eq!(header.entry_count, 1);
+
+        let decoded = <CountryDb as NdbDataset>::from_slice(&buf).unwrap();
+        assert_eq!(decoded.get_name("JP"), Some("Japan"));
+    }
 }
@@ -1,10 +1,38 @@
-use anyhow::Result;
+use ndb_core::bin::DatasetKind;
+use ndb_core::flat::{self, FlatBytes, FlatLayout, FlatTable};
+use ndb_core::utils::net;
+use ndb_core::validate::{self, IssueKind, RangeRecord, ValidationMode, ValidationReport};
+use ndb_core::{NdbDataset, Result};
 use rangemap::RangeInclusiveMap;
 use serde::{Deserialize, Serialize};
-use std::{io::Read, net::Ipv4Addr};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::Ipv4Addr;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+pub use ipnet::Ipv4Net;
+pub use ndb_core::Error;
 
 pub const CSV_NAME: &str = "ipv4-asn.csv";
 pub const BIN_NAME: &str = "ipv4-asn.bin";
+/// File name of the flat (memory-mappable) layout
+pub const FLAT_NAME: &str = "ipv4-asn.flat";
+/// Version of the entry schema written to BIN files
+pub const SCHEMA_VERSION: u16 = 1;
+
+#[cfg(all(feature = "bundled", not(feature = "bundled-zstd")))]
+static BUNDLED_BIN: &[u8] = include_bytes!("../data/ipv4-asn.bin");
+
+#[cfg(feature = "bundled-zstd")]
+static BUNDLED_BIN: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/ipv4-asn.bin.zst"));
+
+const FLAT_LAYOUT: FlatLayout = FlatLayout {
+    kind: DatasetKind::Ipv4Asn,
+    key_size: 4,
+    value_size: 4,
+    ascii_values: false,
+};
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct Ipv4AsnEntry {
@@ -13,46 +41,136 @@ pub struct Ipv4AsnEntry {
     pub asn: u32,
 }
 
+impl RangeRecord for Ipv4AsnEntry {
+    fn bounds(&self) -> (u128, u128) {
+        (u128::from(self.ip_from), u128::from(self.ip_to))
+    }
+
+    fn set_bounds(&mut self, start: u128, end: u128) {
+        // Bounds come from `bounds`, so they fit in `u32`
+        self.ip_from = start as u32;
+        self.ip_to = end as u32;
+    }
+
+    fn format_bound(bound: u128) -> String {
+        Ipv4Addr::from(bound as u32).to_string()
+    }
+
+    fn format_value(&self) -> String {
+        format!("AS{}", self.asn)
+    }
+
+    fn check_value(&self) -> Option<IssueKind> {
+        (self.asn == 0).then_some(IssueKind::ZeroAsn)
+    }
+
+    fn repair_value(&mut self) -> bool {
+        false
+    }
+
+    fn same_value(&self, other: &Self) -> bool {
+        self.asn == other.asn
+    }
+}
+
 /// Represents the IPv4 ASN database
 pub struct Ipv4AsnDb {
     inner_range: RangeInclusiveMap<u32, u32>,
+    asn_index: Option<HashMap<u32, Vec<RangeInclusive<u32>>>>,
 }
 
 impl Ipv4AsnDb {
     /// Load database from a CSV reader
-    pub fn from_csv<R: Read>(reader: R) -> Result<Self, csv::Error> {
-        let mut rdr = csv::Reader::from_reader(reader);
-        let mut range_map: RangeInclusiveMap<u32, u32> = RangeInclusiveMap::new();
-        for result in rdr.deserialize::<Ipv4AsnEntry>() {
-            let entry = result?;
-            range_map.insert(entry.ip_from..=entry.ip_to, entry.asn);
+    pub fn from_csv<R: Read>(reader: R) -> Result<Self> {
+        <Self as NdbDataset>::from_csv(reader)
+    }
+
+    /// Load database from a CSV reader, checking for overlapping, inverted and duplicate
+    /// ranges and invalid values. See `ndb_core::validate::validate_ranges`.
+    pub fn from_csv_validated<R: Read>(
+        reader: R,
+        mode: ValidationMode,
+    ) -> Result<(Self, ValidationReport)> {
+        <Self as NdbDataset>::from_csv_validated(reader, mode)
+    }
+
+    /// Create a new IPv4 ASN database from a vector of entries
+    pub fn from_entries(entries: Vec<Ipv4AsnEntry>) -> Result<Self> {
+        let mut inner_range = RangeInclusiveMap::new();
+        for entry in entries {
+            if entry.ip_from > entry.ip_to {
+                return Err(Error::InvalidRange {
+                    start: Ipv4Addr::from(entry.ip_from).into(),
+                    end: Ipv4Addr::from(entry.ip_to).into(),
+                });
+            }
+            inner_range.insert(entry.ip_from..=entry.ip_to, entry.asn);
         }
         Ok(Self {
-            inner_range: range_map,
+            inner_range,
+            asn_index: None,
         })
     }
 
-    /// Create a new IPv4 ASN database from a vector of entries
-    pub fn from_entries(entries: Vec<Ipv4AsnEntry>) -> Self {
-        let inner_range = entries
-            .into_iter()
-            .map(|entry| (entry.ip_from..=entry.ip_to, entry.asn))
-            .collect();
-        Self { inner_range }
+    /// Create a new IPv4 ASN database from a binary slice
+    pub fn from_slice(slice: &[u8]) -> Result<Self> {
+        <Self as NdbDataset>::from_slice(slice)
     }
 
-    /// Create a new IPv4 ASN database from a binary slice
-    fn from_slice(slice: &[u8]) -> Result<Self> {
-        let (entries, _): (Vec<Ipv4AsnEntry>, _) =
-            bincode::serde::decode_from_slice(slice, bincode::config::standard())?;
-        Ok(Self::from_entries(entries))
+    /// Load database from a reader of a BIN file
+    pub fn from_bin_reader<R: Read>(reader: R) -> Result<Self> {
+        <Self as NdbDataset>::from_bin_reader(reader)
+    }
+
+    /// Load database from a BIN file (e.g. one written by `ndb-cli update`)
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        <Self as NdbDataset>::from_path(path)
     }
 
     /// Load embedded (bundled) database
     #[cfg(feature = "bundled")]
     pub fn bundled() -> Self {
-        static BIN_DATA: &[u8] = include_bytes!("../data/ipv4-asn.bin");
-        Self::from_slice(BIN_DATA).expect("Failed to load bundled ipv4-asn.bin")
+        Self::try_bundled().expect("Failed to load bundled ipv4-asn.bin")
+    }
+
+    /// Load embedded (bundled) database, returning an error instead of panicking
+    #[cfg(feature = "bundled")]
+    pub fn try_bundled() -> Result<Self> {
+        Self::from_slice(BUNDLED_BIN)
+    }
+
+    /// Get the process-wide shared bundled database, loaded once on first use
+    #[cfg(feature = "bundled")]
+    pub fn bundled_static() -> &'static Self {
+        static BUNDLED: std::sync::OnceLock<Ipv4AsnDb> = std::sync::OnceLock::new();
+        BUNDLED.get_or_init(Self::bundled)
+    }
+
+    /// Memory-map a flat database file (e.g. one written by `ndb-cli update --flat`)
+    pub fn open_mmap<P: AsRef<Path>>(path: P) -> Result<Ipv4AsnFlatDb> {
+        Ipv4AsnFlatDb::open_mmap(path)
+    }
+
+    /// Write the database in the flat layout to `writer`, returning the number of bytes written
+    pub fn write_flat<W: Write>(&self, writer: W) -> Result<usize> {
+        let records: Vec<_> = self
+            .inner_range
+            .iter()
+            .map(|(range, asn)| {
+                (
+                    u128::from(*range.start())..=u128::from(*range.end()),
+                    asn.to_le_bytes(),
+                )
+            })
+            .collect();
+        flat::write_flat(FLAT_LAYOUT, &records, writer)
+    }
+
+    /// Encode the database into a flat database image
+    pub fn to_flat(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.write_flat(&mut buf)?;
+        Ok(buf)
     }
 
     /// Get ASN by u32 IP address
@@ -66,6 +184,76 @@ impl Ipv4AsnDb {
         self.inner_range.get(&ip_u32)
     }
 
+    /// Lookup ASN and the matched `ip_from..=ip_to` range by IPv4 address.
+    /// Adjacent ranges with the same ASN are returned as one range.
+    pub fn lookup_range(&self, ip: &Ipv4Addr) -> Option<(RangeInclusive<Ipv4Addr>, &u32)> {
+        self.inner_range
+            .get_key_value(&u32::from(*ip))
+            .map(|(range, value)| {
+                (
+                    Ipv4Addr::from(*range.start())..=Ipv4Addr::from(*range.end()),
+                    value,
+                )
+            })
+    }
+
+    /// Lookup ASN and the minimal CIDR prefixes of the matched range by IPv4 address
+    pub fn lookup_cidrs(&self, ip: &Ipv4Addr) -> Option<(Vec<Ipv4Net>, &u32)> {
+        self.lookup_range(ip)
+            .map(|(range, value)| (net::ipv4_cidrs(&range), value))
+    }
+
+    /// Build the ASN to ranges index used by `ranges_for_asn`.
+    /// Without the index, reverse lookups scan all ranges.
+    pub fn build_asn_index(&mut self) {
+        let mut index: HashMap<u32, Vec<RangeInclusive<u32>>> = HashMap::new();
+        for (range, asn) in self.inner_range.iter() {
+            index.entry(*asn).or_default().push(range.clone());
+        }
+        self.asn_index = Some(index);
+    }
+
+    /// Build the ASN index and return the database
+    pub fn with_asn_index(mut self) -> Self {
+        self.build_asn_index();
+        self
+    }
+
+    /// Get all ranges of an ASN in ascending order
+    pub fn ranges_for_asn(
+        &self,
+        asn: u32,
+    ) -> Box<dyn Iterator<Item = RangeInclusive<Ipv4Addr>> + '_> {
+        let to_addr = |range: &RangeInclusive<u32>| {
+            Ipv4Addr::from(*range.start())..=Ipv4Addr::from(*range.end())
+        };
+        match &self.asn_index {
+            Some(index) => Box::new(index.get(&asn).into_iter().flatten().map(to_addr)),
+            None => Box::new(
+                self.inner_range
+                    .iter()
+                    .filter(move |(_, &value)| value == asn)
+                    .map(move |(range, _)| to_addr(range)),
+            ),
+        }
+    }
+
+    /// Get the aggregated CIDR prefixes of all ranges of an ASN
+    pub fn cidrs_for_asn(&self, asn: u32) -> impl Iterator<Item = Ipv4Net> {
+        let cidrs: Vec<Ipv4Net> = self
+            .ranges_for_asn(asn)
+            .flat_map(|range| net::ipv4_cidrs(&range))
+            .collect();
+        Ipv4Net::aggregate(&cidrs).into_iter()
+    }
+
+    /// Get the total number of addresses in all ranges of an ASN
+    pub fn address_count_for_asn(&self, asn: u32) -> u64 {
+        self.ranges_for_asn(asn)
+            .map(|range| net::ipv4_range_len(&range))
+            .fold(0, u64::saturating_add)
+    }
+
     /// Get all ASN entries as an iterator
     pub fn all(&self) -> impl Iterator<Item = Ipv4AsnEntry> + '_ {
         self.inner_range.iter().map(|(range, asn)| Ipv4AsnEntry {
@@ -80,9 +268,114 @@ impl Ipv4AsnDb {
     }
 }
 
+impl NdbDataset for Ipv4AsnDb {
+    type Entry = Ipv4AsnEntry;
+
+    const KIND: DatasetKind = DatasetKind::Ipv4Asn;
+    const CSV_NAME: &'static str = CSV_NAME;
+    const BIN_NAME: &'static str = BIN_NAME;
+    const SCHEMA_VERSION: u16 = SCHEMA_VERSION;
+    const FLAT_NAME: Option<&'static str> = Some(FLAT_NAME);
+
+    fn from_entries(entries: Vec<Ipv4AsnEntry>) -> Result<Self> {
+        Ipv4AsnDb::from_entries(entries)
+    }
+
+    fn entries(&self) -> Vec<Ipv4AsnEntry> {
+        Ipv4AsnDb::entries(self)
+    }
+
+    fn validate_entries(
+        entries: Vec<(u64, Ipv4AsnEntry)>,
+        mode: ValidationMode,
+    ) -> Result<(Vec<Ipv4AsnEntry>, ValidationReport)> {
+        validate::validate_ranges(entries, mode)
+    }
+
+    fn write_flat<W: Write>(&self, writer: W) -> Option<Result<usize>> {
+        Some(Ipv4AsnDb::write_flat(self, writer))
+    }
+
+    #[cfg(feature = "bundled")]
+    fn bundled_bin() -> Option<&'static [u8]> {
+        Some(BUNDLED_BIN)
+    }
+}
+
+/// Zero-copy IPv4 ASN database searched in place over the flat layout
+pub struct Ipv4AsnFlatDb {
+    table: FlatTable,
+}
+
+impl Ipv4AsnFlatDb {
+    /// Use a flat database image in static memory (e.g. from `include_bytes!`)
+    pub fn from_static(bytes: &'static [u8]) -> Result<Self> {
+        Self::from_bytes(FlatBytes::Static(bytes))
+    }
+
+    /// Use a flat database image held in memory
+    pub fn from_vec(bytes: Vec<u8>) -> Result<Self> {
+        Self::from_bytes(FlatBytes::Owned(bytes))
+    }
+
+    /// Memory-map a flat database file
+    pub fn open_mmap<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let table = FlatTable::open_mmap(path, FLAT_LAYOUT)?;
+        Ok(Self { table })
+    }
+
+    fn from_bytes(bytes: FlatBytes) -> Result<Self> {
+        let table = FlatTable::new(bytes, FLAT_LAYOUT)?;
+        Ok(Self { table })
+    }
+
+    /// Lookup ASN by IPv4 address
+    pub fn lookup(&self, ip: &Ipv4Addr) -> Option<u32> {
+        self.table
+            .find(u128::from(u32::from(*ip)))
+            .map(|(_, value)| read_asn(value))
+    }
+
+    /// Lookup ASN and the matched range by IPv4 address
+    pub fn lookup_range(&self, ip: &Ipv4Addr) -> Option<(RangeInclusive<Ipv4Addr>, u32)> {
+        self.table
+            .find(u128::from(u32::from(*ip)))
+            .map(|(range, value)| (to_addr_range(range), read_asn(value)))
+    }
+
+    /// Number of ranges
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    /// Get all entries as an iterator
+    pub fn all(&self) -> impl Iterator<Item = Ipv4AsnEntry> + '_ {
+        self.table.iter().map(|(range, value)| Ipv4AsnEntry {
+            ip_from: *range.start() as u32,
+            ip_to: *range.end() as u32,
+            asn: read_asn(value),
+        })
+    }
+}
+
+fn to_addr_range(range: RangeInclusive<u128>) -> RangeInclusive<Ipv4Addr> {
+    Ipv4Addr::from(*range.start() as u32)..=Ipv4Addr::from(*range.end() as u32)
+}
+
+fn read_asn(value: &[u8]) -> u32 {
+    let mut buf = [0u8; 4];
+    buf.copy_from_slice(value);
+    u32::from_le_bytes(buf)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ndb_core::bin;
     use std::net::Ipv4Addr;
 
     #[test]
@@ -100,7 +393,7 @@ mod tests {
             },
         ];
 
-        let db = Ipv4AsnDb::from_entries(entries);
+        let db = Ipv4AsnDb::from_entries(entries).unwrap();
 
         // 10.0.0.1
         let ip1 = Ipv4Addr::new(10, 0, 0, 1);
@@ -115,6 +408,116 @@ mod tests {
         assert_eq!(db.lookup(&ip3), None);
     }
 
+    #[test]
+    fn test_ipv4_asn_lookup_range() {
+        let entries = vec![Ipv4AsnEntry {
+            ip_from: 167772161, // 10.0.0.1
+            ip_to: 167772166,   // 10.0.0.6
+            asn: 64500,
+        }];
+        let db = Ipv4AsnDb::from_entries(entries).unwrap();
+
+        let ip = Ipv4Addr::new(10, 0, 0, 3);
+        let (range, asn) = db.lookup_range(&ip).unwrap();
+        assert_eq!(
+            range,
+            Ipv4Addr::new(10, 0, 0, 1)..=Ipv4Addr::new(10, 0, 0, 6)
+        );
+        assert_eq!(asn, &64500);
+
+        let (cidrs, _) = db.lookup_cidrs(&ip).unwrap();
+        let cidrs: Vec<String> = cidrs.iter().map(|n| n.to_string()).collect();
+        assert_eq!(
+            cidrs,
+            ["10.0.0.1/32", "10.0.0.2/31", "10.0.0.4/31", "10.0.0.6/32"]
+        );
+
+        assert!(db.lookup_range(&Ipv4Addr::new(10, 0, 0, 7)).is_none());
+    }
+
+    #[test]
+    fn test_ipv4_asn_invalid_range() {
+        let entries = vec![Ipv4AsnEntry {
+            ip_from: 167772166, // 10.0.0.6
+            ip_to: 167772161,   // 10.0.0.1
+            asn: 64500,
+        }];
+        assert!(matches!(
+            Ipv4AsnDb::from_entries(entries),
+            Err(Error::InvalidRange { .. })
+        ));
+    }
+
+    #[test]
+    fn test_ipv4_asn_from_csv_validated() {
+        // 10.0.0.0-10.0.0.255, an overlapping 10.0.0.128-10.0.1.255 and ASN 0
+        let csv = "ip_from,ip_to,asn\n\
+                   167772160,167772415,64500\n\
+                   167772288,167772671,64501\n\
+                   167772672,167772927,0\n";
+        match Ipv4AsnDb::from_csv_validated(csv.as_bytes(), ValidationMode::Reject) {
+            Err(Error::Validation(report)) => {
+                assert_eq!(report.count(IssueKind::Overlap), 1);
+                assert_eq!(report.count(IssueKind::ZeroAsn), 1);
+                assert_eq!(report.issues[0].line, 3);
+            }
+            _ => panic!("Expected a validation error"),
+        }
+
+        let (db, report) =
+            Ipv4AsnDb::from_csv_validated(csv.as_bytes(), ValidationMode::Repair).unwrap();
+        assert_eq!((report.repaired, report.dropped), (1, 1));
+        assert_eq!(db.lookup(&Ipv4Addr::new(10, 0, 0, 200)), Some(&64500));
+        assert_eq!(db.lookup(&Ipv4Addr::new(10, 0, 1, 0)), Some(&64501));
+        assert_eq!(db.lookup(&Ipv4Addr::new(10, 0, 2, 0)), None);
+    }
+
+    #[test]
+    fn test_ipv4_ranges_for_asn() {
+        let entries = vec![
+            Ipv4AsnEntry {
+                ip_from: 167772160, // 10.0.0.0
+                ip_to: 167772287,   // 10.0.0.127
+                asn: 64500,
+            },
+            Ipv4AsnEntry {
+                ip_from: 167772288, // 10.0.0.128
+                ip_to: 167772415,   // 10.0.0.255
+                asn: 64500,
+            },
+            Ipv4AsnEntry {
+                ip_from: 167772416, // 10.0.1.0
+                ip_to: 167772671,   // 10.0.1.255
+                asn: 64501,
+            },
+            Ipv4AsnEntry {
+                ip_from: 3232235520, // 192.168.0.0
+                ip_to: 3232235775,   // 192.168.0.255
+                asn: 64500,
+            },
+        ];
+        let db = Ipv4AsnDb::from_entries(entries).unwrap();
+        let indexed = Ipv4AsnDb::from_entries(db.entries())
+            .unwrap()
+            .with_asn_index();
+
+        for db in [&db, &indexed] {
+            let ranges: Vec<_> = db.ranges_for_asn(64500).collect();
+            assert_eq!(
+                ranges,
+                vec![
+                    Ipv4Addr::new(10, 0, 0, 0)..=Ipv4Addr::new(10, 0, 0, 255),
+                    Ipv4Addr::new(192, 168, 0, 0)..=Ipv4Addr::new(192, 168, 0, 255),
+                ]
+            );
+            let cidrs: Vec<String> = db.cidrs_for_asn(64500).map(|n| n.to_string()).collect();
+            assert_eq!(cidrs, ["10.0.0.0/24", "192.168.0.0/24"]);
+            assert_eq!(db.address_count_for_asn(64500), 512);
+            assert_eq!(db.ranges_for_asn(64502).count(), 0);
+            assert_eq!(db.address_count_for_asn(64502), 0);
+        }
+    }
+
     #[test]
     fn test_ipv4_asn_entries_roundtrip() {
         let entries = vec![
@@ -129,7 +532,7 @@ mod tests {
                 asn: 2000,
             },
         ];
-        let db = Ipv4AsnDb::from_entries(entries.clone());
+        let db = Ipv4AsnDb::from_entries(entries.clone()).unwrap();
         let out_entries = db.entries();
 
         let mut expected = entries;
@@ -139,4 +542,73 @@ mod tests {
 
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn test_ipv4_asn_load_bin() {
+        let entries = vec![Ipv4AsnEntry {
+            ip_from: 16843008, // 1.1.1.0
+            ip_to: 16843263,   // 1.1.1.255
+            asn: 13335,
+        }];
+        let header = bin::BinHeader::new(DatasetKind::Ipv4Asn, SCHEMA_VERSION, "test", 1);
+        let buf = bin::encode_entries(&header, &entries).unwrap();
+
+        let db = Ipv4AsnDb::from_bin_reader(buf.as_slice()).unwrap();
+        assert_eq!(db.lookup(&Ipv4Addr::new(1, 1, 1, 1)), Some(&13335));
+
+        let path =
+            std::env::temp_dir().join(format!("ndb-test-{}-{}", std::process::id(), BIN_NAME));
+        std::fs::write(&path, &buf).unwrap();
+        let db = Ipv4AsnDb::from_path(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(db.lookup(&Ipv4Addr::new(1, 1, 1, 1)), Some(&13335));
+
+        assert!(matches!(Ipv4AsnDb::from_path(&path), Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn test_ipv4_asn_flat_lookup() {
+        let entries = vec![
+            Ipv4AsnEntry {
+                ip_from: 16843008, // 1.1.1.0
+                ip_to: 16843263,   // 1.1.1.255
+                asn: 13335,
+            },
+            Ipv4AsnEntry {
+                ip_from: 134744064, // 8.8.8.0
+                ip_to: 134744319,   // 8.8.8.255
+                asn: 15169,
+            },
+        ];
+        let db = Ipv4AsnDb::from_entries(entries.clone()).unwrap();
+        let mut flat = Vec::new();
+        let len = db.write_flat(&mut flat).unwrap();
+        assert_eq!(len, flat.len());
+
+        let path =
+            std::env::temp_dir().join(format!("ndb-test-{}-{}", std::process::id(), FLAT_NAME));
+        std::fs::write(&path, &flat).unwrap();
+        let mmap_db = Ipv4AsnDb::open_mmap(&path).unwrap();
+        let vec_db = Ipv4AsnFlatDb::from_vec(flat).unwrap();
+
+        for flat_db in [&vec_db, &mmap_db] {
+            assert_eq!(flat_db.len(), 2);
+            assert_eq!(flat_db.lookup(&Ipv4Addr::new(1, 1, 1, 1)), Some(13335));
+            assert_eq!(flat_db.lookup(&Ipv4Addr::new(1, 1, 1, 0)), Some(13335));
+            assert_eq!(flat_db.lookup(&Ipv4Addr::new(8, 8, 8, 255)), Some(15169));
+            // Between the two ranges
+            assert_eq!(flat_db.lookup(&Ipv4Addr::new(1, 1, 2, 0)), None);
+            assert_eq!(flat_db.lookup(&Ipv4Addr::new(8, 8, 7, 255)), None);
+            assert_eq!(
+                flat_db.lookup_range(&Ipv4Addr::new(8, 8, 8, 8)),
+                Some((
+                    Ipv4Addr::new(8, 8, 8, 0)..=Ipv4Addr::new(8, 8, 8, 255),
+                    15169
+                ))
+            );
+            assert_eq!(flat_db.all().collect::<Vec<_>>(), entries);
+        }
+        drop(mmap_db);
+        std::fs::remove_file(&path).unwrap();
+    }
 }
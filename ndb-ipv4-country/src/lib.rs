@@ -1,10 +1,38 @@
-use anyhow::Result;
+use ndb_core::bin::DatasetKind;
+use ndb_core::flat::{self, FlatBytes, FlatLayout, FlatTable};
+use ndb_core::utils::net;
+use ndb_core::validate::{self, IssueKind, RangeRecord, ValidationMode, ValidationReport};
+use ndb_core::{NdbDataset, Result};
 use rangemap::RangeInclusiveMap;
 use serde::{Deserialize, Serialize};
-use std::{io::Read, net::Ipv4Addr};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::Ipv4Addr;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+pub use ipnet::Ipv4Net;
+pub use ndb_core::Error;
 
 pub const CSV_NAME: &str = "ipv4-country.csv";
 pub const BIN_NAME: &str = "ipv4-country.bin";
+/// File name of the flat (memory-mappable) layout
+pub const FLAT_NAME: &str = "ipv4-country.flat";
+/// Version of the entry schema written to BIN files
+pub const SCHEMA_VERSION: u16 = 1;
+
+#[cfg(all(feature = "bundled", not(feature = "bundled-zstd")))]
+static BUNDLED_BIN: &[u8] = include_bytes!("../data/ipv4-country.bin");
+
+#[cfg(feature = "bundled-zstd")]
+static BUNDLED_BIN: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/ipv4-country.bin.zst"));
+
+const FLAT_LAYOUT: FlatLayout = FlatLayout {
+    kind: DatasetKind::Ipv4Country,
+    key_size: 4,
+    value_size: 2,
+    ascii_values: true,
+};
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct Ipv4CountryEntry {
@@ -13,46 +41,143 @@ pub struct Ipv4CountryEntry {
     pub country_code: String,
 }
 
+impl RangeRecord for Ipv4CountryEntry {
+    fn bounds(&self) -> (u128, u128) {
+        (u128::from(self.ip_from), u128::from(self.ip_to))
+    }
+
+    fn set_bounds(&mut self, start: u128, end: u128) {
+        // Bounds come from `bounds`, so they fit in `u32`
+        self.ip_from = start as u32;
+        self.ip_to = end as u32;
+    }
+
+    fn format_bound(bound: u128) -> String {
+        Ipv4Addr::from(bound as u32).to_string()
+    }
+
+    fn format_value(&self) -> String {
+        self.country_code.clone()
+    }
+
+    fn check_value(&self) -> Option<IssueKind> {
+        (!validate::is_iso_country_code(&self.country_code))
+            .then_some(IssueKind::InvalidCountryCode)
+    }
+
+    /// Upper-case codes like `jp`; other non-ISO codes are dropped
+    fn repair_value(&mut self) -> bool {
+        let code = self.country_code.trim().to_ascii_uppercase();
+        let valid = validate::is_iso_country_code(&code);
+        if valid {
+            self.country_code = code;
+        }
+        valid
+    }
+
+    fn same_value(&self, other: &Self) -> bool {
+        self.country_code == other.country_code
+    }
+}
+
 /// Represents the IPv4 Country database
 pub struct Ipv4CountryDb {
     inner_range: RangeInclusiveMap<u32, String>,
+    country_index: Option<HashMap<String, Vec<RangeInclusive<u32>>>>,
 }
 
 impl Ipv4CountryDb {
     /// Load database from a CSV reader
-    pub fn from_csv<R: Read>(reader: R) -> Result<Self, csv::Error> {
-        let mut rdr = csv::Reader::from_reader(reader);
-        let mut range_map: RangeInclusiveMap<u32, String> = RangeInclusiveMap::new();
-        for result in rdr.deserialize::<Ipv4CountryEntry>() {
-            let entry = result?;
-            range_map.insert(entry.ip_from..=entry.ip_to, entry.country_code);
+    pub fn from_csv<R: Read>(reader: R) -> Result<Self> {
+        <Self as NdbDataset>::from_csv(reader)
+    }
+
+    /// Load database from a CSV reader, checking for overlapping, inverted and duplicate
+    /// ranges and invalid values. See `ndb_core::validate::validate_ranges`.
+    pub fn from_csv_validated<R: Read>(
+        reader: R,
+        mode: ValidationMode,
+    ) -> Result<(Self, ValidationReport)> {
+        <Self as NdbDataset>::from_csv_validated(reader, mode)
+    }
+
+    /// Create a new IPv4 Country database from a vector of entries
+    pub fn from_entries(entries: Vec<Ipv4CountryEntry>) -> Result<Self> {
+        let mut inner_range = RangeInclusiveMap::new();
+        for entry in entries {
+            if entry.ip_from > entry.ip_to {
+                return Err(Error::InvalidRange {
+                    start: Ipv4Addr::from(entry.ip_from).into(),
+                    end: Ipv4Addr::from(entry.ip_to).into(),
+                });
+            }
+            inner_range.insert(entry.ip_from..=entry.ip_to, entry.country_code);
         }
         Ok(Self {
-            inner_range: range_map,
+            inner_range,
+            country_index: None,
         })
     }
 
-    /// Create a new IPv4 Country database from a vector of entries
-    pub fn from_entries(entries: Vec<Ipv4CountryEntry>) -> Self {
-        let inner_range = entries
-            .into_iter()
-            .map(|entry| (entry.ip_from..=entry.ip_to, entry.country_code))
-            .collect();
-        Self { inner_range }
+    /// Create a new IPv4 Country database from a binary slice
+    pub fn from_slice(slice: &[u8]) -> Result<Self> {
+        <Self as NdbDataset>::from_slice(slice)
     }
 
-    /// Create a new IPv4 Country database from a binary slice
-    fn from_slice(slice: &[u8]) -> Result<Self> {
-        let (entries, _): (Vec<Ipv4CountryEntry>, _) =
-            bincode::serde::decode_from_slice(slice, bincode::config::standard())?;
-        Ok(Self::from_entries(entries))
+    /// Load database from a reader of a BIN file
+    pub fn from_bin_reader<R: Read>(reader: R) -> Result<Self> {
+        <Self as NdbDataset>::from_bin_reader(reader)
+    }
+
+    /// Load database from a BIN file (e.g. one written by `ndb-cli update`)
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        <Self as NdbDataset>::from_path(path)
     }
 
     /// Load embedded (bundled) database
     #[cfg(feature = "bundled")]
     pub fn bundled() -> Self {
-        static BIN_DATA: &[u8] = include_bytes!("../data/ipv4-country.bin");
-        Self::from_slice(BIN_DATA).expect("Failed to load bundled ipv4-country.bin")
+        Self::try_bundled().expect("Failed to load bundled ipv4-country.bin")
+    }
+
+    /// Load embedded (bundled) database, returning an error instead of panicking
+    #[cfg(feature = "bundled")]
+    pub fn try_bundled() -> Result<Self> {
+        Self::from_slice(BUNDLED_BIN)
+    }
+
+    /// Get the process-wide shared bundled database, loaded once on first use
+    #[cfg(feature = "bundled")]
+    pub fn bundled_static() -> &'static Self {
+        static BUNDLED: std::sync::OnceLock<Ipv4CountryDb> = std::sync::OnceLock::new();
+        BUNDLED.get_or_init(Self::bundled)
+    }
+
+    /// Memory-map a flat database file (e.g. one written by `ndb-cli update --flat`)
+    pub fn open_mmap<P: AsRef<Path>>(path: P) -> Result<Ipv4CountryFlatDb> {
+        Ipv4CountryFlatDb::open_mmap(path)
+    }
+
+    /// Write the database in the flat layout to `writer`, returning the number of bytes written
+    pub fn write_flat<W: Write>(&self, writer: W) -> Result<usize> {
+        let records: Vec<_> = self
+            .inner_range
+            .iter()
+            .map(|(range, code)| {
+                (
+                    u128::from(*range.start())..=u128::from(*range.end()),
+                    code.as_bytes(),
+                )
+            })
+            .collect();
+        flat::write_flat(FLAT_LAYOUT, &records, writer)
+    }
+
+    /// Encode the database into a flat database image
+    pub fn to_flat(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.write_flat(&mut buf)?;
+        Ok(buf)
     }
 
     /// Get country code by u32 IP address
@@ -66,6 +191,87 @@ impl Ipv4CountryDb {
         self.inner_range.get(&ip_u32)
     }
 
+    /// Lookup country code and the matched `ip_from..=ip_to` range by IPv4 address.
+    /// Adjacent ranges with the same country code are returned as one range.
+    pub fn lookup_range(&self, ip: &Ipv4Addr) -> Option<(RangeInclusive<Ipv4Addr>, &String)> {
+        self.inner_range
+            .get_key_value(&u32::from(*ip))
+            .map(|(range, value)| {
+                (
+                    Ipv4Addr::from(*range.start())..=Ipv4Addr::from(*range.end()),
+                    value,
+                )
+            })
+    }
+
+    /// Lookup country code and the minimal CIDR prefixes of the matched range by IPv4 address
+    pub fn lookup_cidrs(&self, ip: &Ipv4Addr) -> Option<(Vec<Ipv4Net>, &String)> {
+        self.lookup_range(ip)
+            .map(|(range, value)| (net::ipv4_cidrs(&range), value))
+    }
+
+    /// Build the country code to ranges index used by `ranges_for_country`.
+    /// Without the index, reverse lookups scan all ranges.
+    pub fn build_country_index(&mut self) {
+        let mut index: HashMap<String, Vec<RangeInclusive<u32>>> = HashMap::new();
+        for (range, code) in self.inner_range.iter() {
+            let ranges = index.entry(code.to_ascii_uppercase()).or_default();
+            push_merged(ranges, range.clone());
+        }
+        self.country_index = Some(index);
+    }
+
+    /// Build the country index and return the database
+    pub fn with_country_index(mut self) -> Self {
+        self.build_country_index();
+        self
+    }
+
+    /// Get all ranges of a country code (case-insensitive) in ascending order.
+    /// Adjacent ranges are merged into one range.
+    pub fn ranges_for_country(
+        &self,
+        country_code: &str,
+    ) -> Box<dyn Iterator<Item = RangeInclusive<Ipv4Addr>> + '_> {
+        let to_addr = |range: &RangeInclusive<u32>| {
+            Ipv4Addr::from(*range.start())..=Ipv4Addr::from(*range.end())
+        };
+        match &self.country_index {
+            Some(index) => Box::new(
+                index
+                    .get(&country_code.to_ascii_uppercase())
+                    .into_iter()
+                    .flatten()
+                    .map(to_addr),
+            ),
+            None => {
+                let mut ranges = Vec::new();
+                for (range, code) in self.inner_range.iter() {
+                    if code.eq_ignore_ascii_case(country_code) {
+                        push_merged(&mut ranges, range.clone());
+                    }
+                }
+                Box::new(ranges.into_iter().map(move |range| to_addr(&range)))
+            }
+        }
+    }
+
+    /// Get the aggregated CIDR prefixes of all ranges of a country code
+    pub fn cidrs_for_country(&self, country_code: &str) -> impl Iterator<Item = Ipv4Net> {
+        let cidrs: Vec<Ipv4Net> = self
+            .ranges_for_country(country_code)
+            .flat_map(|range| net::ipv4_cidrs(&range))
+            .collect();
+        Ipv4Net::aggregate(&cidrs).into_iter()
+    }
+
+    /// Get the total number of addresses in all ranges of a country code
+    pub fn address_count_for_country(&self, country_code: &str) -> u64 {
+        self.ranges_for_country(country_code)
+            .map(|range| net::ipv4_range_len(&range))
+            .fold(0, u64::saturating_add)
+    }
+
     /// Get all country entries as an iterator
     pub fn all(&self) -> impl Iterator<Item = Ipv4CountryEntry> + '_ {
         self.inner_range
@@ -83,6 +289,119 @@ impl Ipv4CountryDb {
     }
 }
 
+/// Append `range` to the ascending `ranges`, merging it with the last range if adjacent
+fn push_merged(ranges: &mut Vec<RangeInclusive<u32>>, range: RangeInclusive<u32>) {
+    if let Some(last) = ranges.last_mut() {
+        if last.end().checked_add(1) == Some(*range.start()) {
+            *last = *last.start()..=*range.end();
+            return;
+        }
+    }
+    ranges.push(range);
+}
+
+impl NdbDataset for Ipv4CountryDb {
+    type Entry = Ipv4CountryEntry;
+
+    const KIND: DatasetKind = DatasetKind::Ipv4Country;
+    const CSV_NAME: &'static str = CSV_NAME;
+    const BIN_NAME: &'static str = BIN_NAME;
+    const SCHEMA_VERSION: u16 = SCHEMA_VERSION;
+    const FLAT_NAME: Option<&'static str> = Some(FLAT_NAME);
+
+    fn from_entries(entries: Vec<Ipv4CountryEntry>) -> Result<Self> {
+        Ipv4CountryDb::from_entries(entries)
+    }
+
+    fn entries(&self) -> Vec<Ipv4CountryEntry> {
+        Ipv4CountryDb::entries(self)
+    }
+
+    fn validate_entries(
+        entries: Vec<(u64, Ipv4CountryEntry)>,
+        mode: ValidationMode,
+    ) -> Result<(Vec<Ipv4CountryEntry>, ValidationReport)> {
+        validate::validate_ranges(entries, mode)
+    }
+
+    fn write_flat<W: Write>(&self, writer: W) -> Option<Result<usize>> {
+        Some(Ipv4CountryDb::write_flat(self, writer))
+    }
+
+    #[cfg(feature = "bundled")]
+    fn bundled_bin() -> Option<&'static [u8]> {
+        Some(BUNDLED_BIN)
+    }
+}
+
+/// Zero-copy IPv4 country database searched in place over the flat layout
+pub struct Ipv4CountryFlatDb {
+    table: FlatTable,
+}
+
+impl Ipv4CountryFlatDb {
+    /// Use a flat database image in static memory (e.g. from `include_bytes!`)
+    pub fn from_static(bytes: &'static [u8]) -> Result<Self> {
+        Self::from_bytes(FlatBytes::Static(bytes))
+    }
+
+    /// Use a flat database image held in memory
+    pub fn from_vec(bytes: Vec<u8>) -> Result<Self> {
+        Self::from_bytes(FlatBytes::Owned(bytes))
+    }
+
+    /// Memory-map a flat database file
+    pub fn open_mmap<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let table = FlatTable::open_mmap(path, FLAT_LAYOUT)?;
+        Ok(Self { table })
+    }
+
+    fn from_bytes(bytes: FlatBytes) -> Result<Self> {
+        let table = FlatTable::new(bytes, FLAT_LAYOUT)?;
+        Ok(Self { table })
+    }
+
+    /// Lookup country code by IPv4 address
+    pub fn lookup(&self, ip: &Ipv4Addr) -> Option<&str> {
+        self.table
+            .find(u128::from(u32::from(*ip)))
+            .map(|(_, value)| read_country_code(value))
+    }
+
+    /// Lookup country code and the matched range by IPv4 address
+    pub fn lookup_range(&self, ip: &Ipv4Addr) -> Option<(RangeInclusive<Ipv4Addr>, &str)> {
+        self.table
+            .find(u128::from(u32::from(*ip)))
+            .map(|(range, value)| (to_addr_range(range), read_country_code(value)))
+    }
+
+    /// Number of ranges
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    /// Get all entries as an iterator
+    pub fn all(&self) -> impl Iterator<Item = Ipv4CountryEntry> + '_ {
+        self.table.iter().map(|(range, value)| Ipv4CountryEntry {
+            ip_from: *range.start() as u32,
+            ip_to: *range.end() as u32,
+            country_code: read_country_code(value).to_string(),
+        })
+    }
+}
+
+fn to_addr_range(range: RangeInclusive<u128>) -> RangeInclusive<Ipv4Addr> {
+    Ipv4Addr::from(*range.start() as u32)..=Ipv4Addr::from(*range.end() as u32)
+}
+
+fn read_country_code(value: &[u8]) -> &str {
+    std::str::from_utf8(value).expect("flat values are checked to be ASCII when opened")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -112,4 +431,94 @@ mod tests {
             assert!(!entry.country_code.is_empty());
         }
     }
+
+    #[test]
+    fn test_ipv4_ranges_for_country() {
+        let entries = vec![
+            Ipv4CountryEntry {
+                ip_from: 167772160, // 10.0.0.0
+                ip_to: 167772415,   // 10.0.0.255
+                country_code: "JP".into(),
+            },
+            Ipv4CountryEntry {
+                ip_from: 167772416, // 10.0.1.0
+                ip_to: 167772927,   // 10.0.2.255
+                country_code: "jp".into(),
+            },
+            Ipv4CountryEntry {
+                ip_from: 167772928, // 10.0.3.0
+                ip_to: 167773183,   // 10.0.3.255
+                country_code: "US".into(),
+            },
+            Ipv4CountryEntry {
+                ip_from: 3232235520, // 192.168.0.0
+                ip_to: 3232235775,   // 192.168.0.255
+                country_code: "JP".into(),
+            },
+        ];
+        let db = Ipv4CountryDb::from_entries(entries).unwrap();
+        let indexed = Ipv4CountryDb::from_entries(db.entries())
+            .unwrap()
+            .with_country_index();
+
+        for db in [&db, &indexed] {
+            let ranges: Vec<_> = db.ranges_for_country("JP").collect();
+            assert_eq!(
+                ranges,
+                vec![
+                    Ipv4Addr::new(10, 0, 0, 0)..=Ipv4Addr::new(10, 0, 2, 255),
+                    Ipv4Addr::new(192, 168, 0, 0)..=Ipv4Addr::new(192, 168, 0, 255),
+                ]
+            );
+            let cidrs: Vec<String> = db.cidrs_for_country("jp").map(|n| n.to_string()).collect();
+            assert_eq!(cidrs, ["10.0.0.0/23", "10.0.2.0/24", "192.168.0.0/24"]);
+            assert_eq!(db.address_count_for_country("JP"), 1024);
+            assert_eq!(db.ranges_for_country("DE").count(), 0);
+        }
+    }
+
+    #[test]
+    fn test_ipv4_country_open_mmap() {
+        let entries = vec![
+            Ipv4CountryEntry {
+                ip_from: 167772160, // 10.0.0.0
+                ip_to: 167772415,   // 10.0.0.255
+                country_code: "JP".into(),
+            },
+            Ipv4CountryEntry {
+                ip_from: 3232235520, // 192.168.0.0
+                ip_to: 3232235775,   // 192.168.0.255
+                country_code: "US".into(),
+            },
+        ];
+        let db = Ipv4CountryDb::from_entries(entries.clone()).unwrap();
+        let path =
+            std::env::temp_dir().join(format!("ndb-test-{}-{}", std::process::id(), FLAT_NAME));
+        std::fs::write(&path, db.to_flat().unwrap()).unwrap();
+
+        let flat_db = Ipv4CountryDb::open_mmap(&path).unwrap();
+        assert_eq!(flat_db.lookup(&Ipv4Addr::new(10, 0, 0, 1)), Some("JP"));
+        assert_eq!(flat_db.lookup(&Ipv4Addr::new(192, 168, 0, 255)), Some("US"));
+        assert_eq!(flat_db.lookup(&Ipv4Addr::new(10, 0, 1, 0)), None);
+        assert_eq!(flat_db.all().collect::<Vec<_>>(), entries);
+        drop(flat_db);
+        std::fs::remove_file(&path).unwrap();
+
+        let invalid = Ipv4CountryDb::from_entries(vec![Ipv4CountryEntry {
+            ip_from: 0,
+            ip_to: 0,
+            country_code: "JPN".into(),
+        }])
+        .unwrap();
+        assert!(matches!(invalid.to_flat(), Err(Error::InvalidFlat(_))));
+
+        // A corrupt country code fails to open instead of reading as an empty code
+        let mut corrupt = db.to_flat().unwrap();
+        let last = corrupt.len() - 1;
+        corrupt[last] = 0xFF;
+        assert!(matches!(
+            Ipv4CountryFlatDb::from_vec(corrupt),
+            Err(Error::InvalidFlat(_))
+        ));
+    }
 }
@@ -0,0 +1,6 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "bundled-zstd")]
+    ndb_core::compress::compress_bundled("ipv6-asn.bin")
+        .expect("Failed to compress bundled BIN file");
+}
@@ -1,10 +1,38 @@
-use anyhow::Result;
+use ndb_core::bin::DatasetKind;
+use ndb_core::flat::{self, FlatBytes, FlatLayout, FlatTable};
+use ndb_core::utils::net;
+use ndb_core::validate::{self, IssueKind, RangeRecord, ValidationMode, ValidationReport};
+use ndb_core::{NdbDataset, Result};
 use rangemap::RangeInclusiveMap;
 use serde::{Deserialize, Serialize};
-use std::{io::Read, net::Ipv6Addr};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::Ipv6Addr;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+pub use ipnet::Ipv6Net;
+pub use ndb_core::Error;
 
 pub const CSV_NAME: &str = "ipv6-asn.csv";
 pub const BIN_NAME: &str = "ipv6-asn.bin";
+/// File name of the flat (memory-mappable) layout
+pub const FLAT_NAME: &str = "ipv6-asn.flat";
+/// Version of the entry schema written to BIN files
+pub const SCHEMA_VERSION: u16 = 1;
+
+#[cfg(all(feature = "bundled", not(feature = "bundled-zstd")))]
+static BUNDLED_BIN: &[u8] = include_bytes!("../data/ipv6-asn.bin");
+
+#[cfg(feature = "bundled-zstd")]
+static BUNDLED_BIN: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/ipv6-asn.bin.zst"));
+
+const FLAT_LAYOUT: FlatLayout = FlatLayout {
+    kind: DatasetKind::Ipv6Asn,
+    key_size: 16,
+    value_size: 4,
+    ascii_values: false,
+};
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct Ipv6AsnEntry {
@@ -13,46 +41,130 @@ pub struct Ipv6AsnEntry {
     pub asn: u32,
 }
 
+impl RangeRecord for Ipv6AsnEntry {
+    fn bounds(&self) -> (u128, u128) {
+        (self.ip_from, self.ip_to)
+    }
+
+    fn set_bounds(&mut self, start: u128, end: u128) {
+        self.ip_from = start;
+        self.ip_to = end;
+    }
+
+    fn format_bound(bound: u128) -> String {
+        Ipv6Addr::from(bound).to_string()
+    }
+
+    fn format_value(&self) -> String {
+        format!("AS{}", self.asn)
+    }
+
+    fn check_value(&self) -> Option<IssueKind> {
+        (self.asn == 0).then_some(IssueKind::ZeroAsn)
+    }
+
+    fn repair_value(&mut self) -> bool {
+        false
+    }
+
+    fn same_value(&self, other: &Self) -> bool {
+        self.asn == other.asn
+    }
+}
+
 /// Represents the IPv6 ASN database
 pub struct Ipv6AsnDb {
     inner_range: RangeInclusiveMap<u128, u32>,
+    asn_index: Option<HashMap<u32, Vec<RangeInclusive<u128>>>>,
 }
 
 impl Ipv6AsnDb {
     /// Load database from a CSV reader
-    pub fn from_csv<R: Read>(reader: R) -> Result<Self, csv::Error> {
-        let mut rdr = csv::Reader::from_reader(reader);
-        let mut range_map: RangeInclusiveMap<u128, u32> = RangeInclusiveMap::new();
-        for result in rdr.deserialize::<Ipv6AsnEntry>() {
-            let entry = result?;
-            range_map.insert(entry.ip_from..=entry.ip_to, entry.asn);
+    pub fn from_csv<R: Read>(reader: R) -> Result<Self> {
+        <Self as NdbDataset>::from_csv(reader)
+    }
+
+    /// Load database from a CSV reader, checking for overlapping, inverted and duplicate
+    /// ranges and invalid values. See `ndb_core::validate::validate_ranges`.
+    pub fn from_csv_validated<R: Read>(
+        reader: R,
+        mode: ValidationMode,
+    ) -> Result<(Self, ValidationReport)> {
+        <Self as NdbDataset>::from_csv_validated(reader, mode)
+    }
+
+    /// Create a new IPv6 ASN database from a vector of entries
+    pub fn from_entries(entries: Vec<Ipv6AsnEntry>) -> Result<Self> {
+        let mut inner_range = RangeInclusiveMap::new();
+        for entry in entries {
+            if entry.ip_from > entry.ip_to {
+                return Err(Error::InvalidRange {
+                    start: Ipv6Addr::from(entry.ip_from).into(),
+                    end: Ipv6Addr::from(entry.ip_to).into(),
+                });
+            }
+            inner_range.insert(entry.ip_from..=entry.ip_to, entry.asn);
         }
         Ok(Self {
-            inner_range: range_map,
+            inner_range,
+            asn_index: None,
         })
     }
 
-    /// Create a new IPv6 ASN database from a vector of entries
-    pub fn from_entries(entries: Vec<Ipv6AsnEntry>) -> Self {
-        let inner_range = entries
-            .into_iter()
-            .map(|entry| (entry.ip_from..=entry.ip_to, entry.asn))
-            .collect();
-        Self { inner_range }
+    /// Create a new IPv6 ASN database from a binary slice
+    pub fn from_slice(slice: &[u8]) -> Result<Self> {
+        <Self as NdbDataset>::from_slice(slice)
     }
 
-    /// Create a new IPv6 ASN database from a binary slice
-    fn from_slice(slice: &[u8]) -> Result<Self> {
-        let (entries, _): (Vec<Ipv6AsnEntry>, _) =
-            bincode::serde::decode_from_slice(slice, bincode::config::standard())?;
-        Ok(Self::from_entries(entries))
+    /// Load database from a reader of a BIN file
+    pub fn from_bin_reader<R: Read>(reader: R) -> Result<Self> {
+        <Self as NdbDataset>::from_bin_reader(reader)
+    }
+
+    /// Load database from a BIN file (e.g. one written by `ndb-cli update`)
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        <Self as NdbDataset>::from_path(path)
     }
 
     /// Load embedded (bundled) database
     #[cfg(feature = "bundled")]
     pub fn bundled() -> Self {
-        static BIN_DATA: &[u8] = include_bytes!("../data/ipv6-asn.bin");
-        Self::from_slice(BIN_DATA).expect("Failed to load bundled ipv6-asn.bin")
+        Self::try_bundled().expect("Failed to load bundled ipv6-asn.bin")
+    }
+
+    /// Load embedded (bundled) database, returning an error instead of panicking
+    #[cfg(feature = "bundled")]
+    pub fn try_bundled() -> Result<Self> {
+        Self::from_slice(BUNDLED_BIN)
+    }
+
+    /// Get the process-wide shared bundled database, loaded once on first use
+    #[cfg(feature = "bundled")]
+    pub fn bundled_static() -> &'static Self {
+        static BUNDLED: std::sync::OnceLock<Ipv6AsnDb> = std::sync::OnceLock::new();
+        BUNDLED.get_or_init(Self::bundled)
+    }
+
+    /// Memory-map a flat database file (e.g. one written by `ndb-cli update --flat`)
+    pub fn open_mmap<P: AsRef<Path>>(path: P) -> Result<Ipv6AsnFlatDb> {
+        Ipv6AsnFlatDb::open_mmap(path)
+    }
+
+    /// Write the database in the flat layout to `writer`, returning the number of bytes written
+    pub fn write_flat<W: Write>(&self, writer: W) -> Result<usize> {
+        let records: Vec<_> = self
+            .inner_range
+            .iter()
+            .map(|(range, asn)| (range.clone(), asn.to_le_bytes()))
+            .collect();
+        flat::write_flat(FLAT_LAYOUT, &records, writer)
+    }
+
+    /// Encode the database into a flat database image
+    pub fn to_flat(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.write_flat(&mut buf)?;
+        Ok(buf)
     }
 
     /// Get ASN by u128 IP address
@@ -66,6 +178,76 @@ impl Ipv6AsnDb {
         self.inner_range.get(&ip_u128)
     }
 
+    /// Lookup ASN and the matched `ip_from..=ip_to` range by IPv6 address.
+    /// Adjacent ranges with the same ASN are returned as one range.
+    pub fn lookup_range(&self, ip: &Ipv6Addr) -> Option<(RangeInclusive<Ipv6Addr>, &u32)> {
+        self.inner_range
+            .get_key_value(&u128::from(*ip))
+            .map(|(range, value)| {
+                (
+                    Ipv6Addr::from(*range.start())..=Ipv6Addr::from(*range.end()),
+                    value,
+                )
+            })
+    }
+
+    /// Lookup ASN and the minimal CIDR prefixes of the matched range by IPv6 address
+    pub fn lookup_cidrs(&self, ip: &Ipv6Addr) -> Option<(Vec<Ipv6Net>, &u32)> {
+        self.lookup_range(ip)
+            .map(|(range, value)| (net::ipv6_cidrs(&range), value))
+    }
+
+    /// Build the ASN to ranges index used by `ranges_for_asn`.
+    /// Without the index, reverse lookups scan all ranges.
+    pub fn build_asn_index(&mut self) {
+        let mut index: HashMap<u32, Vec<RangeInclusive<u128>>> = HashMap::new();
+        for (range, asn) in self.inner_range.iter() {
+            index.entry(*asn).or_default().push(range.clone());
+        }
+        self.asn_index = Some(index);
+    }
+
+    /// Build the ASN index and return the database
+    pub fn with_asn_index(mut self) -> Self {
+        self.build_asn_index();
+        self
+    }
+
+    /// Get all ranges of an ASN in ascending order
+    pub fn ranges_for_asn(
+        &self,
+        asn: u32,
+    ) -> Box<dyn Iterator<Item = RangeInclusive<Ipv6Addr>> + '_> {
+        let to_addr = |range: &RangeInclusive<u128>| {
+            Ipv6Addr::from(*range.start())..=Ipv6Addr::from(*range.end())
+        };
+        match &self.asn_index {
+            Some(index) => Box::new(index.get(&asn).into_iter().flatten().map(to_addr)),
+            None => Box::new(
+                self.inner_range
+                    .iter()
+                    .filter(move |(_, &value)| value == asn)
+                    .map(move |(range, _)| to_addr(range)),
+            ),
+        }
+    }
+
+    /// Get the aggregated CIDR prefixes of all ranges of an ASN
+    pub fn cidrs_for_asn(&self, asn: u32) -> impl Iterator<Item = Ipv6Net> {
+        let cidrs: Vec<Ipv6Net> = self
+            .ranges_for_asn(asn)
+            .flat_map(|range| net::ipv6_cidrs(&range))
+            .collect();
+        Ipv6Net::aggregate(&cidrs).into_iter()
+    }
+
+    /// Get the total number of addresses in all ranges of an ASN
+    pub fn address_count_for_asn(&self, asn: u32) -> u128 {
+        self.ranges_for_asn(asn)
+            .map(|range| net::ipv6_range_len(&range))
+            .fold(0, u128::saturating_add)
+    }
+
     /// Get all ASN entries as an iterator
     pub fn all(&self) -> impl Iterator<Item = Ipv6AsnEntry> + '_ {
         self.inner_range.iter().map(|(range, asn)| Ipv6AsnEntry {
@@ -81,6 +263,110 @@ impl Ipv6AsnDb {
     }
 }
 
+impl NdbDataset for Ipv6AsnDb {
+    type Entry = Ipv6AsnEntry;
+
+    const KIND: DatasetKind = DatasetKind::Ipv6Asn;
+    const CSV_NAME: &'static str = CSV_NAME;
+    const BIN_NAME: &'static str = BIN_NAME;
+    const SCHEMA_VERSION: u16 = SCHEMA_VERSION;
+    const FLAT_NAME: Option<&'static str> = Some(FLAT_NAME);
+
+    fn from_entries(entries: Vec<Ipv6AsnEntry>) -> Result<Self> {
+        Ipv6AsnDb::from_entries(entries)
+    }
+
+    fn entries(&self) -> Vec<Ipv6AsnEntry> {
+        Ipv6AsnDb::entries(self)
+    }
+
+    fn validate_entries(
+        entries: Vec<(u64, Ipv6AsnEntry)>,
+        mode: ValidationMode,
+    ) -> Result<(Vec<Ipv6AsnEntry>, ValidationReport)> {
+        validate::validate_ranges(entries, mode)
+    }
+
+    fn write_flat<W: Write>(&self, writer: W) -> Option<Result<usize>> {
+        Some(Ipv6AsnDb::write_flat(self, writer))
+    }
+
+    #[cfg(feature = "bundled")]
+    fn bundled_bin() -> Option<&'static [u8]> {
+        Some(BUNDLED_BIN)
+    }
+}
+
+/// Zero-copy IPv6 ASN database searched in place over the flat layout
+pub struct Ipv6AsnFlatDb {
+    table: FlatTable,
+}
+
+impl Ipv6AsnFlatDb {
+    /// Use a flat database image in static memory (e.g. from `include_bytes!`)
+    pub fn from_static(bytes: &'static [u8]) -> Result<Self> {
+        Self::from_bytes(FlatBytes::Static(bytes))
+    }
+
+    /// Use a flat database image held in memory
+    pub fn from_vec(bytes: Vec<u8>) -> Result<Self> {
+        Self::from_bytes(FlatBytes::Owned(bytes))
+    }
+
+    /// Memory-map a flat database file
+    pub fn open_mmap<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let table = FlatTable::open_mmap(path, FLAT_LAYOUT)?;
+        Ok(Self { table })
+    }
+
+    fn from_bytes(bytes: FlatBytes) -> Result<Self> {
+        let table = FlatTable::new(bytes, FLAT_LAYOUT)?;
+        Ok(Self { table })
+    }
+
+    /// Lookup ASN by IPv6 address
+    pub fn lookup(&self, ip: &Ipv6Addr) -> Option<u32> {
+        self.table
+            .find(u128::from(*ip))
+            .map(|(_, value)| read_asn(value))
+    }
+
+    /// Lookup ASN and the matched range by IPv6 address
+    pub fn lookup_range(&self, ip: &Ipv6Addr) -> Option<(RangeInclusive<Ipv6Addr>, u32)> {
+        self.table
+            .find(u128::from(*ip))
+            .map(|(range, value)| (to_addr_range(range), read_asn(value)))
+    }
+
+    /// Number of ranges
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    /// Get all entries as an iterator
+    pub fn all(&self) -> impl Iterator<Item = Ipv6AsnEntry> + '_ {
+        self.table.iter().map(|(range, value)| Ipv6AsnEntry {
+            ip_from: *range.start(),
+            ip_to: *range.end(),
+            asn: read_asn(value),
+        })
+    }
+}
+
+fn to_addr_range(range: RangeInclusive<u128>) -> RangeInclusive<Ipv6Addr> {
+    Ipv6Addr::from(*range.start())..=Ipv6Addr::from(*range.end())
+}
+
+fn read_asn(value: &[u8]) -> u32 {
+    let mut buf = [0u8; 4];
+    buf.copy_from_slice(value);
+    u32::from_le_bytes(buf)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -102,4 +388,35 @@ mod tests {
         let doc_result = db.lookup(&doc_ip);
         assert!(doc_result.is_none() || doc_result.is_some());
     }
+
+    #[test]
+    fn test_ipv6_ranges_for_asn() {
+        let db = Ipv6AsnDb::bundled();
+        let ip: Ipv6Addr = "2606:4700:4700::1111".parse().unwrap();
+
+        let ranges: Vec<_> = db.ranges_for_asn(13335).collect();
+        assert!(ranges.iter().any(|range| range.contains(&ip)));
+        assert!(db.cidrs_for_asn(13335).any(|net| net.contains(&ip)));
+        assert!(db.address_count_for_asn(13335) > 0);
+
+        let db = db.with_asn_index();
+        assert_eq!(db.ranges_for_asn(13335).collect::<Vec<_>>(), ranges);
+    }
+
+    #[test]
+    fn test_ipv6_asn_flat_lookup() {
+        let db = Ipv6AsnDb::bundled();
+        let flat_db = Ipv6AsnFlatDb::from_vec(db.to_flat().unwrap()).unwrap();
+        assert_eq!(flat_db.len(), db.all().count());
+
+        for ip in ["2606:4700:4700::1111", "2001:4860:4860::8888", "::1"] {
+            let ip: Ipv6Addr = ip.parse().unwrap();
+            assert_eq!(flat_db.lookup(&ip), db.lookup(&ip).copied());
+            assert_eq!(
+                flat_db.lookup_range(&ip),
+                db.lookup_range(&ip).map(|(range, &asn)| (range, asn))
+            );
+        }
+        assert!(flat_db.all().eq(db.all()));
+    }
 }
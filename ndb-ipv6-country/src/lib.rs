@@ -1,10 +1,38 @@
-use anyhow::Result;
+use ndb_core::bin::DatasetKind;
+use ndb_core::flat::{self, FlatBytes, FlatLayout, FlatTable};
+use ndb_core::utils::net;
+use ndb_core::validate::{self, IssueKind, RangeRecord, ValidationMode, ValidationReport};
+use ndb_core::{NdbDataset, Result};
 use rangemap::RangeInclusiveMap;
 use serde::{Deserialize, Serialize};
-use std::{io::Read, net::Ipv6Addr};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::Ipv6Addr;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+pub use ipnet::Ipv6Net;
+pub use ndb_core::Error;
 
 pub const CSV_NAME: &str = "ipv6-country.csv";
 pub const BIN_NAME: &str = "ipv6-country.bin";
+/// File name of the flat (memory-mappable) layout
+pub const FLAT_NAME: &str = "ipv6-country.flat";
+/// Version of the entry schema written to BIN files
+pub const SCHEMA_VERSION: u16 = 1;
+
+#[cfg(all(feature = "bundled", not(feature = "bundled-zstd")))]
+static BUNDLED_BIN: &[u8] = include_bytes!("../data/ipv6-country.bin");
+
+#[cfg(feature = "bundled-zstd")]
+static BUNDLED_BIN: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/ipv6-country.bin.zst"));
+
+const FLAT_LAYOUT: FlatLayout = FlatLayout {
+    kind: DatasetKind::Ipv6Country,
+    key_size: 16,
+    value_size: 2,
+    ascii_values: true,
+};
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct Ipv6CountryEntry {
@@ -13,46 +41,137 @@ pub struct Ipv6CountryEntry {
     pub country_code: String,
 }
 
+impl RangeRecord for Ipv6CountryEntry {
+    fn bounds(&self) -> (u128, u128) {
+        (self.ip_from, self.ip_to)
+    }
+
+    fn set_bounds(&mut self, start: u128, end: u128) {
+        self.ip_from = start;
+        self.ip_to = end;
+    }
+
+    fn format_bound(bound: u128) -> String {
+        Ipv6Addr::from(bound).to_string()
+    }
+
+    fn format_value(&self) -> String {
+        self.country_code.clone()
+    }
+
+    fn check_value(&self) -> Option<IssueKind> {
+        (!validate::is_iso_country_code(&self.country_code))
+            .then_some(IssueKind::InvalidCountryCode)
+    }
+
+    /// Upper-case codes like `jp`; other non-ISO codes are dropped
+    fn repair_value(&mut self) -> bool {
+        let code = self.country_code.trim().to_ascii_uppercase();
+        let valid = validate::is_iso_country_code(&code);
+        if valid {
+            self.country_code = code;
+        }
+        valid
+    }
+
+    fn same_value(&self, other: &Self) -> bool {
+        self.country_code == other.country_code
+    }
+}
+
 /// Represents the IPv6 Country database
 pub struct Ipv6CountryDb {
     inner_range: RangeInclusiveMap<u128, String>,
+    country_index: Option<HashMap<String, Vec<RangeInclusive<u128>>>>,
 }
 
 impl Ipv6CountryDb {
     /// Load database from a CSV reader
-    pub fn from_csv<R: Read>(reader: R) -> Result<Self, csv::Error> {
-        let mut rdr = csv::Reader::from_reader(reader);
-        let mut range_map: RangeInclusiveMap<u128, String> = RangeInclusiveMap::new();
-        for result in rdr.deserialize::<Ipv6CountryEntry>() {
-            let entry = result?;
-            range_map.insert(entry.ip_from..=entry.ip_to, entry.country_code);
+    pub fn from_csv<R: Read>(reader: R) -> Result<Self> {
+        <Self as NdbDataset>::from_csv(reader)
+    }
+
+    /// Load database from a CSV reader, checking for overlapping, inverted and duplicate
+    /// ranges and invalid values. See `ndb_core::validate::validate_ranges`.
+    pub fn from_csv_validated<R: Read>(
+        reader: R,
+        mode: ValidationMode,
+    ) -> Result<(Self, ValidationReport)> {
+        <Self as NdbDataset>::from_csv_validated(reader, mode)
+    }
+
+    /// Create a new IPv6 Country database from a vector of entries
+    pub fn from_entries(entries: Vec<Ipv6CountryEntry>) -> Result<Self> {
+        let mut inner_range = RangeInclusiveMap::new();
+        for entry in entries {
+            if entry.ip_from > entry.ip_to {
+                return Err(Error::InvalidRange {
+                    start: Ipv6Addr::from(entry.ip_from).into(),
+                    end: Ipv6Addr::from(entry.ip_to).into(),
+                });
+            }
+            inner_range.insert(entry.ip_from..=entry.ip_to, entry.country_code);
         }
         Ok(Self {
-            inner_range: range_map,
+            inner_range,
+            country_index: None,
         })
     }
 
-    /// Create a new IPv6 Country database from a vector of entries
-    pub fn from_entries(entries: Vec<Ipv6CountryEntry>) -> Self {
-        let inner_range = entries
-            .into_iter()
-            .map(|entry| (entry.ip_from..=entry.ip_to, entry.country_code))
-            .collect();
-        Self { inner_range }
+    /// Create a new IPv6 Country database from a binary slice
+    pub fn from_slice(slice: &[u8]) -> Result<Self> {
+        <Self as NdbDataset>::from_slice(slice)
     }
 
-    /// Create a new IPv6 Country database from a binary slice
-    fn from_slice(slice: &[u8]) -> Result<Self> {
-        let (entries, _): (Vec<Ipv6CountryEntry>, _) =
-            bincode::serde::decode_from_slice(slice, bincode::config::standard())?;
-        Ok(Self::from_entries(entries))
+    /// Load database from a reader of a BIN file
+    pub fn from_bin_reader<R: Read>(reader: R) -> Result<Self> {
+        <Self as NdbDataset>::from_bin_reader(reader)
+    }
+
+    /// Load database from a BIN file (e.g. one written by `ndb-cli update`)
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        <Self as NdbDataset>::from_path(path)
     }
 
     /// Load embedded (bundled) database
     #[cfg(feature = "bundled")]
     pub fn bundled() -> Self {
-        static BIN_DATA: &[u8] = include_bytes!("../data/ipv6-country.bin");
-        Self::from_slice(BIN_DATA).expect("Failed to load bundled ipv6-country.bin")
+        Self::try_bundled().expect("Failed to load bundled ipv6-country.bin")
+    }
+
+    /// Load embedded (bundled) database, returning an error instead of panicking
+    #[cfg(feature = "bundled")]
+    pub fn try_bundled() -> Result<Self> {
+        Self::from_slice(BUNDLED_BIN)
+    }
+
+    /// Get the process-wide shared bundled database, loaded once on first use
+    #[cfg(feature = "bundled")]
+    pub fn bundled_static() -> &'static Self {
+        static BUNDLED: std::sync::OnceLock<Ipv6CountryDb> = std::sync::OnceLock::new();
+        BUNDLED.get_or_init(Self::bundled)
+    }
+
+    /// Memory-map a flat database file (e.g. one written by `ndb-cli update --flat`)
+    pub fn open_mmap<P: AsRef<Path>>(path: P) -> Result<Ipv6CountryFlatDb> {
+        Ipv6CountryFlatDb::open_mmap(path)
+    }
+
+    /// Write the database in the flat layout to `writer`, returning the number of bytes written
+    pub fn write_flat<W: Write>(&self, writer: W) -> Result<usize> {
+        let records: Vec<_> = self
+            .inner_range
+            .iter()
+            .map(|(range, code)| (range.clone(), code.as_bytes()))
+            .collect();
+        flat::write_flat(FLAT_LAYOUT, &records, writer)
+    }
+
+    /// Encode the database into a flat database image
+    pub fn to_flat(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.write_flat(&mut buf)?;
+        Ok(buf)
     }
 
     /// Get country code by u128 IP address
@@ -66,6 +185,87 @@ impl Ipv6CountryDb {
         self.inner_range.get(&ip_u128)
     }
 
+    /// Lookup country code and the matched `ip_from..=ip_to` range by IPv6 address.
+    /// Adjacent ranges with the same country code are returned as one range.
+    pub fn lookup_range(&self, ip: &Ipv6Addr) -> Option<(RangeInclusive<Ipv6Addr>, &String)> {
+        self.inner_range
+            .get_key_value(&u128::from(*ip))
+            .map(|(range, value)| {
+                (
+                    Ipv6Addr::from(*range.start())..=Ipv6Addr::from(*range.end()),
+                    value,
+                )
+            })
+    }
+
+    /// Lookup country code and the minimal CIDR prefixes of the matched range by IPv6 address
+    pub fn lookup_cidrs(&self, ip: &Ipv6Addr) -> Option<(Vec<Ipv6Net>, &String)> {
+        self.lookup_range(ip)
+            .map(|(range, value)| (net::ipv6_cidrs(&range), value))
+    }
+
+    /// Build the country code to ranges index used by `ranges_for_country`.
+    /// Without the index, reverse lookups scan all ranges.
+    pub fn build_country_index(&mut self) {
+        let mut index: HashMap<String, Vec<RangeInclusive<u128>>> = HashMap::new();
+        for (range, code) in self.inner_range.iter() {
+            let ranges = index.entry(code.to_ascii_uppercase()).or_default();
+            push_merged(ranges, range.clone());
+        }
+        self.country_index = Some(index);
+    }
+
+    /// Build the country index and return the database
+    pub fn with_country_index(mut self) -> Self {
+        self.build_country_index();
+        self
+    }
+
+    /// Get all ranges of a country code (case-insensitive) in ascending order.
+    /// Adjacent ranges are merged into one range.
+    pub fn ranges_for_country(
+        &self,
+        country_code: &str,
+    ) -> Box<dyn Iterator<Item = RangeInclusive<Ipv6Addr>> + '_> {
+        let to_addr = |range: &RangeInclusive<u128>| {
+            Ipv6Addr::from(*range.start())..=Ipv6Addr::from(*range.end())
+        };
+        match &self.country_index {
+            Some(index) => Box::new(
+                index
+                    .get(&country_code.to_ascii_uppercase())
+                    .into_iter()
+                    .flatten()
+                    .map(to_addr),
+            ),
+            None => {
+                let mut ranges = Vec::new();
+                for (range, code) in self.inner_range.iter() {
+                    if code.eq_ignore_ascii_case(country_code) {
+                        push_merged(&mut ranges, range.clone());
+                    }
+                }
+                Box::new(ranges.into_iter().map(move |range| to_addr(&range)))
+            }
+        }
+    }
+
+    /// Get the aggregated CIDR prefixes of all ranges of a country code
+    pub fn cidrs_for_country(&self, country_code: &str) -> impl Iterator<Item = Ipv6Net> {
+        let cidrs: Vec<Ipv6Net> = self
+            .ranges_for_country(country_code)
+            .flat_map(|range| net::ipv6_cidrs(&range))
+            .collect();
+        Ipv6Net::aggregate(&cidrs).into_iter()
+    }
+
+    /// Get the total number of addresses in all ranges of a country code
+    pub fn address_count_for_country(&self, country_code: &str) -> u128 {
+        self.ranges_for_country(country_code)
+            .map(|range| net::ipv6_range_len(&range))
+            .fold(0, u128::saturating_add)
+    }
+
     /// Get all country entries as an iterator
     pub fn all(&self) -> impl Iterator<Item = Ipv6CountryEntry> + '_ {
         self.inner_range
@@ -83,6 +283,119 @@ impl Ipv6CountryDb {
     }
 }
 
+/// Append `range` to the ascending `ranges`, merging it with the last range if adjacent
+fn push_merged(ranges: &mut Vec<RangeInclusive<u128>>, range: RangeInclusive<u128>) {
+    if let Some(last) = ranges.last_mut() {
+        if last.end().checked_add(1) == Some(*range.start()) {
+            *last = *last.start()..=*range.end();
+            return;
+        }
+    }
+    ranges.push(range);
+}
+
+impl NdbDataset for Ipv6CountryDb {
+    type Entry = Ipv6CountryEntry;
+
+    const KIND: DatasetKind = DatasetKind::Ipv6Country;
+    const CSV_NAME: &'static str = CSV_NAME;
+    const BIN_NAME: &'static str = BIN_NAME;
+    const SCHEMA_VERSION: u16 = SCHEMA_VERSION;
+    const FLAT_NAME: Option<&'static str> = Some(FLAT_NAME);
+
+    fn from_entries(entries: Vec<Ipv6CountryEntry>) -> Result<Self> {
+        Ipv6CountryDb::from_entries(entries)
+    }
+
+    fn entries(&self) -> Vec<Ipv6CountryEntry> {
+        Ipv6CountryDb::entries(self)
+    }
+
+    fn validate_entries(
+        entries: Vec<(u64, Ipv6CountryEntry)>,
+        mode: ValidationMode,
+    ) -> Result<(Vec<Ipv6CountryEntry>, ValidationReport)> {
+        validate::validate_ranges(entries, mode)
+    }
+
+    fn write_flat<W: Write>(&self, writer: W) -> Option<Result<usize>> {
+        Some(Ipv6CountryDb::write_flat(self, writer))
+    }
+
+    #[cfg(feature = "bundled")]
+    fn bundled_bin() -> Option<&'static [u8]> {
+        Some(BUNDLED_BIN)
+    }
+}
+
+/// Zero-copy IPv6 country database searched in place over the flat layout
+pub struct Ipv6CountryFlatDb {
+    table: FlatTable,
+}
+
+impl Ipv6CountryFlatDb {
+    /// Use a flat database image in static memory (e.g. from `include_bytes!`)
+    pub fn from_static(bytes: &'static [u8]) -> Result<Self> {
+        Self::from_bytes(FlatBytes::Static(bytes))
+    }
+
+    /// Use a flat database image held in memory
+    pub fn from_vec(bytes: Vec<u8>) -> Result<Self> {
+        Self::from_bytes(FlatBytes::Owned(bytes))
+    }
+
+    /// Memory-map a flat database file
+    pub fn open_mmap<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let table = FlatTable::open_mmap(path, FLAT_LAYOUT)?;
+        Ok(Self { table })
+    }
+
+    fn from_bytes(bytes: FlatBytes) -> Result<Self> {
+        let table = FlatTable::new(bytes, FLAT_LAYOUT)?;
+        Ok(Self { table })
+    }
+
+    /// Lookup country code by IPv6 address
+    pub fn lookup(&self, ip: &Ipv6Addr) -> Option<&str> {
+        self.table
+            .find(u128::from(*ip))
+            .map(|(_, value)| read_country_code(value))
+    }
+
+    /// Lookup country code and the matched range by IPv6 address
+    pub fn lookup_range(&self, ip: &Ipv6Addr) -> Option<(RangeInclusive<Ipv6Addr>, &str)> {
+        self.table
+            .find(u128::from(*ip))
+            .map(|(range, value)| (to_addr_range(range), read_country_code(value)))
+    }
+
+    /// Number of ranges
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    /// Get all entries as an iterator
+    pub fn all(&self) -> impl Iterator<Item = Ipv6CountryEntry> + '_ {
+        self.table.iter().map(|(range, value)| Ipv6CountryEntry {
+            ip_from: *range.start(),
+            ip_to: *range.end(),
+            country_code: read_country_code(value).to_string(),
+        })
+    }
+}
+
+fn to_addr_range(range: RangeInclusive<u128>) -> RangeInclusive<Ipv6Addr> {
+    Ipv6Addr::from(*range.start())..=Ipv6Addr::from(*range.end())
+}
+
+fn read_country_code(value: &[u8]) -> &str {
+    std::str::from_utf8(value).expect("flat values are checked to be ASCII when opened")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -98,6 +411,23 @@ mod tests {
         assert!(result.is_some(), "Expected a result for Cloudflare IP");
     }
 
+    #[test]
+    fn test_ipv6_country_lookup_range() {
+        let db = Ipv6CountryDb::bundled();
+        let sample_ip: Ipv6Addr = "2606:4700:4700::1111".parse().unwrap();
+
+        let (range, code) = db
+            .lookup_range(&sample_ip)
+            .expect("Expected a matched range");
+        assert!(range.contains(&sample_ip));
+        assert_eq!(db.lookup(&sample_ip), Some(code));
+
+        let (cidrs, _) = db.lookup_cidrs(&sample_ip).unwrap();
+        assert!(cidrs.iter().any(|net| net.contains(&sample_ip)));
+        assert_eq!(cidrs.first().map(|net| net.network()), Some(*range.start()));
+        assert_eq!(cidrs.last().map(|net| net.broadcast()), Some(*range.end()));
+    }
+
     #[test]
     fn test_ipv6_country_all_entries() {
         let db = Ipv6CountryDb::bundled();
@@ -105,6 +435,87 @@ mod tests {
         assert!(!entries.is_empty(), "entries should not be empty");
     }
 
+    fn entry(from: &str, to: &str, country_code: &str) -> Ipv6CountryEntry {
+        Ipv6CountryEntry {
+            ip_from: u128::from(from.parse::<Ipv6Addr>().unwrap()),
+            ip_to: u128::from(to.parse::<Ipv6Addr>().unwrap()),
+            country_code: country_code.into(),
+        }
+    }
+
+    #[test]
+    fn test_ipv6_ranges_for_country() {
+        let entries = vec![
+            entry("2001:db8::", "2001:db8:0:ffff:ffff:ffff:ffff:ffff", "JP"),
+            entry("2001:db8:1::", "2001:db8:2:ffff:ffff:ffff:ffff:ffff", "jp"),
+            entry("2001:db8:3::", "2001:db8:3:ffff:ffff:ffff:ffff:ffff", "US"),
+            entry("2001:db9::", "2001:db9:0:ffff:ffff:ffff:ffff:ffff", "JP"),
+        ];
+        let db = Ipv6CountryDb::from_entries(entries).unwrap();
+        let indexed = Ipv6CountryDb::from_entries(db.entries())
+            .unwrap()
+            .with_country_index();
+
+        for db in [&db, &indexed] {
+            let ranges: Vec<_> = db.ranges_for_country("JP").collect();
+            let addr = |s: &str| s.parse::<Ipv6Addr>().unwrap();
+            assert_eq!(
+                ranges,
+                vec![
+                    addr("2001:db8::")..=addr("2001:db8:2:ffff:ffff:ffff:ffff:ffff"),
+                    addr("2001:db9::")..=addr("2001:db9:0:ffff:ffff:ffff:ffff:ffff"),
+                ]
+            );
+            let cidrs: Vec<String> = db.cidrs_for_country("jp").map(|n| n.to_string()).collect();
+            assert_eq!(cidrs, ["2001:db8::/47", "2001:db8:2::/48", "2001:db9::/48"]);
+            assert_eq!(db.address_count_for_country("JP"), 1 << 82);
+            assert_eq!(db.ranges_for_country("DE").count(), 0);
+        }
+    }
+
+    #[test]
+    fn test_ipv6_country_open_mmap() {
+        let entries = vec![
+            entry("2001:db8::", "2001:db8:0:ffff:ffff:ffff:ffff:ffff", "JP"),
+            entry("2001:db8:3::", "2001:db8:3:ffff:ffff:ffff:ffff:ffff", "US"),
+        ];
+        let db = Ipv6CountryDb::from_entries(entries.clone()).unwrap();
+        let path =
+            std::env::temp_dir().join(format!("ndb-test-{}-{}", std::process::id(), FLAT_NAME));
+        std::fs::write(&path, db.to_flat().unwrap()).unwrap();
+
+        let flat_db = Ipv6CountryDb::open_mmap(&path).unwrap();
+        assert_eq!(flat_db.len(), 2);
+        assert_eq!(flat_db.lookup(&"2001:db8::1".parse().unwrap()), Some("JP"));
+        let (range, code) = flat_db
+            .lookup_range(&"2001:db8:3::1".parse().unwrap())
+            .unwrap();
+        assert_eq!(code, "US");
+        assert_eq!(*range.start(), "2001:db8:3::".parse::<Ipv6Addr>().unwrap());
+        assert_eq!(flat_db.lookup(&"2001:db8:1::".parse().unwrap()), None);
+        assert_eq!(flat_db.all().collect::<Vec<_>>(), entries);
+        drop(flat_db);
+        std::fs::remove_file(&path).unwrap();
+
+        let invalid =
+            Ipv6CountryDb::from_entries(vec![entry("2001:db8::", "2001:db8::", "JPN")]).unwrap();
+        assert!(matches!(invalid.to_flat(), Err(Error::InvalidFlat(_))));
+    }
+
+    #[test]
+    fn test_ipv6_country_flat_rejects_corrupt_value() {
+        let db =
+            Ipv6CountryDb::from_entries(vec![entry("2001:db8::", "2001:db8::ff", "JP")]).unwrap();
+        let mut flat = db.to_flat().unwrap();
+        assert!(Ipv6CountryFlatDb::from_vec(flat.clone()).is_ok());
+        let last = flat.len() - 1;
+        flat[last] = 0xFF;
+        assert!(matches!(
+            Ipv6CountryFlatDb::from_vec(flat),
+            Err(Error::InvalidFlat(_))
+        ));
+    }
+
     #[test]
     fn test_ipv6_country_lookup_str() {
         let db = Ipv6CountryDb::bundled();
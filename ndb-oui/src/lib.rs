@@ -1,13 +1,85 @@
-use anyhow::Result;
+use ndb_core::bin::DatasetKind;
+use ndb_core::{NdbDataset, Result};
 use rangemap::RangeInclusiveMap;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::io::Read;
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
 
+pub use ndb_core::Error;
 pub use netdev::MacAddr;
 
 pub const CSV_NAME: &str = "oui.csv";
 pub const BIN_NAME: &str = "oui.bin";
+/// Version of the entry schema written to BIN files
+pub const SCHEMA_VERSION: u16 = 2;
+/// File name of the Wireshark vendor database
+pub const MANUF_NAME: &str = "manuf";
+/// File names of the IEEE registry CSV exports (MA-L, MA-M, MA-S and IAB)
+pub const IEEE_CSV_NAMES: [&str; 4] = ["oui.csv", "mam.csv", "oui36.csv", "iab.csv"];
+
+#[cfg(all(feature = "bundled", not(feature = "bundled-zstd")))]
+static BUNDLED_BIN: &[u8] = include_bytes!("../data/oui.bin");
+
+#[cfg(feature = "bundled-zstd")]
+static BUNDLED_BIN: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/oui.bin.zst"));
+
+/// IEEE registry an assignment comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum OuiRegistry {
+    /// MAC Address Block Large, a `/24` prefix
+    #[serde(rename = "MA-L")]
+    MaL,
+    /// MAC Address Block Medium, a `/28` prefix
+    #[serde(rename = "MA-M")]
+    MaM,
+    /// MAC Address Block Small, a `/36` prefix
+    #[serde(rename = "MA-S")]
+    MaS,
+    /// Individual Address Block (superseded by MA-S), a `/36` prefix
+    #[serde(rename = "IAB")]
+    Iab,
+}
+
+impl OuiRegistry {
+    pub const ALL: [OuiRegistry; 4] = [
+        OuiRegistry::MaL,
+        OuiRegistry::MaM,
+        OuiRegistry::MaS,
+        OuiRegistry::Iab,
+    ];
+
+    /// Name used in the IEEE exports (e.g. `MA-L`)
+    pub fn name(&self) -> &'static str {
+        match self {
+            OuiRegistry::MaL => "MA-L",
+            OuiRegistry::MaM => "MA-M",
+            OuiRegistry::MaS => "MA-S",
+            OuiRegistry::Iab => "IAB",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<OuiRegistry> {
+        Self::ALL
+            .into_iter()
+            .find(|registry| registry.name() == name)
+    }
+
+    /// Length in bits of the prefixes assigned from this registry
+    pub fn prefix_len(&self) -> u8 {
+        match self {
+            OuiRegistry::MaL => 24,
+            OuiRegistry::MaM => 28,
+            OuiRegistry::MaS | OuiRegistry::Iab => 36,
+        }
+    }
+}
+
+impl std::fmt::Display for OuiRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
 
 /// Represents a single OUI entry
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -15,6 +87,20 @@ pub struct OuiEntry {
     pub mac_prefix: String,
     pub vendor: String,
     pub vendor_detail: Option<String>,
+    /// IEEE registry of the assignment, if known
+    #[serde(default)]
+    pub registry: Option<OuiRegistry>,
+}
+
+/// Row of an IEEE registry CSV export
+#[derive(Debug, Deserialize)]
+struct IeeeRecord {
+    #[serde(rename = "Registry")]
+    registry: String,
+    #[serde(rename = "Assignment")]
+    assignment: String,
+    #[serde(rename = "Organization Name")]
+    organization_name: String,
 }
 
 /// Represents the OUI database
@@ -25,54 +111,92 @@ pub struct OuiDb {
 
 impl OuiDb {
     /// Create a new OUI database from a CSV reader
-    pub fn from_csv<R: Read>(reader: R) -> Result<Self, csv::Error> {
-        let mut rdr = csv::Reader::from_reader(reader);
-        let mut map = HashMap::new();
-        let mut range_map: RangeInclusiveMap<u64, OuiEntry> = RangeInclusiveMap::new();
-        for result in rdr.deserialize::<OuiEntry>() {
-            let entry = result?;
-            if let Some((prefix, bits)) = parse_mac_prefix_cidr(&entry.mac_prefix) {
-                let start = mac_to_u64(prefix) & (!0u64 << (48 - bits));
-                let end = start | ((1u64 << (48 - bits)) - 1);
-                range_map.insert(start..=end, entry.clone());
-            } else {
-                map.insert(entry.mac_prefix.clone(), entry);
+    pub fn from_csv<R: Read>(reader: R) -> Result<Self> {
+        <Self as NdbDataset>::from_csv(reader)
+    }
+
+    /// Create a new OUI database from IEEE registry CSV exports
+    /// (`oui.csv`, `mam.csv`, `oui36.csv` and `iab.csv`)
+    pub fn from_ieee_csv<R: Read>(readers: impl IntoIterator<Item = R>) -> Result<Self> {
+        let mut entries = Vec::new();
+        for reader in readers {
+            entries.extend(read_ieee_csv(reader)?);
+        }
+        Self::from_entries(entries)
+    }
+
+    /// Create a new OUI database from a Wireshark `manuf` file
+    pub fn from_manuf<R: Read>(reader: R) -> Result<Self> {
+        Self::from_entries(read_manuf(reader)?)
+    }
+
+    /// Write the database in the Wireshark `manuf` format, returning the number of entries written
+    pub fn write_manuf<W: Write>(&self, mut writer: W) -> Result<usize> {
+        writeln!(writer, "# Generated by ndb-oui")?;
+        writeln!(writer, "#")?;
+        writeln!(writer, "# <prefix>[/<mask>]\t<short name>\t<long name>")?;
+        let entries = self.entries();
+        for entry in &entries {
+            write!(writer, "{}\t{}", entry.mac_prefix, entry.vendor)?;
+            if let Some(detail) = &entry.vendor_detail {
+                write!(writer, "\t{}", detail)?;
             }
+            writeln!(writer)?;
         }
-        Ok(Self {
-            inner: map,
-            inner_range: range_map,
-        })
+        writer.flush()?;
+        Ok(entries.len())
     }
 
     /// Create a new OUI database from a vector of entries
-    pub fn from_entries(entries: Vec<OuiEntry>) -> Self {
+    pub fn from_entries(entries: Vec<OuiEntry>) -> Result<Self> {
         let mut inner = HashMap::new();
         let mut inner_range = RangeInclusiveMap::new();
         for entry in entries {
-            if let Some((prefix, bits)) = parse_mac_prefix_cidr(&entry.mac_prefix) {
-                let start = mac_to_u64(prefix) & (!0u64 << (48 - bits));
-                let end = start | ((1u64 << (48 - bits)) - 1);
-                inner_range.insert(start..=end, entry.clone());
-            } else {
+            if !entry.mac_prefix.contains('/') {
                 inner.insert(entry.mac_prefix.clone(), entry);
+                continue;
             }
+            let (prefix, bits) = parse_mac_prefix_cidr(&entry.mac_prefix)
+                .ok_or_else(|| Error::InvalidPrefix(entry.mac_prefix.clone()))?;
+            let start = mac_to_u64(prefix) & (!0u64 << (48 - bits));
+            let end = start | ((1u64 << (48 - bits)) - 1);
+            inner_range.insert(start..=end, entry);
         }
-        Self { inner, inner_range }
+        Ok(Self { inner, inner_range })
     }
 
     /// Create a new OUI database from a binary slice
-    fn from_slice(slice: &[u8]) -> Result<Self> {
-        let (entries, _): (Vec<OuiEntry>, _) =
-            bincode::serde::decode_from_slice(slice, bincode::config::standard())?;
-        Ok(Self::from_entries(entries))
+    pub fn from_slice(slice: &[u8]) -> Result<Self> {
+        <Self as NdbDataset>::from_slice(slice)
+    }
+
+    /// Load database from a reader of a BIN file
+    pub fn from_bin_reader<R: Read>(reader: R) -> Result<Self> {
+        <Self as NdbDataset>::from_bin_reader(reader)
+    }
+
+    /// Load database from a BIN file (e.g. one written by `ndb-cli update`)
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        <Self as NdbDataset>::from_path(path)
     }
 
     /// Create a new OUI database from a bundled file
     #[cfg(feature = "bundled")]
     pub fn bundled() -> Self {
-        static BIN_DATA: &[u8] = include_bytes!("../data/oui.bin");
-        Self::from_slice(BIN_DATA).expect("Failed to load bundled oui.bin")
+        Self::try_bundled().expect("Failed to load bundled oui.bin")
+    }
+
+    /// Load embedded (bundled) database, returning an error instead of panicking
+    #[cfg(feature = "bundled")]
+    pub fn try_bundled() -> Result<Self> {
+        Self::from_slice(BUNDLED_BIN)
+    }
+
+    /// Get the process-wide shared bundled database, loaded once on first use
+    #[cfg(feature = "bundled")]
+    pub fn bundled_static() -> &'static Self {
+        static BUNDLED: std::sync::OnceLock<OuiDb> = std::sync::OnceLock::new();
+        BUNDLED.get_or_init(Self::bundled)
     }
 
     /// Get an OUI entry by its MAC prefix.
@@ -81,9 +205,17 @@ impl OuiDb {
         self.inner.get(prefix)
     }
 
-    /// Get all OUI entries as an iterator
+    /// Get all OUI entries as an iterator, including CIDR (MA-M/MA-S) prefixes
     pub fn all(&self) -> impl Iterator<Item = (&String, &OuiEntry)> {
-        self.inner.iter()
+        // A CIDR entry can be split into several ranges by a more specific
+        // prefix inside it, so yield each entry only once.
+        let mut seen = HashSet::new();
+        let range_entries = self
+            .inner_range
+            .iter()
+            .map(|(_, entry)| (&entry.mac_prefix, entry))
+            .filter(move |(prefix, _)| seen.insert(prefix.as_str()));
+        self.inner.iter().chain(range_entries)
     }
 
     /// Lookup from string MAC address (e.g., "ac:4a:56:12:34:56")
@@ -107,10 +239,210 @@ impl OuiDb {
         self.inner.get(&key)
     }
 
-    /// Get all entries as a vector
+    /// Get all entries as a vector sorted by MAC prefix, including CIDR (MA-M/MA-S) prefixes
     pub fn entries(&self) -> Vec<OuiEntry> {
-        self.inner.values().cloned().collect()
+        let mut entries: Vec<OuiEntry> = self.all().map(|(_, entry)| entry.clone()).collect();
+        entries.sort_by(|a, b| a.mac_prefix.cmp(&b.mac_prefix));
+        entries
+    }
+}
+
+impl NdbDataset for OuiDb {
+    type Entry = OuiEntry;
+
+    const KIND: DatasetKind = DatasetKind::Oui;
+    const CSV_NAME: &'static str = CSV_NAME;
+    const BIN_NAME: &'static str = BIN_NAME;
+    const SCHEMA_VERSION: u16 = SCHEMA_VERSION;
+
+    fn from_entries(entries: Vec<OuiEntry>) -> Result<Self> {
+        OuiDb::from_entries(entries)
+    }
+
+    fn entries(&self) -> Vec<OuiEntry> {
+        OuiDb::entries(self)
+    }
+
+    #[cfg(feature = "bundled")]
+    fn bundled_bin() -> Option<&'static [u8]> {
+        Some(BUNDLED_BIN)
+    }
+}
+
+/// Read the entries of an IEEE registry CSV export.
+/// `vendor` is a short name derived from the organization name, which is kept in `vendor_detail`.
+pub fn read_ieee_csv<R: Read>(reader: R) -> Result<Vec<OuiEntry>> {
+    let mut rdr = csv::Reader::from_reader(reader);
+    let headers = rdr.headers()?.clone();
+    let mut row = csv::StringRecord::new();
+    let mut entries = Vec::new();
+    while rdr.read_record(&mut row)? {
+        let record: IeeeRecord = row.deserialize(Some(&headers))?;
+        let registry =
+            OuiRegistry::from_name(record.registry.trim()).ok_or_else(|| Error::Parse {
+                line: row.position().map_or(0, |pos| pos.line()),
+                message: format!("unknown registry {}", record.registry),
+            })?;
+        let mac_prefix = ieee_mac_prefix(record.assignment.trim(), registry)
+            .ok_or_else(|| Error::InvalidPrefix(record.assignment.clone()))?;
+        let name = record.organization_name.trim();
+        entries.push(OuiEntry {
+            mac_prefix,
+            vendor: short_name(name),
+            vendor_detail: (!name.is_empty()).then(|| name.to_string()),
+            registry: Some(registry),
+        });
+    }
+    Ok(entries)
+}
+
+/// Check whether `data` starts with the header of an IEEE registry CSV export
+pub fn is_ieee_csv(data: &[u8]) -> bool {
+    let data = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data);
+    data.starts_with(b"Registry,Assignment,")
+}
+
+/// Read the entries of a Wireshark `manuf` file.
+///
+/// Each line holds a MAC prefix with an optional `/<mask>`, a short name and an optional
+/// long name, separated by tabs. `vendor` is the short name and `vendor_detail` the long name.
+/// `#` starts a comment line; older files put the long name in a `# ` comment.
+pub fn read_manuf<R: Read>(reader: R) -> Result<Vec<OuiEntry>> {
+    let mut entries = Vec::new();
+    for (idx, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parse_error = |message: String| Error::Parse {
+            line: idx as u64 + 1,
+            message,
+        };
+        let mut fields = line.split('\t').map(str::trim).filter(|f| !f.is_empty());
+        let prefix = fields.next().unwrap_or_default();
+        let mac_prefix = manuf_mac_prefix(prefix)
+            .ok_or_else(|| parse_error(format!("invalid MAC prefix {}", prefix)))?;
+        let vendor = fields
+            .next()
+            .ok_or_else(|| parse_error(format!("missing vendor name for {}", prefix)))?;
+        let vendor_detail = fields
+            .next()
+            .map(|detail| detail.trim_start_matches('#').trim())
+            .filter(|detail| !detail.is_empty())
+            .map(str::to_string);
+        entries.push(OuiEntry {
+            mac_prefix,
+            vendor: vendor.to_string(),
+            vendor_detail,
+            registry: None,
+        });
+    }
+    Ok(entries)
+}
+
+/// Convert a `manuf` prefix (e.g. `00:1B:C5:00:00:00/36`, `00-00-0C` or `00:00:0C`)
+/// to the `mac_prefix` notation
+fn manuf_mac_prefix(prefix: &str) -> Option<String> {
+    let (addr, mask) = match prefix.split_once('/') {
+        Some((addr, mask)) => (addr, Some(mask.parse::<u8>().ok()?)),
+        None => (prefix, None),
+    };
+    let octets = addr
+        .split([':', '-', '.'])
+        .map(|octet| {
+            u8::from_str_radix(octet, 16)
+                .ok()
+                .filter(|_| octet.len() <= 2)
+        })
+        .collect::<Option<Vec<u8>>>()?;
+    if octets.is_empty() || octets.len() > 6 {
+        return None;
     }
+    // Without a mask, the prefix covers the octets that are given
+    let bits = mask.unwrap_or(octets.len() as u8 * 8);
+    if !(1..=48).contains(&bits) {
+        return None;
+    }
+    let mut mac = [0u8; 6];
+    mac[..octets.len()].copy_from_slice(&octets);
+    Some(format_mac_prefix(mac_to_u64(mac), bits))
+}
+
+/// Convert an IEEE assignment (e.g. `5C857E0`) to the `mac_prefix` notation of `registry`
+fn ieee_mac_prefix(assignment: &str, registry: OuiRegistry) -> Option<String> {
+    let prefix_len = registry.prefix_len();
+    if assignment.len() != usize::from(prefix_len / 4)
+        || !assignment.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return None;
+    }
+    let value = u64::from_str_radix(assignment, 16).ok()? << (48 - prefix_len);
+    Some(format_mac_prefix(value, prefix_len))
+}
+
+/// Format the first `bits` bits of the 48-bit MAC address `value` as a `mac_prefix`:
+/// `AC:4A:56` for `/24` prefixes and `5C:85:7E:00:00:00/28` otherwise
+fn format_mac_prefix(value: u64, bits: u8) -> String {
+    let value = value & (!0u64 << (48 - bits));
+    let octets: Vec<String> = (0..6)
+        .rev()
+        .map(|i| format!("{:02X}", (value >> (i * 8)) & 0xFF))
+        .collect();
+    if bits == 24 {
+        octets[..3].join(":")
+    } else {
+        format!("{}/{}", octets.join(":"), bits)
+    }
+}
+
+/// Legal form words dropped from organization names when deriving short names
+const GENERAL_TERMS: [&str; 16] = [
+    "inc",
+    "incorporated",
+    "corp",
+    "corporation",
+    "co",
+    "company",
+    "ltd",
+    "limited",
+    "llc",
+    "gmbh",
+    "ag",
+    "bv",
+    "oy",
+    "plc",
+    "sa",
+    "spa",
+];
+
+/// Short vendor name in the style of Wireshark's `manuf` file:
+/// legal form words and punctuation are dropped and the rest is truncated to 8 characters
+pub fn short_name(name: &str) -> String {
+    let words: Vec<String> = name
+        .split_whitespace()
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_alphanumeric() || *c == '-')
+                .collect::<String>()
+        })
+        .filter(|word| !word.is_empty())
+        .collect();
+    let kept: Vec<&String> = words
+        .iter()
+        .filter(|word| !GENERAL_TERMS.contains(&word.to_ascii_lowercase().as_str()))
+        .collect();
+    // Keep names made up of general terms only (e.g. "Co Ltd") as they are
+    let words: Vec<&String> = if kept.is_empty() {
+        words.iter().collect()
+    } else {
+        kept
+    };
+    words
+        .into_iter()
+        .flat_map(|word| word.chars())
+        .take(8)
+        .collect()
 }
 
 fn parse_mac_prefix_cidr(s: &str) -> Option<([u8; 6], u8)> {
@@ -118,8 +450,11 @@ fn parse_mac_prefix_cidr(s: &str) -> Option<([u8; 6], u8)> {
     if parts.len() != 2 {
         return None;
     }
-    let mac = MacAddr::from_hex_format(parts[0]);
-    let bits = parts[1].parse::<u8>().ok()?;
+    let mac = parts[0].parse::<MacAddr>().ok()?;
+    let bits = parts[1]
+        .parse::<u8>()
+        .ok()
+        .filter(|bits| (1..=48).contains(bits))?;
     Some((mac.octets(), bits))
 }
 
@@ -172,6 +507,166 @@ mod tests {
         assert!(entry.is_some());
     }
 
+    #[test]
+    fn test_bundled_contains_cidr_prefixes() {
+        let db = OuiDb::bundled();
+        assert!(db.entries().iter().any(|e| e.mac_prefix.ends_with("/28")));
+        assert!(db.entries().iter().any(|e| e.mac_prefix.ends_with("/36")));
+        let entry = db.lookup("fc:d2:b6:1a:bc:de").unwrap();
+        assert_eq!(entry.mac_prefix, "FC:D2:B6:10:00:00/28");
+    }
+
+    #[test]
+    fn test_entries_include_cidr_prefixes() {
+        let csv = "mac_prefix,vendor,vendor_detail\n\
+                   FC:D2:B6,IEEERegi,IEEE Registration Authority\n\
+                   FC:D2:B6:00:00:00/28,CgPowerA,Cg Power And Industrial Solutions Ltd\n\
+                   FC:D2:B6:10:00:00/28,LinkFar-,Link (Far-East) Corporation\n\
+                   70:B3:D5:00:00:00/36,Artis,Artis GmbH\n";
+        let db = OuiDb::from_csv(csv.as_bytes()).unwrap();
+
+        let mut prefixes: Vec<String> = db.entries().into_iter().map(|e| e.mac_prefix).collect();
+        prefixes.sort();
+        assert_eq!(
+            prefixes,
+            vec![
+                "70:B3:D5:00:00:00/36",
+                "FC:D2:B6",
+                "FC:D2:B6:00:00:00/28",
+                "FC:D2:B6:10:00:00/28",
+            ]
+        );
+        assert_eq!(db.all().count(), 4);
+    }
+
+    #[test]
+    fn test_csv_bin_roundtrip_keeps_cidr_prefixes() {
+        let csv = "mac_prefix,vendor,vendor_detail\n\
+                   FC:D2:B6,IEEERegi,IEEE Registration Authority\n\
+                   FC:D2:B6:10:00:00/28,LinkFar-,Link (Far-East) Corporation\n\
+                   70:B3:D5:00:00:00/36,Artis,Artis GmbH\n";
+        let db = OuiDb::from_csv(csv.as_bytes()).unwrap();
+
+        let buf = db.to_bin("oui.csv").unwrap();
+        let decoded = OuiDb::from_slice(&buf).unwrap();
+
+        let entry = decoded.lookup("fc:d2:b6:1a:bc:de").unwrap();
+        assert_eq!(entry.mac_prefix, "FC:D2:B6:10:00:00/28");
+        let entry = decoded.lookup("70:b3:d5:00:0f:ff").unwrap();
+        assert_eq!(entry.mac_prefix, "70:B3:D5:00:00:00/36");
+        let entry = decoded.lookup("fc:d2:b6:2a:bc:de").unwrap();
+        assert_eq!(entry.mac_prefix, "FC:D2:B6");
+    }
+
+    #[test]
+    fn test_invalid_prefix() {
+        let csv = "mac_prefix,vendor,vendor_detail\n70:B3:D5:00:00:00/52,Artis,Artis GmbH\n";
+        assert!(matches!(
+            OuiDb::from_csv(csv.as_bytes()),
+            Err(Error::InvalidPrefix(prefix)) if prefix == "70:B3:D5:00:00:00/52"
+        ));
+    }
+
+    #[test]
+    fn test_from_ieee_csv() {
+        let mal = "Registry,Assignment,Organization Name,Organization Address\n\
+                   MA-L,AC4A56,\"Cisco Systems, Inc\",\"80 West Tasman Drive San Jose CA US 94568 \"\n";
+        let mam = "Registry,Assignment,Organization Name,Organization Address\n\
+                   MA-M,FCD2B61,Link (Far-East) Corporation,Taipei TW\n";
+        let mas = "Registry,Assignment,Organization Name,Organization Address\n\
+                   MA-S,70B3D5001,Artis GmbH,Hamburg DE\n";
+        let iab = "Registry,Assignment,Organization Name,Organization Address\n\
+                   IAB,0050C2ABC,Example Ltd,\n";
+        assert!(is_ieee_csv(mal.as_bytes()));
+        let db = OuiDb::from_ieee_csv([mal, mam, mas, iab].map(str::as_bytes)).unwrap();
+
+        let entry = db.get("AC:4A:56").unwrap();
+        assert_eq!(entry.vendor, "CiscoSys");
+        assert_eq!(entry.vendor_detail.as_deref(), Some("Cisco Systems, Inc"));
+        assert_eq!(entry.registry, Some(OuiRegistry::MaL));
+
+        let entry = db.lookup("fc:d2:b6:1a:bc:de").unwrap();
+        assert_eq!(entry.mac_prefix, "FC:D2:B6:10:00:00/28");
+        assert_eq!(entry.vendor, "LinkFar-");
+        assert_eq!(entry.registry, Some(OuiRegistry::MaM));
+
+        let entry = db.lookup("70:b3:d5:00:1f:ff").unwrap();
+        assert_eq!(entry.mac_prefix, "70:B3:D5:00:10:00/36");
+        assert_eq!(entry.registry, Some(OuiRegistry::MaS));
+
+        let entry = db.lookup("00:50:c2:ab:c1:23").unwrap();
+        assert_eq!(entry.mac_prefix, "00:50:C2:AB:C0:00/36");
+        assert_eq!(entry.vendor, "Example");
+        assert_eq!(entry.registry, Some(OuiRegistry::Iab));
+    }
+
+    #[test]
+    fn test_ieee_csv_invalid_assignment() {
+        let csv = "Registry,Assignment,Organization Name,Organization Address\n\
+                   MA-M,FCD2B6,Link (Far-East) Corporation,Taipei TW\n";
+        assert!(matches!(
+            read_ieee_csv(csv.as_bytes()),
+            Err(Error::InvalidPrefix(assignment)) if assignment == "FCD2B6"
+        ));
+        assert!(!is_ieee_csv(b"mac_prefix,vendor,vendor_detail\n"));
+    }
+
+    #[test]
+    fn test_ieee_csv_unknown_registry() {
+        let csv = "Registry,Assignment,Organization Name,Organization Address\n\
+                   MA-L,00000C,Cisco Systems Inc,San Jose US\n\
+                   MA-X,00000E,Fujitsu Limited,Kawasaki JP\n";
+        assert!(matches!(
+            read_ieee_csv(csv.as_bytes()),
+            Err(Error::Parse { line: 3, message }) if message == "unknown registry MA-X"
+        ));
+    }
+
+    const MANUF: &str = "# Wireshark manuf\n\
+                         #\n\
+                         00:00:0C\tCisco\tCisco Systems, Inc\n\
+                         00-00-0E\tFujitsu\t# FUJITSU LIMITED\n\
+                         00:1B:C5:00:00:00/36\tConverge\tConverging Systems Inc.\n\
+                         FC:D2:B6:10:00:00/28\tLinkFar-\tLink (Far-East) Corporation\n\
+                         \n\
+                         00:50:C2:00:00:00/24\tIEEERegi\n";
+
+    #[test]
+    fn test_from_manuf() {
+        let db = OuiDb::from_manuf(MANUF.as_bytes()).unwrap();
+        let entry = db.get("00:00:0C").unwrap();
+        assert_eq!(entry.vendor, "Cisco");
+        assert_eq!(entry.vendor_detail.as_deref(), Some("Cisco Systems, Inc"));
+        let entry = db.get("00:00:0E").unwrap();
+        assert_eq!(entry.vendor_detail.as_deref(), Some("FUJITSU LIMITED"));
+        let entry = db.get("00:50:C2").unwrap();
+        assert_eq!(entry.vendor_detail, None);
+        let entry = db.lookup("00:1b:c5:00:00:0f").unwrap();
+        assert_eq!(entry.mac_prefix, "00:1B:C5:00:00:00/36");
+        assert_eq!(entry.vendor, "Converge");
+        assert_eq!(db.lookup("fc:d2:b6:1a:bc:de").unwrap().vendor, "LinkFar-");
+    }
+
+    #[test]
+    fn test_manuf_roundtrip() {
+        let db = OuiDb::from_manuf(MANUF.as_bytes()).unwrap();
+        let mut buf = Vec::new();
+        assert_eq!(db.write_manuf(&mut buf).unwrap(), 5);
+        let manuf = String::from_utf8(buf).unwrap();
+        assert!(manuf.contains("FC:D2:B6:10:00:00/28\tLinkFar-\tLink (Far-East) Corporation\n"));
+        let decoded = OuiDb::from_manuf(manuf.as_bytes()).unwrap();
+        assert_eq!(decoded.entries(), db.entries());
+    }
+
+    #[test]
+    fn test_manuf_invalid_line() {
+        let manuf = "# comment\n00:00:0C\tCisco\nZZ:00:0C\tBroken\n";
+        assert!(matches!(
+            OuiDb::from_manuf(manuf.as_bytes()),
+            Err(Error::Parse { line: 3, .. })
+        ));
+    }
+
     #[test]
     fn test_lookup_mac_str() {
         let db = OuiDb::bundled();
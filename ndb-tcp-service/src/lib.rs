@@ -1,14 +1,28 @@
-use anyhow::Result;
-use ndb_core::utils::serde::de_u8_to_bool;
+use ndb_core::bin::DatasetKind;
+pub use ndb_core::service::COMMON_FREQUENCY;
+use ndb_core::service::{self, Protocol, ServiceRecord};
+use ndb_core::utils::serde::{de_u8_to_bool, space_separated};
+use ndb_core::{NdbDataset, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::io::Read;
+use std::io::{Read, Write};
+use std::path::Path;
+
+pub use ndb_core::Error;
 
 pub const CSV_NAME: &str = "tcp-services.csv";
 pub const BIN_NAME: &str = "tcp-services.bin";
+/// Version of the entry schema written to BIN files
+pub const SCHEMA_VERSION: u16 = 4;
+
+#[cfg(all(feature = "bundled", not(feature = "bundled-zstd")))]
+static BUNDLED_BIN: &[u8] = include_bytes!("../data/tcp-services.bin");
+
+#[cfg(feature = "bundled-zstd")]
+static BUNDLED_BIN: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/tcp-services.bin.zst"));
 
 /// Represents a single TCP service entry
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TcpServiceEntry {
     pub port: u16,
     pub name: String,
@@ -17,6 +31,18 @@ pub struct TcpServiceEntry {
     pub wellknown: bool,
     #[serde(deserialize_with = "de_u8_to_bool")]
     pub common: bool,
+    /// Other service names registered for the port
+    #[serde(default, with = "space_separated")]
+    pub aliases: Vec<String>,
+    /// Fraction of scanned hosts with the port open (0.0-1.0), from `nmap-services`
+    #[serde(default)]
+    pub frequency: Option<f64>,
+    /// Assignee of the IANA registration
+    #[serde(default)]
+    pub assignee: Option<String>,
+    /// Reference of the IANA registration (e.g. an RFC)
+    #[serde(default)]
+    pub reference: Option<String>,
 }
 
 /// Represents the TCP service database
@@ -26,14 +52,26 @@ pub struct TcpServiceDb {
 
 impl TcpServiceDb {
     /// Load database from a CSV reader
-    pub fn from_csv<R: Read>(reader: R) -> Result<Self, csv::Error> {
-        let mut rdr = csv::Reader::from_reader(reader);
-        let mut map = HashMap::new();
-        for result in rdr.deserialize::<TcpServiceEntry>() {
-            let entry = result?;
-            map.insert(entry.port, entry);
-        }
-        Ok(Self { inner: map })
+    pub fn from_csv<R: Read>(reader: R) -> Result<Self> {
+        <Self as NdbDataset>::from_csv(reader)
+    }
+
+    /// Load database from the IANA `service-names-port-numbers.csv` registry
+    pub fn from_iana_csv<R: Read>(reader: R) -> Result<Self> {
+        Ok(Self::from_entries(read_iana_csv(reader)?))
+    }
+
+    /// Load database from an nmap `nmap-services` file.
+    /// `common` is set for the ports open at least [`COMMON_FREQUENCY`] of the time.
+    pub fn from_nmap_services<R: Read>(reader: R) -> Result<Self> {
+        let mut db = Self::from_entries(read_nmap_services(reader)?);
+        db.derive_common(COMMON_FREQUENCY);
+        Ok(db)
+    }
+
+    /// Load database from an `/etc/services` file, keeping the aliases
+    pub fn from_etc_services<R: Read>(reader: R) -> Result<Self> {
+        Ok(Self::from_entries(read_etc_services(reader)?))
     }
 
     pub fn from_entries(entries: Vec<TcpServiceEntry>) -> Self {
@@ -46,16 +84,36 @@ impl TcpServiceDb {
 
     /// Create a new TCP service database from a binary slice
     pub fn from_slice(slice: &[u8]) -> Result<Self> {
-        let (entries, _): (Vec<TcpServiceEntry>, _) =
-            bincode::serde::decode_from_slice(slice, bincode::config::standard())?;
-        Ok(Self::from_entries(entries))
+        <Self as NdbDataset>::from_slice(slice)
+    }
+
+    /// Load database from a reader of a BIN file
+    pub fn from_bin_reader<R: Read>(reader: R) -> Result<Self> {
+        <Self as NdbDataset>::from_bin_reader(reader)
+    }
+
+    /// Load database from a BIN file (e.g. one written by `ndb-cli update`)
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        <Self as NdbDataset>::from_path(path)
     }
 
     /// Load embedded (bundled) database
     #[cfg(feature = "bundled")]
     pub fn bundled() -> Self {
-        static BIN_DATA: &[u8] = include_bytes!("../data/tcp-services.bin");
-        Self::from_slice(BIN_DATA).expect("Failed to load bundled tcp-services.bin")
+        Self::try_bundled().expect("Failed to load bundled tcp-services.bin")
+    }
+
+    /// Load embedded (bundled) database, returning an error instead of panicking
+    #[cfg(feature = "bundled")]
+    pub fn try_bundled() -> Result<Self> {
+        Self::from_slice(BUNDLED_BIN)
+    }
+
+    /// Get the process-wide shared bundled database, loaded once on first use
+    #[cfg(feature = "bundled")]
+    pub fn bundled_static() -> &'static Self {
+        static BUNDLED: std::sync::OnceLock<TcpServiceDb> = std::sync::OnceLock::new();
+        BUNDLED.get_or_init(Self::bundled)
     }
 
     /// Lookup a TCP service name by port
@@ -83,9 +141,144 @@ impl TcpServiceDb {
         self.inner.iter().filter(|(_, e)| e.common)
     }
 
-    /// Get all TCP service entries as a vector
+    /// Get the `n` most frequently open ports, most frequent first.
+    /// Only ports with a known frequency are ranked.
+    pub fn top_ports(&self, n: usize) -> Vec<u16> {
+        let mut ranked: Vec<(u16, f64)> = self
+            .inner
+            .values()
+            .filter_map(|e| e.frequency.map(|frequency| (e.port, frequency)))
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        ranked.into_iter().take(n).map(|(port, _)| port).collect()
+    }
+
+    /// Set `common` for the ports whose frequency is at least `threshold`
+    /// and clear it for the others
+    pub fn derive_common(&mut self, threshold: f64) {
+        for entry in self.inner.values_mut() {
+            entry.common = entry
+                .frequency
+                .is_some_and(|frequency| frequency >= threshold);
+        }
+    }
+
+    /// Add the frequencies of `entries` (e.g. from [`read_nmap_services`]) to the database.
+    /// Ports not in the database are added as they are.
+    pub fn merge_frequencies(&mut self, entries: Vec<TcpServiceEntry>) {
+        for entry in entries {
+            match self.inner.get_mut(&entry.port) {
+                Some(existing) => existing.frequency = entry.frequency,
+                None => {
+                    self.inner.insert(entry.port, entry);
+                }
+            }
+        }
+    }
+
+    /// Layer the entries of `other` (e.g. a local `/etc/services`) on top of the database,
+    /// replacing the entries of the same ports. The frequency and `common` of a replaced
+    /// entry are kept when `other` has no frequency for the port.
+    pub fn merge(&mut self, other: TcpServiceDb) {
+        for (port, mut entry) in other.inner {
+            if let Some(existing) = self.inner.get(&port) {
+                if entry.frequency.is_none() {
+                    entry.frequency = existing.frequency;
+                    entry.common = existing.common;
+                }
+            }
+            self.inner.insert(port, entry);
+        }
+    }
+
+    /// Write the database in the `/etc/services` format, returning the number of lines written
+    pub fn write_etc_services<W: Write>(&self, writer: W) -> Result<usize> {
+        let records = self.entries().into_iter().map(|entry| ServiceRecord {
+            port: entry.port,
+            protocol: Protocol::Tcp,
+            name: entry.name,
+            aliases: entry.aliases,
+            description: entry.description,
+            frequency: entry.frequency,
+            assignee: entry.assignee,
+            reference: entry.reference,
+        });
+        service::write_etc_services(writer, records)
+    }
+
+    /// Get all TCP service entries as a vector, sorted by port
     pub fn entries(&self) -> Vec<TcpServiceEntry> {
-        self.inner.values().cloned().collect()
+        let mut entries: Vec<TcpServiceEntry> = self.inner.values().cloned().collect();
+        entries.sort_by_key(|entry| entry.port);
+        entries
+    }
+}
+
+impl NdbDataset for TcpServiceDb {
+    type Entry = TcpServiceEntry;
+
+    const KIND: DatasetKind = DatasetKind::TcpService;
+    const CSV_NAME: &'static str = CSV_NAME;
+    const BIN_NAME: &'static str = BIN_NAME;
+    const SCHEMA_VERSION: u16 = SCHEMA_VERSION;
+
+    fn from_entries(entries: Vec<TcpServiceEntry>) -> Result<Self> {
+        Ok(TcpServiceDb::from_entries(entries))
+    }
+
+    fn entries(&self) -> Vec<TcpServiceEntry> {
+        TcpServiceDb::entries(self)
+    }
+
+    #[cfg(feature = "bundled")]
+    fn bundled_bin() -> Option<&'static [u8]> {
+        Some(BUNDLED_BIN)
+    }
+}
+
+/// Read the TCP services of the IANA `service-names-port-numbers.csv` registry.
+/// `wellknown` is set for the System Ports (0-1023); the registry has no usage data for `common`.
+pub fn read_iana_csv<R: Read>(reader: R) -> Result<Vec<TcpServiceEntry>> {
+    let records = service::read_iana_csv(reader)?;
+    Ok(records
+        .into_iter()
+        .filter(|record| record.protocol == Protocol::Tcp)
+        .map(entry_from_record)
+        .collect())
+}
+
+/// Read the TCP services of an nmap `nmap-services` file, keeping their frequency.
+/// `common` is left unset; see [`TcpServiceDb::derive_common`].
+pub fn read_nmap_services<R: Read>(reader: R) -> Result<Vec<TcpServiceEntry>> {
+    let records = service::read_nmap_services(reader)?;
+    Ok(records
+        .into_iter()
+        .filter(|record| record.protocol == Protocol::Tcp)
+        .map(entry_from_record)
+        .collect())
+}
+
+/// Read the TCP services of an `/etc/services` file
+pub fn read_etc_services<R: Read>(reader: R) -> Result<Vec<TcpServiceEntry>> {
+    let records = service::read_etc_services(reader)?;
+    Ok(records
+        .into_iter()
+        .filter(|record| record.protocol == Protocol::Tcp)
+        .map(entry_from_record)
+        .collect())
+}
+
+fn entry_from_record(record: ServiceRecord) -> TcpServiceEntry {
+    TcpServiceEntry {
+        port: record.port,
+        wellknown: service::is_wellknown(record.port),
+        common: false,
+        name: record.name,
+        description: record.description,
+        aliases: record.aliases,
+        frequency: record.frequency,
+        assignee: record.assignee,
+        reference: record.reference,
     }
 }
 
@@ -130,4 +323,102 @@ mod tests {
         let db = TcpServiceDb::bundled();
         assert!(db.common().any(|(port, _)| *port == 53));
     }
+
+    #[test]
+    fn test_from_iana_csv() {
+        let csv = "Service Name,Port Number,Transport Protocol,Description,Assignee,Reference\n\
+                   http,80,tcp,First,,[RFC9110]\n\
+                   www,80,tcp,Second,[Tim_Berners_Lee],\n\
+                   http,80,sctp,SCTP,,[RFC9260]\n\
+                   x11,6000-6063,tcp,X Window System,[Stuart_Anderson],\n";
+        let db = TcpServiceDb::from_iana_csv(csv.as_bytes()).unwrap();
+        let entry = db.get(80).unwrap();
+        assert_eq!(entry.name, "http");
+        assert_eq!(entry.aliases, vec!["www"]);
+        assert_eq!(entry.description.as_deref(), Some("First"));
+        assert!(entry.wellknown);
+        assert_eq!(entry.assignee.as_deref(), Some("[Tim_Berners_Lee]"));
+        assert_eq!(entry.reference.as_deref(), Some("[RFC9110]"));
+        assert_eq!(db.get_name(6063), Some("x11"));
+        let x11 = db.get(6063).unwrap();
+        assert_eq!(x11.assignee.as_deref(), Some("[Stuart_Anderson]"));
+        assert_eq!(x11.reference, None);
+        assert!(!db.get(6063).unwrap().wellknown);
+        assert_eq!(db.all().count(), 65);
+
+        let decoded = TcpServiceDb::from_slice(&db.to_bin("iana").unwrap()).unwrap();
+        assert_eq!(decoded.get(80), Some(entry));
+    }
+
+    #[test]
+    fn test_from_nmap_services() {
+        let nmap = "# Fields in this file are: Service name, portnum/protocol, open-frequency\n\
+                    http\t80/tcp\t0.484143\t# World Wide Web HTTP\n\
+                    ssh\t22/tcp\t0.182286\t# Secure Shell Login\n\
+                    telnet\t23/tcp\t0.221265\n\
+                    unknown\t6000/tcp\t0.000500\n\
+                    domain\t53/udp\t0.213496\n";
+        let mut db = TcpServiceDb::from_nmap_services(nmap.as_bytes()).unwrap();
+        assert_eq!(db.all().count(), 4);
+        assert_eq!(db.top_ports(3), vec![80, 23, 22]);
+        assert_eq!(db.top_ports(10).len(), 4);
+        let entry = db.get(22).unwrap();
+        assert_eq!(entry.frequency, Some(0.182286));
+        assert_eq!(entry.description.as_deref(), Some("Secure Shell Login"));
+        assert!(entry.common);
+        assert!(!db.get(6000).unwrap().common);
+
+        db.derive_common(0.2);
+        let mut common: Vec<u16> = db.common().map(|(port, _)| *port).collect();
+        common.sort();
+        assert_eq!(common, vec![23, 80]);
+
+        let mut iana = TcpServiceDb::from_entries(vec![TcpServiceEntry {
+            port: 80,
+            name: "http".to_string(),
+            description: Some("World Wide Web HTTP".to_string()),
+            wellknown: true,
+            common: false,
+            aliases: vec!["www".to_string()],
+            frequency: None,
+            assignee: None,
+            reference: None,
+        }]);
+        iana.merge_frequencies(read_nmap_services(nmap.as_bytes()).unwrap());
+        assert_eq!(iana.get(80).unwrap().aliases, vec!["www"]);
+        assert_eq!(iana.get(80).unwrap().frequency, Some(0.484143));
+        assert_eq!(iana.top_ports(1), vec![80]);
+
+        let decoded = TcpServiceDb::from_slice(&db.to_bin("nmap").unwrap()).unwrap();
+        assert_eq!(decoded.get(80), db.get(80));
+    }
+
+    #[test]
+    #[cfg(feature = "bundled")]
+    fn test_etc_services_overrides() {
+        let services = "http\t80/tcp\twww\t# Local web server\n\
+                        myapp\t8765/tcp\t\t# Custom service\n\
+                        myapp\t8765/udp\n";
+        let local = TcpServiceDb::from_etc_services(services.as_bytes()).unwrap();
+        assert_eq!(local.all().count(), 2);
+        assert_eq!(local.get(80).unwrap().aliases, vec!["www"]);
+
+        let mut db = TcpServiceDb::bundled();
+        let common = db.get(80).unwrap().common;
+        db.merge(local);
+        let entry = db.get(80).unwrap();
+        assert_eq!(entry.description.as_deref(), Some("Local web server"));
+        assert_eq!(entry.common, common);
+        assert_eq!(db.get_name(8765), Some("myapp"));
+        assert_eq!(db.get_name(443), Some("https"));
+
+        let mut out = Vec::new();
+        let count = db.write_etc_services(&mut out).unwrap();
+        // Entries without a name cannot be written
+        assert_eq!(count, db.all().filter(|(_, e)| !e.name.is_empty()).count());
+        let exported = TcpServiceDb::from_etc_services(out.as_slice()).unwrap();
+        assert_eq!(exported.all().count(), count);
+        assert_eq!(exported.get(80).unwrap().aliases, vec!["www"]);
+        assert_eq!(exported.get_name(8765), Some("myapp"));
+    }
 }
@@ -0,0 +1,361 @@
+use ndb_core::NdbDataset;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+pub use ndb_core::{Error, Result};
+
+#[cfg(feature = "asn")]
+pub use ndb_as::AsDb;
+#[cfg(feature = "country")]
+pub use ndb_country::CountryDb;
+#[cfg(feature = "asn")]
+pub use ndb_ipv4_asn::Ipv4AsnDb;
+#[cfg(feature = "country")]
+pub use ndb_ipv4_country::Ipv4CountryDb;
+#[cfg(feature = "asn")]
+pub use ndb_ipv6_asn::Ipv6AsnDb;
+#[cfg(feature = "country")]
+pub use ndb_ipv6_country::Ipv6CountryDb;
+
+/// Where `NetDb` loads its datasets from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataSource {
+    /// Datasets embedded at build time
+    #[cfg(feature = "bundled")]
+    Bundled,
+    /// BIN files in a directory (e.g. written by `ndb-cli update`)
+    Dir(PathBuf),
+    /// BIN files in a directory, using the bundled dataset for any missing file
+    #[cfg(feature = "bundled")]
+    DirOrBundled(PathBuf),
+    /// BIN files in a directory, checked against its `manifest.json` before loading
+    #[cfg(feature = "manifest")]
+    VerifiedDir(PathBuf),
+}
+
+impl DataSource {
+    /// Load dataset `D` from this source
+    pub fn load<D: NdbDataset>(&self) -> Result<D> {
+        match self {
+            #[cfg(feature = "bundled")]
+            DataSource::Bundled => load_bundled(),
+            DataSource::Dir(dir) => D::from_path(dir.join(D::BIN_NAME)),
+            #[cfg(feature = "bundled")]
+            DataSource::DirOrBundled(dir) => {
+                let path = dir.join(D::BIN_NAME);
+                if path.exists() {
+                    D::from_path(path)
+                } else {
+                    load_bundled()
+                }
+            }
+            #[cfg(feature = "manifest")]
+            DataSource::VerifiedDir(dir) => {
+                let manifest = ndb_core::manifest::Manifest::from_dir(dir)?;
+                D::from_path_verified(dir.join(D::BIN_NAME), &manifest)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "bundled")]
+fn load_bundled<D: NdbDataset>() -> Result<D> {
+    // The `bundled` feature enables the bundled data of every dataset crate in use
+    D::load_bundled().unwrap_or_else(|| panic!("No bundled {} database", D::KIND))
+}
+
+/// Information about a single IP address
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IpInfo {
+    pub ip: IpAddr,
+    pub asn: Option<u32>,
+    pub as_name: Option<String>,
+    pub country_code: Option<String>,
+    pub country_name: Option<String>,
+    /// Range of the ASN database entry containing `ip`
+    pub asn_range: Option<RangeInclusive<IpAddr>>,
+    /// Range of the country database entry containing `ip`
+    pub country_range: Option<RangeInclusive<IpAddr>>,
+}
+
+impl IpInfo {
+    fn new(ip: IpAddr) -> Self {
+        Self {
+            ip,
+            asn: None,
+            as_name: None,
+            country_code: None,
+            country_name: None,
+            asn_range: None,
+            country_range: None,
+        }
+    }
+}
+
+/// IP databases enabled through cargo features
+pub struct NetDb {
+    #[cfg(feature = "asn")]
+    ipv4_asn: Ipv4AsnDb,
+    #[cfg(feature = "asn")]
+    ipv6_asn: Ipv6AsnDb,
+    #[cfg(feature = "asn")]
+    as_db: AsDb,
+    #[cfg(feature = "country")]
+    ipv4_country: Ipv4CountryDb,
+    #[cfg(feature = "country")]
+    ipv6_country: Ipv6CountryDb,
+    #[cfg(feature = "country")]
+    country: CountryDb,
+}
+
+impl NetDb {
+    /// Load the enabled databases from `source`
+    #[cfg_attr(
+        not(any(feature = "asn", feature = "country")),
+        allow(unused_variables)
+    )]
+    pub fn load(source: &DataSource) -> Result<Self> {
+        Ok(Self {
+            #[cfg(feature = "asn")]
+            ipv4_asn: source.load()?,
+            #[cfg(feature = "asn")]
+            ipv6_asn: source.load()?,
+            #[cfg(feature = "asn")]
+            as_db: source.load()?,
+            #[cfg(feature = "country")]
+            ipv4_country: source.load()?,
+            #[cfg(feature = "country")]
+            ipv6_country: source.load()?,
+            #[cfg(feature = "country")]
+            country: source.load()?,
+        })
+    }
+
+    /// Load the enabled databases from BIN files in `dir`
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        Self::load(&DataSource::Dir(dir.as_ref().to_path_buf()))
+    }
+
+    /// Load embedded (bundled) databases
+    #[cfg(feature = "bundled")]
+    pub fn bundled() -> Self {
+        Self::try_bundled().expect("Failed to load bundled databases")
+    }
+
+    /// Load embedded (bundled) databases, returning an error instead of panicking
+    #[cfg(feature = "bundled")]
+    pub fn try_bundled() -> Result<Self> {
+        Self::load(&DataSource::Bundled)
+    }
+
+    /// Get the process-wide shared bundled databases, loaded once on first use
+    #[cfg(feature = "bundled")]
+    pub fn bundled_static() -> &'static Self {
+        static BUNDLED: std::sync::OnceLock<NetDb> = std::sync::OnceLock::new();
+        BUNDLED.get_or_init(Self::bundled)
+    }
+
+    /// Lookup ASN, AS name and country by IP address
+    #[allow(unused_mut)]
+    pub fn lookup_ip(&self, ip: IpAddr) -> IpInfo {
+        let mut info = IpInfo::new(ip);
+        #[cfg(feature = "asn")]
+        {
+            let matched = match ip {
+                IpAddr::V4(ipv4) => self
+                    .ipv4_asn
+                    .lookup_range(&ipv4)
+                    .map(|(range, &asn)| (ip_range(range), asn)),
+                IpAddr::V6(ipv6) => self
+                    .ipv6_asn
+                    .lookup_range(&ipv6)
+                    .map(|(range, &asn)| (ip_range(range), asn)),
+            };
+            if let Some((range, asn)) = matched {
+                info.asn = Some(asn);
+                info.as_name = self.as_db.get_name(asn).map(str::to_string);
+                info.asn_range = Some(range);
+            }
+        }
+        #[cfg(feature = "country")]
+        {
+            let matched = match ip {
+                IpAddr::V4(ipv4) => self
+                    .ipv4_country
+                    .lookup_range(&ipv4)
+                    .map(|(range, code)| (ip_range(range), code)),
+                IpAddr::V6(ipv6) => self
+                    .ipv6_country
+                    .lookup_range(&ipv6)
+                    .map(|(range, code)| (ip_range(range), code)),
+            };
+            if let Some((range, code)) = matched {
+                info.country_name = self.country.get_name(code).map(str::to_string);
+                info.country_code = Some(code.clone());
+                info.country_range = Some(range);
+            }
+        }
+        info
+    }
+
+    #[cfg(feature = "asn")]
+    pub fn ipv4_asn(&self) -> &Ipv4AsnDb {
+        &self.ipv4_asn
+    }
+
+    #[cfg(feature = "asn")]
+    pub fn ipv6_asn(&self) -> &Ipv6AsnDb {
+        &self.ipv6_asn
+    }
+
+    #[cfg(feature = "asn")]
+    pub fn as_db(&self) -> &AsDb {
+        &self.as_db
+    }
+
+    #[cfg(feature = "country")]
+    pub fn ipv4_country(&self) -> &Ipv4CountryDb {
+        &self.ipv4_country
+    }
+
+    #[cfg(feature = "country")]
+    pub fn ipv6_country(&self) -> &Ipv6CountryDb {
+        &self.ipv6_country
+    }
+
+    #[cfg(feature = "country")]
+    pub fn country(&self) -> &CountryDb {
+        &self.country
+    }
+}
+
+#[cfg(any(feature = "asn", feature = "country"))]
+fn ip_range<A: Into<IpAddr>>(range: RangeInclusive<A>) -> RangeInclusive<IpAddr> {
+    let (start, end) = range.into_inner();
+    start.into()..=end.into()
+}
+
+#[cfg(all(test, feature = "asn", feature = "country"))]
+mod tests {
+    use super::*;
+    use ndb_as::AsEntry;
+    use ndb_country::CountryEntry;
+    use ndb_ipv4_asn::Ipv4AsnEntry;
+    use ndb_ipv4_country::Ipv4CountryEntry;
+    use ndb_ipv6_asn::Ipv6AsnEntry;
+    use ndb_ipv6_country::Ipv6CountryEntry;
+
+    fn write_test_dir(dir: &Path) {
+        std::fs::create_dir_all(dir).unwrap();
+        let write = |name: &str, bin: Result<Vec<u8>>| {
+            std::fs::write(dir.join(name), bin.unwrap()).unwrap();
+        };
+        let ipv4_asn = Ipv4AsnDb::from_entries(vec![Ipv4AsnEntry {
+            ip_from: 167772160, // 10.0.0.0
+            ip_to: 167772415,   // 10.0.0.255
+            asn: 64500,
+        }])
+        .unwrap();
+        write(ndb_ipv4_asn::BIN_NAME, ipv4_asn.to_bin("test"));
+        let ipv6_asn = Ipv6AsnDb::from_entries(vec![Ipv6AsnEntry {
+            ip_from: 0x2001_0db8 << 96,
+            ip_to: (0x2001_0db8 << 96) | ((1 << 96) - 1),
+            asn: 64501,
+        }])
+        .unwrap();
+        write(ndb_ipv6_asn::BIN_NAME, ipv6_asn.to_bin("test"));
+        let as_db = AsDb::from_entries(vec![AsEntry {
+            asn: 64500,
+            name: "TestNet".into(),
+        }]);
+        write(ndb_as::BIN_NAME, as_db.to_bin("test"));
+        let ipv4_country = Ipv4CountryDb::from_entries(vec![Ipv4CountryEntry {
+            ip_from: 167772160, // 10.0.0.0
+            ip_to: 184549375,   // 10.255.255.255
+            country_code: "JP".into(),
+        }])
+        .unwrap();
+        write(ndb_ipv4_country::BIN_NAME, ipv4_country.to_bin("test"));
+        let ipv6_country = Ipv6CountryDb::from_entries(Vec::<Ipv6CountryEntry>::new()).unwrap();
+        write(ndb_ipv6_country::BIN_NAME, ipv6_country.to_bin("test"));
+        let country = CountryDb::from_entries(vec![CountryEntry {
+            code: "JP".into(),
+            name: "Japan".into(),
+        }]);
+        write(ndb_country::BIN_NAME, country.to_bin("test"));
+    }
+
+    #[test]
+    fn test_lookup_ip_from_dir() {
+        let dir = std::env::temp_dir().join(format!("ndb-netdb-test-{}", std::process::id()));
+        write_test_dir(&dir);
+        let db = NetDb::from_dir(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let info = db.lookup_ip(ip);
+        assert_eq!(info.asn, Some(64500));
+        assert_eq!(info.as_name.as_deref(), Some("TestNet"));
+        assert_eq!(info.country_code.as_deref(), Some("JP"));
+        assert_eq!(info.country_name.as_deref(), Some("Japan"));
+        assert_eq!(
+            info.asn_range,
+            Some("10.0.0.0".parse().unwrap()..="10.0.0.255".parse().unwrap())
+        );
+        assert_eq!(
+            info.country_range,
+            Some("10.0.0.0".parse().unwrap()..="10.255.255.255".parse().unwrap())
+        );
+
+        let info = db.lookup_ip("2001:db8::1".parse().unwrap());
+        assert_eq!(info.asn, Some(64501));
+        assert_eq!(info.as_name, None);
+        assert_eq!(info.country_code, None);
+
+        let info = db.lookup_ip("192.0.2.1".parse().unwrap());
+        assert_eq!(info, IpInfo::new("192.0.2.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_from_dir_missing_file() {
+        let dir = std::env::temp_dir().join("ndb-netdb-test-missing");
+        assert!(matches!(NetDb::from_dir(&dir), Err(Error::Io(_))));
+    }
+
+    #[cfg(feature = "bundled")]
+    #[test]
+    fn test_bundled_bins_have_headers() {
+        use ndb_core::Error as CoreError;
+        fn mismatch<T>(result: ndb_core::Result<T>) -> bool {
+            matches!(result, Err(CoreError::DatasetMismatch { .. }))
+        }
+        let as_bin = ndb_as::AsDb::bundled_bin().unwrap();
+        let country_bin = ndb_country::CountryDb::bundled_bin().unwrap();
+        let ipv4_country_bin = ndb_ipv4_country::Ipv4CountryDb::bundled_bin().unwrap();
+        let ipv6_asn_bin = ndb_ipv6_asn::Ipv6AsnDb::bundled_bin().unwrap();
+        let ipv6_country_bin = ndb_ipv6_country::Ipv6CountryDb::bundled_bin().unwrap();
+        assert!(mismatch(ndb_ipv4_asn::Ipv4AsnDb::from_slice(as_bin)));
+        assert!(mismatch(ndb_as::AsDb::from_slice(country_bin)));
+        assert!(mismatch(ndb_ipv6_country::Ipv6CountryDb::from_slice(
+            ipv4_country_bin
+        )));
+        assert!(mismatch(ndb_ipv4_asn::Ipv4AsnDb::from_slice(ipv6_asn_bin)));
+        assert!(mismatch(ndb_ipv4_country::Ipv4CountryDb::from_slice(
+            ipv6_country_bin
+        )));
+    }
+
+    #[cfg(feature = "bundled")]
+    #[test]
+    fn test_bundled_lookup_ip() {
+        let db = NetDb::bundled();
+        let info = db.lookup_ip("2606:4700:4700::1111".parse().unwrap());
+        assert_eq!(info.asn, Some(13335));
+        assert!(info.as_name.is_some());
+        assert!(info.country_code.is_some());
+        let range = info.asn_range.expect("Expected a matched ASN range");
+        assert!(range.contains(&info.ip));
+    }
+}